@@ -6,13 +6,25 @@ fn main() {
 
    let kernel = PathBuf::from(std::env::var_os("CARGO_BIN_FILE_TRIDENT3_MAIN_t3_main").unwrap());
 
+   // A statically linked program to run as init, loaded by the bootloader as the ramdisk.
+   println!("cargo:rerun-if-env-changed=TRIDENT3_INIT");
+   let init = std::env::var_os("TRIDENT3_INIT").map(PathBuf::from);
+
    // Create an EFI-compatible boot image
    let uefiPath = outDir.join("uefi.img");
-   UefiBoot::new(&kernel).create_disk_image(&uefiPath).unwrap();
+   let mut uefi = UefiBoot::new(&kernel);
+   if let Some(init) = &init {
+      uefi.set_ramdisk(init);
+   }
+   uefi.create_disk_image(&uefiPath).unwrap();
 
    // Create a legacy BIOS-compatible boot image
    let biosPath = outDir.join("bios.img");
-   BiosBoot::new(&kernel).create_disk_image(&biosPath).unwrap();
+   let mut bios = BiosBoot::new(&kernel);
+   if let Some(init) = &init {
+      bios.set_ramdisk(init);
+   }
+   bios.create_disk_image(&biosPath).unwrap();
 
    // pass the disk image paths as env variables to the `main.rs`
    println!("cargo:rustc-env=UEFI_PATH={}", uefiPath.display());
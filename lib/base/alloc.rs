@@ -1,35 +1,65 @@
-#[global_allocator]
+#[cfg_attr(not(test), global_allocator)]
 pub static GLOBAL: GlobalAllocator = GlobalAllocator;
 
 /// Allocates a chunk of memory.
+///
+/// Small allocations are served from the [`slab`] caches; everything else goes to the buddy
+/// heap. Returns a null pointer if the allocation cannot be satisfied.
+///
+/// The allocator locks are taken with interrupts disabled, so code that allocates from an
+/// interrupt-free section cannot deadlock against an interrupted allocation.
 #[no_mangle]
 pub extern "C" fn __rust_allocate(size: usize, align: usize) -> *mut u8 {
-   let layout = Layout::from_size_align(size, align);
-   unsafe {
-      HEAP
-         .lock()
-         .as_mut()
-         .expect("must first initialise heap before allocating memory")
-         .allocate(layout)
-         .unwrap()
-         .as_ptr()
-   }
+   let layout = match Layout::from_size_align(size, align) {
+      Ok(layout) => layout,
+      Err(_) => return ptr::null_mut(),
+   };
+
+   return without_interrupts(|| {
+      if let Some(class) = slab::size_class(layout) {
+         let allocation = SLAB.lock().allocate(class);
+         if let Some(allocation) = allocation {
+            return allocation.as_ptr();
+         }
+
+         log::error!("failed to allocate {} bytes from slab cache {}", size, class);
+         dump_stats();
+         return ptr::null_mut();
+      }
+
+      let allocation = unsafe { allocate_or_grow(layout) };
+      return match allocation {
+         Ok(allocation) => allocation.as_ptr(),
+         Err(_) => {
+            log::error!("failed to allocate {} bytes aligned to {}", size, align);
+            dump_stats();
+            ptr::null_mut()
+         }
+      };
+   });
 }
 
 /// Frees a chunk of memory.
 #[no_mangle]
 pub extern "C" fn __rust_deallocate(pointer: *mut u8, oldSize: usize, align: usize) {
-   let layout = Layout::from_size_align(oldSize, align);
-   unsafe {
-      HEAP
-         .lock()
-         .as_mut()
-         .expect("must first initialise heap before attempting to deallocate memory")
-         .deallocate(NonNull::new_unchecked(pointer), layout);
-   }
+   let layout = unsafe { Layout::from_size_align_unchecked(oldSize, align) };
+
+   without_interrupts(|| {
+      if let Some(class) = slab::size_class(layout) {
+         unsafe {
+            SLAB.lock().deallocate(class, NonNull::new_unchecked(pointer));
+         }
+
+         return;
+      }
+
+      unsafe {
+         release(NonNull::new_unchecked(pointer), layout);
+      }
+   });
 }
 
-/// Reallocates a chunk of memory.
+/// Reallocates a chunk of memory, resizing it in place when possible.
 #[no_mangle]
 pub extern "C" fn __rust_reallocate(
    pointer: *mut u8,
@@ -37,6 +67,10 @@ pub extern "C" fn __rust_reallocate(
    newSize: usize,
    align: usize,
 ) -> *mut u8 {
+   if __rust_reallocate_inplace(pointer, oldSize, newSize, align) == newSize {
+      return pointer;
+   }
+
    let newPointer = __rust_allocate(newSize, align);
    return if newPointer.is_null() {
       newPointer
@@ -50,15 +84,34 @@ pub extern "C" fn __rust_reallocate(
    };
 }
 
-/// We do not support in-place reallocation, so just return `oldSize`.
+/// Tries to resize a chunk of memory without moving it.
+///
+/// Returns `size` on success, or `oldSize` if the memory could not be resized in place.
+/// Slab allocations can only be resized within their size class.
 #[no_mangle]
 pub extern "C" fn __rust_reallocate_inplace(
-   _pointer: *mut u8,
+   pointer: *mut u8,
    oldSize: usize,
-   _size: usize,
-   _align: usize,
+   size: usize,
+   align: usize,
 ) -> usize {
-   oldSize
+   let oldLayout = unsafe { Layout::from_size_align_unchecked(oldSize, align) };
+   let newLayout = match Layout::from_size_align(size, align) {
+      Ok(layout) => layout,
+      Err(_) => return oldSize,
+   };
+
+   return match (slab::size_class(oldLayout), slab::size_class(newLayout)) {
+      (Some(old), Some(new)) if old == new => size,
+      (None, None) => {
+         let pointer = unsafe { NonNull::new_unchecked(pointer) };
+         match without_interrupts(|| unsafe { resize_in_place(pointer, oldLayout, size) }) {
+            true => size,
+            false => oldSize,
+         }
+      }
+      _ => oldSize,
+   };
 }
 
 /// I have no idea what this actually does, but we're supposed to have one,
@@ -69,6 +122,32 @@ pub extern "C" fn __rust_usable_size(size: usize, _align: usize) -> usize {
    size
 }
 
+/// Returns a snapshot of the kernel heap's usage, or `None` before the heap is initialised.
+pub fn stats() -> Option<HeapStats> {
+   #[cfg(not(feature = "best-fit"))]
+   return without_interrupts(|| heap::HEAP.lock().as_ref().map(|heap| heap.stats()));
+
+   #[cfg(feature = "best-fit")]
+   return without_interrupts(|| Some(paging::FIT_ALLOCATOR.lock().stats()));
+}
+
+/// Logs the heap and slab cache statistics, e.g. after an allocation failure.
+pub fn dump_stats() {
+   match stats() {
+      Some(stats) => log::error!("{}", stats),
+      None => log::error!("heap: not initialised"),
+   }
+
+   slab::statistics(|cache| {
+      if cache.slabs > 0 {
+         log::error!(
+            "slab {}: {} of {} objects in use, {} slabs, {} allocations, {} frees",
+            cache.name, cache.in_use, cache.objects, cache.slabs, cache.allocations, cache.frees,
+         );
+      }
+   });
+}
+
 /// Performs a single heap allocation, like `malloc`.
 /// Uses the size and align of `T` for the memory layout.
 ///
@@ -161,7 +240,9 @@ pub unsafe trait Allocator {
       let actualP2P = alignedPointer - size_of::<usize>();
       let actualPointer = ptr::read_unaligned(actualP2P as *const usize);
 
-      self.deallocate(actualPointer as *mut u8, layout);
+      // Free with the same padded layout `allocate_aligned` asked for.
+      let actualSize = layout.size + layout.align - 1 + size_of::<usize>();
+      self.deallocate(actualPointer as *mut u8, Layout::from_size(actualSize));
    }
 }
 
@@ -224,7 +305,7 @@ unsafe impl<A: Allocator> Allocator for &RefCell<A> {
 
 unsafe impl Allocator for GlobalAllocator {
    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
-      return Some(NonNull::new(__rust_allocate(layout.size, layout.align)).unwrap());
+      return NonNull::new(__rust_allocate(layout.size, layout.align));
    }
 
    unsafe fn deallocate(&self, pointer: *mut u8, layout: Layout) {
@@ -237,15 +318,12 @@ unsafe impl Allocator for GlobalAllocator {
       oldSize: usize,
       layout: Layout,
    ) -> Option<NonNull<u8>> {
-      return Some(
-         NonNull::new(__rust_reallocate(
-            pointer,
-            oldSize,
-            layout.size,
-            layout.align,
-         ))
-         .unwrap(),
-      );
+      return NonNull::new(__rust_reallocate(
+         pointer,
+         oldSize,
+         layout.size,
+         layout.align,
+      ));
    }
 }
 
@@ -261,6 +339,10 @@ unsafe impl GlobalAlloc for GlobalAllocator {
       let layout = Layout::from(layout);
       self.deallocate(ptr, layout);
    }
+
+   unsafe fn realloc(&self, ptr: *mut u8, layout: StdLayout, new_size: usize) -> *mut u8 {
+      return __rust_reallocate(ptr, layout.size(), new_size, layout.align());
+   }
 }
 
 // MODULES //
@@ -283,6 +365,10 @@ pub mod heap;
 /// Implements a simple memory layout structure.
 pub mod layout;
 
+/// A slab allocator layered over the buddy [`heap`], serving small allocations from
+/// per-size-class caches without rounding them up to a power of two.
+pub mod slab;
+
 /// Implements two memory allocators: a Buddy Allocator and a Best-Fit Allocator,
 /// respectively referred to as [`BUDDY_ALLOCATOR`][crate::alloc::paging::BUDDY_ALLOCATOR]
 /// and [`FIT_ALLOCATOR`][crate::alloc::paging::FIT_ALLOCATOR].
@@ -290,12 +376,16 @@ pub mod paging;
 
 // EXPORTS //
 
-pub use self::layout::Layout;
+pub use self::{heap::HeapStats, layout::Layout};
 
 // IMPORTS //
 
 use {
-   self::heap::HEAP,
+   self::{
+      heap::{allocate_or_grow, release, resize_in_place},
+      slab::SLAB,
+   },
+   crate::arch::without_interrupts,
    std_alloc::alloc::{GlobalAlloc, Layout as StdLayout},
    core::{
       cell::RefCell,
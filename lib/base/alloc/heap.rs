@@ -1,13 +1,306 @@
 pub static HEAP: Mutex<Option<Heap<32>>> = Mutex::new(None);
 
-pub const HEAP_START: usize = 0x4444_4444_0000;
+pub const HEAP_START: usize = 0xffff_c000_0000_0000;
 pub const HEAP_SIZE: usize = 8 * 1024 * 1024;
 
+/// Size of the virtual window reserved for the heap, starting at [`HEAP_START`].
+///
+/// Only the first [`HEAP_SIZE`] bytes are mapped up front; the rest is mapped as the heap grows.
+pub const HEAP_WINDOW: usize = 16 * 1024 * 1024 * 1024;
+
+/// Minimum number of bytes mapped each time the heap grows.
+pub const HEAP_GROWTH: usize = 2 * 1024 * 1024;
+
+/// Hard limit on the number of bytes the heap may grow to. Defaults to the whole window.
+pub static HEAP_LIMIT: AtomicUsize = AtomicUsize::new(HEAP_WINDOW);
+
+/// Number of bytes of the heap window the heap has grown into, all of which are mapped.
+pub static HEAP_MAPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// Callback used to back `size` bytes of the heap window at `start` with physical memory.
+///
+/// Returns `true` if the whole range is now mapped. The callback runs with [`HEAP`] locked and
+/// interrupts disabled, so it must not allocate from the heap or wait on a lock.
+pub type HeapGrower = fn(start: usize, size: usize) -> bool;
+
+/// The grower registered by the kernel, if any.
+pub static HEAP_GROWER: Mutex<Option<HeapGrower>> = Mutex::new(None);
+
+/// Registers the callback used to map more of the heap window.
+pub fn set_heap_grower(grower: HeapGrower) {
+   *HEAP_GROWER.lock() = Some(grower);
+}
+
+/// Sets the hard limit on heap growth, clamped to [`HEAP_WINDOW`].
+pub fn set_heap_limit(limit: usize) {
+   HEAP_LIMIT.store(min(limit, HEAP_WINDOW), Ordering::Relaxed);
+}
+
+/// Maps more of the heap window so that the heap can satisfy `layout`, returning the newly
+/// mapped range for the caller to add to its backend.
+unsafe fn grow_window(layout: Layout) -> Result<(usize, usize), AllocationError> {
+   let grower = HEAP_GROWER.lock().ok_or(AllocationError)?;
+
+   let size = Heap::<32>::block_size(layout.size, layout.align);
+
+   // Twice the block size guarantees an aligned block of `size` lands in the new range.
+   let mapped = HEAP_MAPPED.load(Ordering::Relaxed);
+   let growth = max(size.saturating_mul(2), HEAP_GROWTH);
+   let growth = (growth + HEAP_GROWTH - 1) & !(HEAP_GROWTH - 1);
+
+   if mapped.saturating_add(growth) > HEAP_LIMIT.load(Ordering::Relaxed) {
+      return Err(AllocationError);
+   }
+
+   let start = HEAP_START + mapped;
+   if !grower(start, growth) {
+      return Err(AllocationError);
+   }
+
+   HEAP_MAPPED.store(mapped + growth, Ordering::Relaxed);
+
+   return Ok((start, start + growth));
+}
+
+/// Maps more of the heap window and adds it to `heap` so that it can satisfy `layout`.
+///
+/// ## Safety
+///
+/// `heap` must be the heap occupying the window at [`HEAP_START`].
+pub unsafe fn grow_heap<const ORDER: usize>(
+   heap: &mut Heap<ORDER>,
+   layout: Layout,
+) -> Result<(), AllocationError> {
+   let (start, end) = grow_window(layout)?;
+   return heap.add_to_heap(start, end);
+}
+
+/// Hands the already-mapped range between `start` and `end` to the heap backend selected at
+/// build time: the buddy [`HEAP`] by default, or the best-fit
+/// [`FIT_ALLOCATOR`](crate::alloc::paging::FIT_ALLOCATOR) with the `best-fit` feature.
+///
+/// Fails if the range is too small to hold the buddy heap's free bitmaps.
+///
+/// ## Safety
+///
+/// The range must be mapped, writable and unused.
+pub unsafe fn init_heap(start: usize, end: usize) -> Result<(), AllocationError> {
+   #[cfg(not(feature = "best-fit"))]
+   {
+      let mut blocks = Heap::new();
+      blocks.add_to_heap(start, end)?;
+
+      *HEAP.lock() = Some(blocks);
+   }
+
+   #[cfg(feature = "best-fit")]
+   FIT_ALLOCATOR.lock().add_to_heap(start, end);
+
+   return Ok(());
+}
+
+/// Smallest block the heap hands out; every free block must be able to hold a [`FreeBlock`].
+pub const MIN_BLOCK: usize = 32;
+
+/// The order of [`MIN_BLOCK`], the smallest order with a free bitmap.
+const MIN_ORDER: usize = MIN_BLOCK.trailing_zeros() as usize;
+
+/// Maximum number of disjoint memory regions a single heap can manage.
+///
+/// A range that starts where a region ends is merged into it, so a heap that only ever grows
+/// upwards and contiguously uses one.
+pub const MAX_REGIONS: usize = 16;
+
 pub struct Heap<const ORDER: usize> {
    pub allocated: usize,
-   pub freeList: [LinkedList; ORDER],
+   pub freeList: [FreeList; ORDER],
    pub total: usize,
    pub user: usize,
+
+   /// The highest value `allocated` has ever reached.
+   pub peak: usize,
+
+   /// Number of successful allocations.
+   pub allocations: usize,
+
+   /// Number of deallocations.
+   pub frees: usize,
+
+   /// The ranges added through [`add_to_heap`](Heap::add_to_heap).
+   pub regions: [Region; MAX_REGIONS],
+   pub regionCount: usize,
+}
+
+/// A range of memory managed by a [`Heap`], along with the bitmaps recording which of its
+/// blocks are free.
+#[derive(Clone, Copy)]
+pub struct Region {
+   pub start: usize,
+   pub end: usize,
+
+   /// Address of the free bitmaps: one for each order from [`MIN_BLOCK`] up, with a bit for
+   /// every block of that order that could start inside the region.
+   pub bitmap: usize,
+
+   /// Size of the bitmaps in bytes, rounded up to [`MIN_BLOCK`].
+   pub bitmapSize: usize,
+
+   /// Whether the bitmaps were carved out of the region's own memory rather than allocated
+   /// from the heap.
+   pub carved: bool,
+}
+
+impl Region {
+   const EMPTY: Region = Region{ start: 0, end: 0, bitmap: 0, bitmapSize: 0, carved: false };
+}
+
+/// The header written at the start of every free block.
+#[repr(C)]
+pub struct FreeBlock {
+   pub next: *mut FreeBlock,
+   pub previous: *mut FreeBlock,
+}
+
+/// A doubly-linked, intrusive list of free blocks of a single order.
+///
+/// Unlike [`LinkedList`](crate::array::linked_list::LinkedList), blocks can be unlinked from
+/// the middle of the list in constant time, which is what makes buddy coalescing `O(1)`.
+#[derive(Clone, Copy)]
+pub struct FreeList {
+   pub head: *mut FreeBlock,
+   pub length: usize,
+}
+
+unsafe impl Send for FreeList {}
+
+impl FreeList {
+   pub const fn new() -> Self {
+      return FreeList{
+         head: ptr::null_mut(),
+         length: 0,
+      };
+   }
+
+   /// Return `true` if the list is empty.
+   pub fn empty(&self) -> bool {
+      return self.head.is_null();
+   }
+
+   /// Push the block at `address` to the front of the list.
+   ///
+   /// ## Safety
+   ///
+   /// `address` must point to at least [`MIN_BLOCK`] bytes of unused memory.
+   pub unsafe fn push(&mut self, address: usize) {
+      let block = address as *mut FreeBlock;
+      block.write(FreeBlock{
+         next: self.head,
+         previous: ptr::null_mut(),
+      });
+
+      if !self.head.is_null() {
+         (*self.head).previous = block;
+      }
+
+      self.head = block;
+      self.length += 1;
+   }
+
+   /// Try to remove the first block from the list.
+   pub fn pop(&mut self) -> Option<usize> {
+      if self.empty() {
+         return None;
+      }
+
+      let block = self.head;
+      unsafe { self.remove(block as usize) };
+      return Some(block as usize);
+   }
+
+   /// Unlink the block at `address`.
+   ///
+   /// ## Safety
+   ///
+   /// The block must currently be on this list.
+   pub unsafe fn remove(&mut self, address: usize) {
+      let block = address as *mut FreeBlock;
+
+      if (*block).previous.is_null() {
+         self.head = (*block).next;
+      } else {
+         (*(*block).previous).next = (*block).next;
+      }
+
+      if !(*block).next.is_null() {
+         (*(*block).next).previous = (*block).previous;
+      }
+
+      self.length -= 1;
+   }
+}
+
+/// A snapshot of heap usage, as returned by [`stats`](crate::alloc::stats).
+#[derive(Copy, Clone, Debug)]
+pub struct HeapStats {
+   /// Bytes managed by the heap.
+   pub total: usize,
+
+   /// Bytes handed out, including rounding.
+   pub allocated: usize,
+
+   /// Bytes actually requested by callers.
+   pub user: usize,
+
+   /// The highest value `allocated` has ever reached.
+   pub peak: usize,
+
+   /// Number of successful allocations.
+   pub allocations: usize,
+
+   /// Number of deallocations.
+   pub frees: usize,
+
+   /// Size of the largest free block, i.e. the largest allocation guaranteed to succeed.
+   pub largest_free: usize,
+
+   /// Number of free blocks of each order, where a block of order `n` is `2^n` bytes.
+   pub free_blocks: [usize; usize::BITS as usize],
+}
+
+impl HeapStats {
+   /// Bytes not currently handed out.
+   pub fn free(&self) -> usize {
+      return self.total - self.allocated;
+   }
+
+   /// External fragmentation, from `0.0` (all free memory is one block) to `1.0`.
+   pub fn fragmentation(&self) -> f32 {
+      return match self.free() {
+         0 => 0.0,
+         free => 1.0 - self.largest_free as f32 / free as f32,
+      };
+   }
+}
+
+impl Display for HeapStats {
+   fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+      writeln!(
+         f,
+         "heap: {} of {} bytes allocated ({} requested), peak {}",
+         self.allocated, self.total, self.user, self.peak,
+      )?;
+      writeln!(
+         f,
+         "heap: {} allocations, {} frees, largest free block {} bytes, fragmentation {:.2}",
+         self.allocations, self.frees, self.largest_free, self.fragmentation(),
+      )?;
+
+      for (order, &count) in self.free_blocks.iter().enumerate().filter(|(_, &count)| count > 0) {
+         writeln!(f, "heap:   order {:2} ({} bytes): {} free", order, 1usize << order, count)?;
+      }
+
+      return Ok(());
+   }
 }
 
 impl<const ORDER: usize> Heap<ORDER> {
@@ -15,120 +308,375 @@ impl<const ORDER: usize> Heap<ORDER> {
    pub const fn new() -> Self {
       return Heap{
          allocated: 0,
-         freeList: [LinkedList::new(); ORDER],
+         freeList: [FreeList::new(); ORDER],
          total: 0,
          user: 0,
+         peak: 0,
+         allocations: 0,
+         frees: 0,
+         regions: [Region::EMPTY; MAX_REGIONS],
+         regionCount: 0,
       };
    }
 
-   pub unsafe fn add_to_heap(&mut self, mut start: usize, mut end: usize) {
-      // Avoid unaligned access.
-      start = (start + size_of::<usize>() - 1) & (!size_of::<usize>() + 1);
-      end &= !size_of::<usize>() + 1;
+   /// Adds the memory between `start` and `end` to the heap.
+   ///
+   /// A range starting where a region ends extends that region; any other range becomes a new
+   /// one. Fails if the heap already manages [`MAX_REGIONS`] regions, or if there is no room
+   /// for the region's free bitmaps.
+   pub unsafe fn add_to_heap(&mut self, mut start: usize, mut end: usize) -> Result<(), AllocationError> {
+      // Every block must be able to hold a free-list header.
+      start = (start + MIN_BLOCK - 1) & !(MIN_BLOCK - 1);
+      end &= !(MIN_BLOCK - 1);
       assert!(start <= end);
 
-      let mut total = 0;
-      let mut current_start = start;
-
-      while current_start + size_of::<usize>() <= end {
-         let lowbit = current_start & (!current_start + 1);
-         let size = min(lowbit, previous_po2(end - current_start));
-         total += size;
-
-         self.freeList[size.trailing_zeros() as usize].push(current_start as *mut usize);
-         current_start += size;
+      if start == end {
+         return Ok(());
       }
 
-      self.total += total;
+      return match self.regions[..self.regionCount].iter().position(|region| region.end == start) {
+         Some(index) => self.extend_region(index, end),
+         None => self.add_region(start, end),
+      };
    }
 
    /// Allocate a block of memory large enough to contain `size` bytes,
-   /// and aligned on `align`.  This will return NULL if the `align` is
-   /// greater than `MIN_HEAP_ALIGN`, if `align` is not a power of 2, or
-   /// if we can't find enough memory.
+   /// and aligned on `align`.  Returns an error if `align` is not a power
+   /// of 2, or if we can't find enough memory.
    ///
    /// All allocated memory must be passed to `deallocate` with the same
    /// `size` and `align` parameter, or else horrible things will happen.
    pub unsafe fn allocate(&mut self, layout: Layout) -> Result<NonNull<u8>, AllocationError> {
-      let size = max(
-         layout.size.nextPowerOf2(),
-         max(layout.align, size_of::<usize>()),
-      );
+      let class = Self::class_of(layout.size, layout.align);
 
-      let class = size.trailing_zeros() as usize;
-      for i in class..self.freeList.len() {
-         // Find the first non-empty size class
-         if !self.freeList[i].empty() {
-            // Split the buffers.
-            for j in (class + 1..i + 1).rev() {
-               if let Some(block) = self.freeList[j].pop() {
-                  unsafe {
-                     self.freeList[j - 1].push((block as usize + (1 << (j - 1))) as *mut usize);
-                     self.freeList[j - 1].push(block);
-                  }
-               } else {
-                  return Err(AllocationError);
-               }
-            }
+      // Find the first non-empty size class.
+      let order = (class..self.freeList.len())
+         .find(|&order| !self.freeList[order].empty())
+         .ok_or(AllocationError)?;
 
-            let result = NonNull::new(
-               self.freeList[class].pop()
-                  .expect("current block should have free space") as *mut u8
-            );
-
-            return if let Some(result) = result {
-               self.user += layout.size;
-               self.allocated += size;
-               Ok(result)
-            } else {
-               Err(AllocationError)
-            };
-         }
+      let block = self.pop_free(order).ok_or(AllocationError)?;
+
+      // Split the block, returning the upper halves to the free lists.
+      for j in (class..order).rev() {
+         self.push_free(block + (1 << j), j);
       }
 
-      return Err(AllocationError);
+      self.user += layout.size;
+      self.allocated += 1 << class;
+      self.peak = max(self.peak, self.allocated);
+      self.allocations += 1;
+
+      return NonNull::new(block as *mut u8).ok_or(AllocationError);
    }
 
    pub unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
-      let size = max(
-         layout.size.nextPowerOf2(),
-         max(layout.align, size_of::<usize>()),
-      );
-
-      let class = size.trailing_zeros() as usize;
+      let class = Self::class_of(layout.size, layout.align);
 
-      // Place the block back into the free list.
-      self.freeList[class].push(ptr.as_ptr() as *mut usize);
-
-      // Merge buddy free lists.
+      // Merge with free buddies for as long as possible.
       let mut currentPointer = ptr.as_ptr() as usize;
       let mut currentClass = class;
 
-      while currentClass < self.freeList.len() {
+      while currentClass + 1 < self.freeList.len() {
          let buddy = currentPointer ^ (1 << currentClass);
-         let mut flag = false;
+         if !self.is_free(buddy, currentClass) {
+            break;
+         }
 
-         for block in self.freeList[currentClass].iterator_mut() {
-            if block.value() as usize == buddy {
-               block.pop();
-               flag = true;
-               break;
+         self.remove_free(buddy, currentClass);
+         currentPointer = min(currentPointer, buddy);
+         currentClass += 1;
+      }
+
+      // Place the (possibly merged) block back into the free list.
+      self.push_free(currentPointer, currentClass);
+
+      self.user -= layout.size;
+      self.allocated -= 1 << class;
+      self.frees += 1;
+   }
+
+   /// Tries to resize the allocation at `ptr` to `new_size` bytes without moving it.
+   ///
+   /// Shrinking splits the unused upper halves back onto the free lists. Growing absorbs the
+   /// buddies directly above the block, which is only possible while the block is the lower
+   /// half at every level and each of those buddies is free. Returns `false`, leaving the
+   /// allocation untouched, if the block cannot be resized in place.
+   pub unsafe fn reallocate_in_place(
+      &mut self,
+      ptr: NonNull<u8>,
+      layout: Layout,
+      new_size: usize,
+   ) -> bool {
+      let oldClass = Self::class_of(layout.size, layout.align);
+      let newClass = Self::class_of(new_size, layout.align);
+      let address = ptr.as_ptr() as usize;
+
+      if newClass >= self.freeList.len() {
+         return false;
+      }
+
+      if newClass < oldClass {
+         for class in newClass..oldClass {
+            self.push_free(address + (1 << class), class);
+         }
+      } else if newClass > oldClass {
+         for class in oldClass..newClass {
+            let buddy = address ^ (1 << class);
+            if buddy < address || !self.is_free(buddy, class) {
+               return false;
             }
          }
 
-         if flag {
-            self.freeList[currentClass].pop();
-            currentPointer = min(currentPointer, buddy);
-            currentClass += 1;
-            self.freeList[currentClass].push(currentPointer as *mut usize);
-         } else {
-            break;
+         for class in oldClass..newClass {
+            self.remove_free(address + (1 << class), class);
          }
       }
 
-      self.user -= layout.size;
-      self.allocated -= size;
+      self.allocated = self.allocated - (1 << oldClass) + (1 << newClass);
+      self.user = self.user - layout.size + new_size;
+      self.peak = max(self.peak, self.allocated);
+
+      return true;
+   }
+
+   /// The size of the block that would hold an allocation of `size` bytes aligned to `align`.
+   pub fn block_size(size: usize, align: usize) -> usize {
+      return max(size.nextPowerOf2(), max(align, MIN_BLOCK));
+   }
+
+   /// The free-list index of the block that would hold an allocation of `size` bytes aligned
+   /// to `align`.
+   fn class_of(size: usize, align: usize) -> usize {
+      return Self::block_size(size, align).trailing_zeros() as usize;
    }
+
+   /// Puts the block at `address` on the free list for `order`.
+   unsafe fn push_free(&mut self, address: usize, order: usize) {
+      self.freeList[order].push(address);
+      self.mark(address, order, true);
+   }
+
+   /// Takes the block at `address` off the free list for `order`.
+   unsafe fn remove_free(&mut self, address: usize, order: usize) {
+      self.freeList[order].remove(address);
+      self.mark(address, order, false);
+   }
+
+   /// Takes the first block off the free list for `order`.
+   fn pop_free(&mut self, order: usize) -> Option<usize> {
+      let address = self.freeList[order].pop()?;
+      unsafe { self.mark(address, order, false) };
+
+      return Some(address);
+   }
+
+   unsafe fn mark(&mut self, address: usize, order: usize, free: bool) {
+      let (word, bit) = self.free_bit(address, order).expect("free block outside the heap");
+
+      if free {
+         *word |= bit;
+      } else {
+         *word &= !bit;
+      }
+   }
+
+   /// Returns `true` if the block at `address` is on the free list for `order`, in constant
+   /// time.
+   ///
+   /// The answer comes from the region's free bitmap rather than from the block itself, so
+   /// user data is never mistaken for a free-list header.
+   fn is_free(&self, address: usize, order: usize) -> bool {
+      return match self.free_bit(address, order) {
+         Some((word, bit)) => unsafe { *word & bit != 0 },
+         None => false,
+      };
+   }
+
+   /// The bitmap word and bit recording whether the block of `order` at `address` is free, or
+   /// `None` if the address lies outside the heap.
+   fn free_bit(&self, address: usize, order: usize) -> Option<(*mut u64, u64)> {
+
+      let region = self.regions[..self.regionCount]
+         .iter()
+         .find(|region| address >= region.start && address < region.end)?;
+
+      let size = region.end - region.start;
+      let offset: usize = (MIN_ORDER..order).map(|order| Self::bitmap_words(size, order)).sum();
+      let index = (address - region.start) >> order;
+
+      let word = (region.bitmap as *mut u64).wrapping_add(offset + index / u64::BITS as usize);
+      return Some((word, 1 << (index % u64::BITS as usize)));
+   }
+
+   /// Words in the bitmap for blocks of `order` in a region of `size` bytes.
+   fn bitmap_words(size: usize, order: usize) -> usize {
+      return ((size - 1) >> order) / u64::BITS as usize + 1;
+   }
+
+   /// Bytes needed for the free bitmaps of a region of `size` bytes, rounded up to
+   /// [`MIN_BLOCK`].
+   fn bitmap_size(size: usize) -> usize {
+      let words: usize = (MIN_ORDER..ORDER).map(|order| Self::bitmap_words(size, order)).sum();
+      return (words * size_of::<u64>() + MIN_BLOCK - 1) & !(MIN_BLOCK - 1);
+   }
+
+   /// Starts a new region covering `[start, end)`, with its bitmaps at the bottom.
+   unsafe fn add_region(&mut self, start: usize, end: usize) -> Result<(), AllocationError> {
+      let bitmapSize = Self::bitmap_size(end - start);
+      if self.regionCount == MAX_REGIONS || bitmapSize >= end - start {
+         return Err(AllocationError);
+      }
+
+      ptr::write_bytes(start as *mut u8, 0, bitmapSize);
+      self.regions[self.regionCount] = Region{ start, end, bitmap: start, bitmapSize, carved: true };
+      self.regionCount += 1;
+
+      self.free_range(start + bitmapSize, end);
+      return Ok(());
+   }
+
+   /// Grows region `index` up to `end`, moving its bitmaps somewhere large enough.
+   unsafe fn extend_region(&mut self, index: usize, end: usize) -> Result<(), AllocationError> {
+      let old = self.regions[index];
+      let bitmapSize = Self::bitmap_size(end - old.start);
+
+      // Carve the new bitmaps out of the added memory if they fit, or allocate them otherwise.
+      let carved = bitmapSize < end - old.end;
+      let bitmap = match carved {
+         true => old.end,
+         false => {
+            let layout = Layout::from_size_align(bitmapSize, MIN_BLOCK).map_err(|_| AllocationError)?;
+            self.allocate(layout)?.as_ptr() as usize
+         }
+      };
+
+      // A block's bit keeps its index when the region grows, so each bitmap is copied as is.
+      ptr::write_bytes(bitmap as *mut u8, 0, bitmapSize);
+
+      let mut from = old.bitmap as *const u64;
+      let mut to = bitmap as *mut u64;
+      for order in MIN_ORDER..ORDER {
+         let words = Self::bitmap_words(old.end - old.start, order);
+         ptr::copy_nonoverlapping(from, to, words);
+
+         from = from.add(words);
+         to = to.add(Self::bitmap_words(end - old.start, order));
+      }
+
+      self.regions[index] = Region{ start: old.start, end, bitmap, bitmapSize, carved };
+      self.free_range(if carved { old.end + bitmapSize } else { old.end }, end);
+
+      // The old bitmaps are ordinary memory again.
+      if old.carved {
+         self.free_range(old.bitmap, old.bitmap + old.bitmapSize);
+      } else {
+         let layout = Layout::from_size_align_unchecked(old.bitmapSize, MIN_BLOCK);
+         self.deallocate(NonNull::new_unchecked(old.bitmap as *mut u8), layout);
+      }
+
+      return Ok(());
+   }
+
+   /// Splits `[start, end)` into the largest aligned blocks that fit and puts them on the
+   /// free lists.
+   unsafe fn free_range(&mut self, start: usize, end: usize) {
+      let mut current = start;
+
+      while current + MIN_BLOCK <= end {
+         let lowbit = current & (!current + 1);
+         let size = min(min(lowbit, previous_po2(end - current)), 1 << (ORDER - 1));
+
+         self.push_free(current, size.trailing_zeros() as usize);
+         self.total += size;
+         current += size;
+      }
+   }
+
+   /// Returns a snapshot of this heap's usage.
+   pub fn stats(&self) -> HeapStats {
+      let mut free_blocks = [0; usize::BITS as usize];
+      let mut largest_free = 0;
+
+      for (order, list) in self.freeList.iter().enumerate() {
+         free_blocks[order] = list.length;
+
+         if free_blocks[order] > 0 {
+            largest_free = 1 << order;
+         }
+      }
+
+      return HeapStats{
+         total: self.total,
+         allocated: self.allocated,
+         user: self.user,
+         peak: self.peak,
+         allocations: self.allocations,
+         frees: self.frees,
+         largest_free,
+         free_blocks,
+      };
+   }
+}
+
+/// Allocates from the heap backend, growing it if the allocation does not fit.
+///
+/// ## Safety
+///
+/// The returned memory must be released with [`release`] and the same layout.
+pub unsafe fn allocate_or_grow(layout: Layout) -> Result<NonNull<u8>, AllocationError> {
+   #[cfg(not(feature = "best-fit"))]
+   {
+      let mut heap = HEAP.lock();
+      let heap = heap.as_mut().ok_or(AllocationError)?;
+
+      return heap
+         .allocate(layout)
+         .or_else(|_| grow_heap(heap, layout).and_then(|_| heap.allocate(layout)));
+   }
+
+   #[cfg(feature = "best-fit")]
+   {
+      let mut heap = FIT_ALLOCATOR.lock();
+
+      return heap.allocate(layout).or_else(|_| {
+         let (start, end) = grow_window(layout)?;
+         heap.add_to_heap(start, end);
+         heap.allocate(layout)
+      });
+   }
+}
+
+/// Tries to resize memory obtained from [`allocate_or_grow`] without moving it.
+///
+/// ## Safety
+///
+/// `pointer` must have been allocated with `layout`.
+pub unsafe fn resize_in_place(pointer: NonNull<u8>, layout: Layout, new_size: usize) -> bool {
+   #[cfg(not(feature = "best-fit"))]
+   return HEAP
+      .lock()
+      .as_mut()
+      .map_or(false, |heap| heap.reallocate_in_place(pointer, layout, new_size));
+
+   #[cfg(feature = "best-fit")]
+   return FIT_ALLOCATOR.lock().reallocate_in_place(pointer, layout, new_size);
+}
+
+/// Returns memory obtained from [`allocate_or_grow`] to the heap backend.
+///
+/// ## Safety
+///
+/// `pointer` must have been allocated with the same `layout`.
+pub unsafe fn release(pointer: NonNull<u8>, layout: Layout) {
+   #[cfg(not(feature = "best-fit"))]
+   HEAP
+      .lock()
+      .as_mut()
+      .expect("must first initialise heap before attempting to deallocate memory")
+      .deallocate(pointer, layout);
+
+   #[cfg(feature = "best-fit")]
+   FIT_ALLOCATOR.lock().deallocate(pointer, layout);
 }
 
 impl<const ORDER: usize> Debug for Heap<ORDER> {
@@ -157,7 +705,7 @@ impl<const ORDER: usize> Debug for Heap<ORDER> {
 /// unsafe {
 ///     heap.lock().init(begin, size);
 ///     // or
-///     heap.lock().add_to_heap(begin, end);
+///     heap.lock().add_to_heap(begin, end).unwrap();
 /// }
 /// ```
 pub struct LockedHeap<const ORDER: usize>(Mutex<Heap<ORDER>>);
@@ -182,6 +730,22 @@ unsafe impl<const ORDER: usize> GlobalAlloc for LockedHeap<ORDER> {
       let layout = Layout::from(layout);
       self.0.lock().deallocate(NonNull::new_unchecked(ptr), layout);
    }
+
+   unsafe fn realloc(&self, ptr: *mut u8, layout: StdLayout, new_size: usize) -> *mut u8 {
+      let oldLayout = Layout::from(layout);
+      if self.0.lock().reallocate_in_place(NonNull::new_unchecked(ptr), oldLayout, new_size) {
+         return ptr;
+      }
+
+      let newLayout = Layout::from_size_align_unchecked(new_size, layout.align());
+      let newPointer = self.alloc(newLayout.into());
+      if !newPointer.is_null() {
+         ptr::copy_nonoverlapping(ptr, newPointer, min(layout.size(), new_size));
+         self.dealloc(ptr, layout);
+      }
+
+      return newPointer;
+   }
 }
 
 // IMPORTS //
@@ -189,15 +753,202 @@ unsafe impl<const ORDER: usize> GlobalAlloc for LockedHeap<ORDER> {
 use {
    crate::{
       alloc::{AllocationError, Layout},
-      array::linked_list::LinkedList,
       math::{previous_po2, PowersOf2},
    },
    std_alloc::alloc::{GlobalAlloc, Layout as StdLayout},
    core::{
       cmp::{min, max},
-      fmt::{Debug, Formatter, Result as FmtResult},
+      fmt::{Debug, Display, Formatter, Result as FmtResult},
       mem::size_of,
-      ptr::NonNull,
+      ptr::{self, NonNull},
+      sync::atomic::{AtomicUsize, Ordering},
    },
    spin::Mutex,
 };
+
+#[cfg(feature = "best-fit")]
+use crate::alloc::paging::FIT_ALLOCATOR;
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::test::{init_host_heap, random_allocations, HostArena, TestHeap},
+      std::{
+         time::{Duration, Instant},
+         vec::Vec,
+      },
+   };
+
+   #[repr(align(4096))]
+   struct Arena([u8; 64 * 1024]);
+
+   impl<const ORDER: usize> TestHeap for Heap<ORDER> {
+      unsafe fn allocate(&mut self, layout: Layout) -> Result<NonNull<u8>, AllocationError> {
+         return Heap::allocate(self, layout);
+      }
+
+      unsafe fn reallocate_in_place(&mut self, pointer: NonNull<u8>, layout: Layout, new_size: usize) -> bool {
+         return Heap::reallocate_in_place(self, pointer, layout, new_size);
+      }
+
+      unsafe fn deallocate(&mut self, pointer: NonNull<u8>, layout: Layout) {
+         Heap::deallocate(self, pointer, layout);
+      }
+
+      fn usage(&self) -> (usize, usize) {
+         return (self.allocated, self.total);
+      }
+   }
+
+   #[test]
+   fn over_aligned_allocations() {
+      let mut arena = Arena([0; 64 * 1024]);
+      let start = arena.0.as_mut_ptr() as usize;
+
+      let mut heap = Heap::<32>::new();
+      unsafe { heap.add_to_heap(start, start + arena.0.len()) }.unwrap();
+
+      for align in [16, 64, 512, 4096] {
+         let layout = Layout::from_size_align(24, align).unwrap();
+         let pointer = unsafe { heap.allocate(layout) }.unwrap();
+
+         assert_eq!(pointer.as_ptr() as usize % align, 0);
+         unsafe { heap.deallocate(pointer, layout) };
+      }
+
+      assert_eq!(heap.allocated, 0);
+   }
+
+   #[test]
+   fn locked_heap_honours_std_layout() {
+      let mut arena = Arena([0; 64 * 1024]);
+      let start = arena.0.as_mut_ptr() as usize;
+
+      let heap = LockedHeap::<32>::new();
+      unsafe { heap.0.lock().add_to_heap(start, start + arena.0.len()) }.unwrap();
+
+      let layout = StdLayout::from_size_align(100, 4096).unwrap();
+      let pointer = unsafe { heap.alloc(layout) };
+
+      assert!(!pointer.is_null());
+      assert_eq!(pointer as usize % 4096, 0);
+      unsafe { heap.dealloc(pointer, layout) };
+   }
+
+   /// Fills a heap of `size` bytes with minimum-sized blocks, then frees every even block
+   /// followed by every odd block. The first pass leaves half the blocks on one free list, and
+   /// every free in the second pass coalesces with a buddy sitting somewhere in that list, which
+   /// is quadratic with a list scan and linear with constant-time buddy lookup.
+   ///
+   /// Returns the number of blocks freed and the fastest of a few runs at freeing them.
+   fn free_time(size: usize) -> (usize, Duration) {
+      let arena = HostArena::new(size);
+      let layout = Layout::from_size_align(MIN_BLOCK, MIN_BLOCK).unwrap();
+      let mut fastest = Duration::MAX;
+      let mut frees = 0;
+
+      for _ in 0..5 {
+         let mut heap = Heap::<32>::new();
+         unsafe { heap.add_to_heap(arena.start, arena.end()) }.unwrap();
+         let initial = heap.stats().free_blocks;
+
+         let mut blocks: Vec<_> = core::iter::from_fn(|| unsafe { heap.allocate(layout) }.ok()).collect();
+         blocks.sort();
+
+         let start = Instant::now();
+         for parity in [0, 1] {
+            for &block in blocks.iter().skip(parity).step_by(2) {
+               unsafe { heap.deallocate(block, layout) };
+            }
+         }
+         fastest = fastest.min(start.elapsed());
+
+         // Everything coalesced back into the blocks the heap started with.
+         assert_eq!(heap.allocated, 0);
+         assert_eq!(heap.stats().free_blocks, initial);
+         frees = blocks.len();
+      }
+
+      return (frees, fastest);
+   }
+
+   #[test]
+   fn deallocation_scales_linearly() {
+      let (smallFrees, small) = free_time(256 * 1024);
+      let (largeFrees, large) = free_time(8 * 256 * 1024);
+
+      // Eight times the frees should take roughly eight times as long; a quadratic free path
+      // would take about sixty-four times as long, so anything under twenty-four passes.
+      assert!(
+         large.as_nanos() * (smallFrees as u128) < small.as_nanos() * (largeFrees as u128) * 3,
+         "{:?} for {} frees vs {:?} for {}", large, largeFrees, small, smallFrees,
+      );
+   }
+
+   #[test]
+   fn random_allocations_never_overlap() {
+      let arena = HostArena::new(1024 * 1024);
+
+      let mut heap = Heap::<32>::new();
+      unsafe { heap.add_to_heap(arena.start, arena.end()) }.unwrap();
+      let initial = heap.stats().free_blocks;
+
+      random_allocations(&mut heap, &arena, 0xb0dd1e5);
+
+      // Every block coalesced back into the ones the heap started with.
+      assert_eq!(heap.allocated, 0);
+      assert_eq!(heap.user, 0);
+      assert_eq!(heap.stats().free_blocks, initial);
+   }
+
+   #[test]
+   fn contiguous_ranges_share_a_region() {
+      let arena = HostArena::new(1024 * 1024);
+      let mut heap = Heap::<32>::new();
+
+      // The third range is too small for the grown bitmaps, so they are allocated from the heap
+      // until the last range makes room for them again.
+      let bounds = [0, 64 * 1024, 512 * 1024, 512 * 1024 + 64, arena.size];
+      for range in bounds.windows(2) {
+         unsafe { heap.add_to_heap(arena.start + range[0], arena.start + range[1]) }.unwrap();
+      }
+
+      assert_eq!(heap.regionCount, 1);
+      assert_eq!(heap.regions[0].end, arena.end());
+      assert!(heap.regions[0].carved);
+
+      random_allocations(&mut heap, &arena, 0x9e0);
+      assert_eq!(heap.allocated, 0);
+   }
+
+   #[test]
+   fn too_many_regions_is_an_error() {
+      let arena = HostArena::new(2 * MAX_REGIONS * 4096 + 4096);
+      let mut heap = Heap::<32>::new();
+
+      // Leave a gap after every range so none of them merge.
+      for region in 0..MAX_REGIONS {
+         let start = arena.start + region * 2 * 4096;
+         unsafe { heap.add_to_heap(start, start + 4096) }.unwrap();
+      }
+
+      let start = arena.start + MAX_REGIONS * 2 * 4096;
+      assert!(unsafe { heap.add_to_heap(start, start + 4096) }.is_err());
+   }
+
+   #[test]
+   fn global_heap_allocates_and_releases() {
+      init_host_heap();
+
+      let layout = Layout::from_size_align(64 * 1024, 4096).unwrap();
+      let pointer = unsafe { allocate_or_grow(layout) }.unwrap();
+
+      assert_eq!(pointer.as_ptr() as usize % 4096, 0);
+      unsafe {
+         ptr::write_bytes(pointer.as_ptr(), 0xAA, layout.size);
+         assert!(resize_in_place(pointer, layout, 32 * 1024));
+         release(pointer, Layout::from_size_align(32 * 1024, 4096).unwrap());
+      }
+   }
+}
@@ -1,5 +1,8 @@
 /// Defines the layout of memory to be allocated.
-#[derive(Copy, Clone)]
+///
+/// Mirrors [`core::alloc::Layout`]: the alignment is always a non-zero power of two, and the
+/// size, rounded up to the alignment, never exceeds `isize::MAX`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Layout {
    #[doc(hidden)]
    pub size: usize,
@@ -10,31 +13,110 @@ pub struct Layout {
 impl Layout {
    /// Creates a new instance of a Layout.
    #[inline]
-   pub fn new<T>() -> Self {
+   pub const fn new<T>() -> Self {
       return Layout {
          size: size_of::<T>(),
          align: align_of::<T>(),
       };
    }
 
-   /// Creates a new instance of a Layout with the given size.
+   /// Creates a layout describing the value behind `value`.
    #[inline]
-   pub fn from_size(size: usize) -> Self {
-      return Layout { size, align: 4 };
+   pub fn for_value<T: ?Sized>(value: &T) -> Self {
+      return Layout {
+         size: size_of_val(value),
+         align: align_of_val(value),
+      };
+   }
+
+   /// Creates a new instance of a Layout with the given size and byte alignment.
+   #[inline]
+   pub const fn from_size(size: usize) -> Self {
+      return Layout { size, align: 1 };
+   }
+
+   /// Creates a layout from the given size and alignment, validating both.
+   ///
+   /// Fails if `align` is not a non-zero power of two, or if `size` rounded up to `align`
+   /// would overflow `isize`.
+   #[inline]
+   pub const fn from_size_align(size: usize, align: usize) -> Result<Self, LayoutError> {
+      if !align.is_power_of_two() || size > isize::MAX as usize - (align - 1) {
+         return Err(LayoutError);
+      }
+
+      return Ok(Layout{ size, align });
    }
 
+   /// Creates a layout without checking its validity.
+   ///
+   /// ## Safety
+   ///
+   /// The arguments must satisfy the requirements of [`from_size_align`](Layout::from_size_align).
    #[inline]
-   pub fn from_size_align(size: usize, align: usize) -> Self {
+   pub const unsafe fn from_size_align_unchecked(size: usize, align: usize) -> Self {
       return Layout{ size, align };
    }
 
    /// Create a new instance of Layout from the given array-length and type parameter.
+   ///
+   /// Panics if the array would be too large; see [`array`](Layout::array).
    #[inline]
    pub fn from_type_array<T>(length: usize) -> Self {
-      return Layout {
-         size: size_of::<T>() * length,
-         align: align_of::<T>(),
-      };
+      return Layout::array::<T>(length).expect("array layout overflow");
+   }
+
+   /// Creates a layout for an array of `length` elements of `T`.
+   #[inline]
+   pub fn array<T>(length: usize) -> Result<Self, LayoutError> {
+      let size = size_of::<T>().checked_mul(length).ok_or(LayoutError)?;
+      return Layout::from_size_align(size, align_of::<T>());
+   }
+
+   /// The size of the allocation, in bytes.
+   #[inline]
+   pub const fn size(&self) -> usize {
+      return self.size;
+   }
+
+   /// The alignment of the allocation, in bytes.
+   #[inline]
+   pub const fn align(&self) -> usize {
+      return self.align;
+   }
+
+   /// Creates a layout with the same size and an alignment of at least `align`.
+   #[inline]
+   pub fn align_to(&self, align: usize) -> Result<Self, LayoutError> {
+      return Layout::from_size_align(self.size, max(self.align, align));
+   }
+
+   /// Number of bytes of padding needed after `self` so that the following address is
+   /// aligned to `align`.
+   #[inline]
+   pub const fn padding_needed_for(&self, align: usize) -> usize {
+      let rounded = self.size.wrapping_add(align).wrapping_sub(1) & !align.wrapping_sub(1);
+      return rounded.wrapping_sub(self.size);
+   }
+
+   /// Rounds the size up to a multiple of the alignment.
+   #[inline]
+   pub const fn pad_to_align(&self) -> Self {
+      let size = self.size + self.padding_needed_for(self.align);
+      return Layout{ size, align: self.align };
+   }
+
+   /// Creates a layout describing `self` followed by `next`, with `next` properly aligned.
+   ///
+   /// Returns the combined layout and the offset of `next` within it. The result is not
+   /// padded to its own alignment; call [`pad_to_align`](Layout::pad_to_align) for that.
+   #[inline]
+   pub fn extend(&self, next: Self) -> Result<(Self, usize), LayoutError> {
+      let align = max(self.align, next.align);
+      let offset = self.size.checked_add(self.padding_needed_for(next.align)).ok_or(LayoutError)?;
+      let size = offset.checked_add(next.size).ok_or(LayoutError)?;
+
+      return Ok((Layout::from_size_align(size, align)?, offset));
    }
 
    /// Realigns data.
@@ -53,7 +135,10 @@ impl Into<StdLayout> for Layout {
 
 impl From<StdLayout> for Layout {
    fn from(value: StdLayout) -> Self {
-      return Layout::from_size(value.size());
+      return Layout{
+         size: value.size(),
+         align: value.align(),
+      };
    }
 }
 
@@ -71,7 +156,108 @@ impl Display for LayoutError {
 use {
    std_alloc::alloc::Layout as StdLayout,
    core::{
+      cmp::max,
       fmt::{self, Display},
-      mem::{align_of, size_of},
+      mem::{align_of, align_of_val, size_of, size_of_val},
    },
 };
+
+#[cfg(test)]
+mod tests {
+   use {super::*, crate::test::Random};
+
+   /// A random alignment, occasionally not a power of two.
+   fn random_align(random: &mut Random) -> usize {
+      return match random.below(8) {
+         0 => random.below(64),
+         _ => 1 << random.below(usize::BITS as usize),
+      };
+   }
+
+   #[test]
+   fn from_size_align_rejects_invalid_alignment() {
+      assert!(Layout::from_size_align(8, 0).is_err());
+      assert!(Layout::from_size_align(8, 3).is_err());
+      assert!(Layout::from_size_align(isize::MAX as usize, 2).is_err());
+      assert!(Layout::from_size_align(8, 4096).is_ok());
+   }
+
+   #[test]
+   fn conversion_preserves_alignment() {
+      let std = StdLayout::from_size_align(24, 4096).unwrap();
+      let layout = Layout::from(std);
+
+      assert_eq!(layout.size(), 24);
+      assert_eq!(layout.align(), 4096);
+   }
+
+   #[test]
+   fn extend_and_pad() {
+      let header = Layout::new::<u8>();
+      let (layout, offset) = header.extend(Layout::new::<u64>()).unwrap();
+
+      assert_eq!(offset, 8);
+      assert_eq!(layout.size(), 16);
+      assert_eq!(layout.align(), 8);
+
+      let padded = Layout::from_size_align(13, 8).unwrap().pad_to_align();
+      assert_eq!(padded.size(), 16);
+
+      assert!(Layout::array::<u32>(usize::MAX).is_err());
+      assert_eq!(Layout::array::<u32>(4).unwrap().size(), 16);
+   }
+
+   #[test]
+   fn validation_matches_core() {
+      let mut random = Random::new(0x1a70);
+
+      for _ in 0..8192 {
+         let size = random.magnitude();
+         let align = random_align(&mut random);
+
+         let ours = Layout::from_size_align(size, align);
+         let std = StdLayout::from_size_align(size, align);
+         assert_eq!(ours.is_ok(), std.is_ok(), "size {} align {}", size, align);
+
+         if let (Ok(ours), Ok(std)) = (ours, std) {
+            assert_eq!(ours.pad_to_align(), Layout::from(std.pad_to_align()));
+            assert_eq!(ours.padding_needed_for(align), ours.pad_to_align().size() - size);
+         }
+      }
+   }
+
+   #[test]
+   fn extend_matches_core() {
+      let mut random = Random::new(0xe8e7d);
+
+      for _ in 0..8192 {
+         let (size, align) = (random.magnitude() >> 2, 1 << random.below(16));
+         let (nextSize, nextAlign) = (random.magnitude() >> 2, 1 << random.below(16));
+
+         let ours = Layout::from_size_align(size, align).unwrap()
+            .extend(Layout::from_size_align(nextSize, nextAlign).unwrap());
+         let std = StdLayout::from_size_align(size, align).unwrap()
+            .extend(StdLayout::from_size_align(nextSize, nextAlign).unwrap());
+
+         match (ours, std) {
+            (Ok((ours, offset)), Ok((std, stdOffset))) => {
+               assert_eq!(ours, Layout::from(std));
+               assert_eq!(offset, stdOffset);
+            }
+            (ours, std) => assert_eq!(ours.is_ok(), std.is_ok()),
+         }
+      }
+   }
+
+   #[test]
+   fn array_matches_core() {
+      let mut random = Random::new(0xa77a);
+
+      for _ in 0..8192 {
+         let length = random.magnitude();
+
+         assert_eq!(Layout::array::<u64>(length).ok(), StdLayout::array::<u64>(length).ok().map(Layout::from));
+         assert_eq!(Layout::array::<()>(length).ok(), StdLayout::array::<()>(length).ok().map(Layout::from));
+      }
+   }
+}
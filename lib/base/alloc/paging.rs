@@ -77,7 +77,434 @@ impl Table {
 
 // BUDDY ALLOCATOR //
 
+/// The buddy allocator backing the kernel heap; see [`Heap`](crate::alloc::heap::Heap).
+pub use super::heap::HEAP as BUDDY_ALLOCATOR;
+
 // BEST-FIT ALLOCATOR //
-// TODO: implement best-fit allocator.
+
+/// The best-fit allocator. Backs the kernel heap instead of
+/// [`BUDDY_ALLOCATOR`] when the `best-fit` feature is enabled.
+pub static FIT_ALLOCATOR: LockedFitHeap = LockedFitHeap::new();
+
+/// Granularity of best-fit allocations; also the smallest block that can sit on the free list.
+pub const FIT_MIN_BLOCK: usize = size_of::<FreeBlock>();
+
+/// A free region, stored in place at the start of the region itself.
+#[repr(C)]
+pub struct FreeBlock {
+   pub size: usize,
+   pub next: *mut FreeBlock,
+}
+
+/// A best-fit allocator over an address-ordered free list.
+///
+/// Allocation walks the whole list and picks the smallest block that fits, splitting off any
+/// leftover space. Freed blocks are inserted in address order and coalesced with their
+/// neighbours, so adjacent free space never stays fragmented.
+pub struct FitHeap {
+   pub head: *mut FreeBlock,
+   pub allocated: usize,
+   pub total: usize,
+   pub user: usize,
+   pub peak: usize,
+   pub allocations: usize,
+   pub frees: usize,
+}
+
+unsafe impl Send for FitHeap {}
+
+impl FitHeap {
+   /// Create an empty heap.
+   pub const fn new() -> Self {
+      return FitHeap{
+         head: ptr::null_mut(),
+         allocated: 0,
+         total: 0,
+         user: 0,
+         peak: 0,
+         allocations: 0,
+         frees: 0,
+      };
+   }
+
+   /// Adds the memory between `start` and `end` to the heap.
+   ///
+   /// ## Safety
+   ///
+   /// The range must be valid, writable and unused.
+   pub unsafe fn add_to_heap(&mut self, start: usize, end: usize) {
+      let start = align_up(start, FIT_MIN_BLOCK);
+      let end = end & !(FIT_MIN_BLOCK - 1);
+      if end <= start {
+         return;
+      }
+
+      self.total += end - start;
+      self.insert(start, end - start);
+   }
+
+   /// Allocates the smallest free block able to hold `layout`.
+   pub unsafe fn allocate(&mut self, layout: Layout) -> Result<NonNull<u8>, AllocationError> {
+      let size = Self::block_size(layout);
+      let align = max(layout.align, FIT_MIN_BLOCK);
+
+      // Find the best fit, remembering its predecessor so it can be unlinked.
+      let mut best: Option<(*mut FreeBlock, *mut FreeBlock, usize)> = None;
+      let mut previous: *mut FreeBlock = ptr::null_mut();
+      let mut current = self.head;
+
+      while !current.is_null() {
+         if let Some(start) = Self::fit(current, size, align) {
+            let better = match best {
+               Some((_, block, _)) => (*current).size < (*block).size,
+               None => true,
+            };
+
+            if better {
+               best = Some((previous, current, start));
+            }
+         }
+
+         previous = current;
+         current = (*current).next;
+      }
+
+      let (previous, block, start) = best.ok_or(AllocationError)?;
+      let blockStart = block as usize;
+      let blockEnd = blockStart + (*block).size;
+
+      // Unlink the chosen block, then give back whatever is left on either side.
+      self.unlink(previous, block);
+
+      if start > blockStart {
+         self.insert(blockStart, start - blockStart);
+      }
+
+      if blockEnd > start + size {
+         self.insert(start + size, blockEnd - (start + size));
+      }
+
+      self.allocated += size;
+      self.user += layout.size;
+      self.peak = max(self.peak, self.allocated);
+      self.allocations += 1;
+
+      return Ok(NonNull::new_unchecked(start as *mut u8));
+   }
+
+   /// Returns a block to the free list, merging it with adjacent free blocks.
+   ///
+   /// ## Safety
+   ///
+   /// `pointer` must have been allocated from this heap with the same `layout`.
+   pub unsafe fn deallocate(&mut self, pointer: NonNull<u8>, layout: Layout) {
+      let size = Self::block_size(layout);
+
+      self.insert(pointer.as_ptr() as usize, size);
+      self.allocated -= size;
+      self.user -= layout.size;
+      self.frees += 1;
+   }
+
+   /// Tries to resize the allocation at `pointer` to `new_size` bytes without moving it.
+   ///
+   /// Shrinking returns the tail to the free list; growing succeeds if the free block
+   /// immediately after the allocation is large enough.
+   pub unsafe fn reallocate_in_place(
+      &mut self,
+      pointer: NonNull<u8>,
+      layout: Layout,
+      new_size: usize,
+   ) -> bool {
+      let address = pointer.as_ptr() as usize;
+      let oldSize = Self::block_size(layout);
+      let newSize = Self::block_size(Layout::from_size_align_unchecked(new_size, layout.align));
+
+      if newSize < oldSize {
+         self.insert(address + newSize, oldSize - newSize);
+      } else if newSize > oldSize {
+         let end = address + oldSize;
+         let needed = newSize - oldSize;
+
+         // Find the free block starting right where the allocation ends.
+         let mut previous: *mut FreeBlock = ptr::null_mut();
+         let mut current = self.head;
+         while !current.is_null() && (current as usize) < end {
+            previous = current;
+            current = (*current).next;
+         }
+
+         if current as usize != end || (*current).size < needed {
+            return false;
+         }
+
+         let remainder = (*current).size - needed;
+         if remainder != 0 && remainder < FIT_MIN_BLOCK {
+            return false;
+         }
+
+         self.unlink(previous, current);
+         if remainder > 0 {
+            self.insert(end + needed, remainder);
+         }
+      }
+
+      self.allocated = self.allocated - oldSize + newSize;
+      self.user = self.user - layout.size + new_size;
+      self.peak = max(self.peak, self.allocated);
+
+      return true;
+   }
+
+   /// Size of the largest free block.
+   pub fn largest_free(&self) -> usize {
+      return self.stats().largest_free;
+   }
+
+   /// Returns a snapshot of this heap's usage.
+   ///
+   /// Free blocks are bucketed by the order of their size rounded down to a power of two.
+   pub fn stats(&self) -> HeapStats {
+      let mut free_blocks = [0; usize::BITS as usize];
+      let mut largest_free = 0;
+      let mut current = self.head;
+
+      while !current.is_null() {
+         unsafe {
+            free_blocks[(*current).size.log2() as usize] += 1;
+            largest_free = max(largest_free, (*current).size);
+            current = (*current).next;
+         }
+      }
+
+      return HeapStats{
+         total: self.total,
+         allocated: self.allocated,
+         user: self.user,
+         peak: self.peak,
+         allocations: self.allocations,
+         frees: self.frees,
+         largest_free,
+         free_blocks,
+      };
+   }
+
+   fn block_size(layout: Layout) -> usize {
+      return max(align_up(layout.size, FIT_MIN_BLOCK), FIT_MIN_BLOCK);
+   }
+
+   /// Returns the aligned start address if `block` can hold `size` bytes.
+   ///
+   /// Any padding in front of the allocation must itself be large enough to go back on the
+   /// free list.
+   unsafe fn fit(block: *mut FreeBlock, size: usize, align: usize) -> Option<usize> {
+      let blockStart = block as usize;
+      let blockEnd = blockStart + (*block).size;
+
+      let mut start = align_up(blockStart, align);
+      if start != blockStart && start - blockStart < FIT_MIN_BLOCK {
+         start = align_up(blockStart + FIT_MIN_BLOCK, align);
+      }
+
+      let end = start.checked_add(size)?;
+      if end > blockEnd {
+         return None;
+      }
+
+      let remainder = blockEnd - end;
+      return if remainder == 0 || remainder >= FIT_MIN_BLOCK {
+         Some(start)
+      } else {
+         None
+      };
+   }
+
+   unsafe fn unlink(&mut self, previous: *mut FreeBlock, block: *mut FreeBlock) {
+      if previous.is_null() {
+         self.head = (*block).next;
+      } else {
+         (*previous).next = (*block).next;
+      }
+   }
+
+   /// Inserts a region in address order, coalescing with its neighbours.
+   unsafe fn insert(&mut self, address: usize, size: usize) {
+      let mut previous: *mut FreeBlock = ptr::null_mut();
+      let mut next = self.head;
+
+      while !next.is_null() && (next as usize) < address {
+         previous = next;
+         next = (*next).next;
+      }
+
+      let block = address as *mut FreeBlock;
+      block.write(FreeBlock{ size, next });
+
+      if previous.is_null() {
+         self.head = block;
+      } else {
+         (*previous).next = block;
+      }
+
+      // Merge with the following block.
+      if !next.is_null() && address + size == next as usize {
+         (*block).size += (*next).size;
+         (*block).next = (*next).next;
+      }
+
+      // Merge with the preceding block.
+      if !previous.is_null() && previous as usize + (*previous).size == address {
+         (*previous).size += (*block).size;
+         (*previous).next = (*block).next;
+      }
+   }
+}
+
+impl Debug for FitHeap {
+   fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+      f.debug_struct("FitHeap")
+         .field("user", &self.user)
+         .field("allocated", &self.allocated)
+         .field("total", &self.total)
+         .finish()
+   }
+}
+
+/// A locked version of [`FitHeap`].
+pub struct LockedFitHeap(pub Mutex<FitHeap>);
+
+impl LockedFitHeap {
+   pub const fn new() -> Self {
+      return LockedFitHeap(Mutex::new(FitHeap::new()));
+   }
+
+   pub fn lock(&self) -> MutexGuard<FitHeap> {
+      return self.0.lock();
+   }
+}
+
+unsafe impl Allocator for LockedFitHeap {
+   fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+      return unsafe { self.lock().allocate(layout).ok() };
+   }
+
+   unsafe fn deallocate(&self, pointer: *mut u8, layout: Layout) {
+      self.lock().deallocate(NonNull::new_unchecked(pointer), layout);
+   }
+
+   unsafe fn reallocate(
+      &self,
+      pointer: *mut u8,
+      oldSize: usize,
+      layout: Layout,
+   ) -> Option<NonNull<u8>> {
+      let newPointer = self.allocate(layout)?;
+      ptr::copy_nonoverlapping(pointer, newPointer.as_ptr(), min(oldSize, layout.size));
+      self.deallocate(pointer, Layout::from_size_align_unchecked(oldSize, layout.align));
+
+      return Some(newPointer);
+   }
+}
+
+unsafe impl GlobalAlloc for LockedFitHeap {
+   unsafe fn alloc(&self, layout: StdLayout) -> *mut u8 {
+      let layout = Layout::from(layout);
+
+      self.lock()
+         .allocate(layout)
+         .ok()
+         .map_or(0 as *mut u8, |allocation| allocation.as_ptr())
+   }
+
+   unsafe fn dealloc(&self, ptr: *mut u8, layout: StdLayout) {
+      let layout = Layout::from(layout);
+      self.lock().deallocate(NonNull::new_unchecked(ptr), layout);
+   }
+}
+
+fn align_up(address: usize, align: usize) -> usize {
+   return (address + align - 1) & !(align - 1);
+}
 
 // IMPORTS //
+
+use {
+   crate::{
+      alloc::{heap::HeapStats, AllocationError, Allocator, Layout},
+      math::PowersOf2,
+   },
+   core::{
+      cmp::{max, min},
+      fmt::{Debug, Formatter, Result as FmtResult},
+      mem::size_of,
+      ptr::{self, NonNull},
+   },
+   spin::{Mutex, MutexGuard},
+   std_alloc::alloc::{GlobalAlloc, Layout as StdLayout},
+};
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::test::{random_allocations, HostArena, TestHeap},
+   };
+
+   #[repr(align(4096))]
+   struct Arena([u8; 64 * 1024]);
+
+   impl TestHeap for FitHeap {
+      unsafe fn allocate(&mut self, layout: Layout) -> Result<NonNull<u8>, AllocationError> {
+         return FitHeap::allocate(self, layout);
+      }
+
+      unsafe fn reallocate_in_place(&mut self, pointer: NonNull<u8>, layout: Layout, new_size: usize) -> bool {
+         return FitHeap::reallocate_in_place(self, pointer, layout, new_size);
+      }
+
+      unsafe fn deallocate(&mut self, pointer: NonNull<u8>, layout: Layout) {
+         FitHeap::deallocate(self, pointer, layout);
+      }
+
+      fn usage(&self) -> (usize, usize) {
+         return (self.allocated, self.total);
+      }
+   }
+
+   #[test]
+   fn over_aligned_allocations() {
+      let mut arena = Arena([0; 64 * 1024]);
+      let start = arena.0.as_mut_ptr() as usize;
+
+      let heap = LockedFitHeap::new();
+      unsafe { heap.lock().add_to_heap(start + 8, start + arena.0.len()) };
+
+      for align in [16, 64, 512, 4096] {
+         let layout = Layout::from_size_align(24, align).unwrap();
+         let pointer = heap.allocate(layout).unwrap();
+
+         assert_eq!(pointer.as_ptr() as usize % align, 0);
+         unsafe { heap.deallocate(pointer.as_ptr(), layout) };
+      }
+
+      // Everything coalesces back into a single block.
+      let stats = heap.lock().stats();
+      assert_eq!(stats.allocated, 0);
+      assert_eq!(stats.largest_free, stats.total);
+   }
+
+   #[test]
+   fn random_allocations_never_overlap() {
+      let arena = HostArena::new(1024 * 1024);
+
+      let heap = LockedFitHeap::new();
+      unsafe { heap.lock().add_to_heap(arena.start, arena.end()) };
+
+      random_allocations(&mut *heap.lock(), &arena, 0xf17);
+
+      // Address-ordered coalescing leaves a single block covering the whole arena.
+      let stats = heap.lock().stats();
+      assert_eq!(stats.allocated, 0);
+      assert_eq!(stats.largest_free, stats.total);
+   }
+}
@@ -0,0 +1,475 @@
+/// Object sizes served by the general-purpose slab caches.
+///
+/// Anything larger than the last class falls through to the buddy heap.
+pub const SIZE_CLASSES: [usize; 16] = [
+   8, 16, 24, 32, 48, 64, 96, 128, 192, 256, 384, 512, 768, 1024, 1536, 2048,
+];
+
+/// Smallest slab carved from the buddy heap.
+pub const SLAB_SIZE: usize = 4096;
+
+/// Maximum number of named caches that can be registered for statistics.
+pub const MAX_NAMED_CACHES: usize = 32;
+
+/// The general-purpose size-class caches backing small [`GlobalAllocator`](super::GlobalAllocator)
+/// allocations.
+pub static SLAB: Mutex<SlabAllocator> = Mutex::new(SlabAllocator::new());
+
+/// Named caches registered through [`ObjectCache::register`].
+pub static NAMED_CACHES: Mutex<[Option<&'static Mutex<SlabCache>>; MAX_NAMED_CACHES]> =
+   Mutex::new([None; MAX_NAMED_CACHES]);
+
+/// Returns the index of the size class able to hold `layout`, or `None` if the allocation
+/// should go to the buddy heap.
+pub fn size_class(layout: Layout) -> Option<usize> {
+   return SIZE_CLASSES
+      .iter()
+      .position(|&size| size >= layout.size && class_align(size) >= layout.align);
+}
+
+/// Calls `f` with the statistics of every size-class and named cache.
+pub fn statistics(mut f: impl FnMut(CacheStats)) {
+   for cache in SLAB.lock().caches.iter() {
+      f(cache.stats());
+   }
+
+   for cache in NAMED_CACHES.lock().iter().flatten() {
+      f(cache.lock().stats());
+   }
+}
+
+/// The natural alignment of objects in a size class: the lowest set bit of its size.
+const fn class_align(size: usize) -> usize {
+   return size & size.wrapping_neg();
+}
+
+/// Per-cache statistics.
+#[derive(Copy, Clone, Debug)]
+pub struct CacheStats {
+   /// Name of the cache.
+   pub name: &'static str,
+
+   /// Size of a single object, in bytes.
+   pub object_size: usize,
+
+   /// Number of slabs currently owned by the cache.
+   pub slabs: usize,
+
+   /// Number of object slots across all slabs.
+   pub objects: usize,
+
+   /// Number of objects currently handed out.
+   pub in_use: usize,
+
+   /// Total number of successful allocations.
+   pub allocations: usize,
+
+   /// Total number of frees.
+   pub frees: usize,
+}
+
+/// Header stored at the start of every slab.
+#[repr(C)]
+pub struct Slab {
+   pub next: *mut Slab,
+   pub previous: *mut Slab,
+   pub free: LinkedList,
+   pub used: usize,
+}
+
+/// A doubly-linked list of slabs, so slabs can move between lists in constant time.
+pub struct SlabList {
+   pub head: *mut Slab,
+   pub length: usize,
+}
+
+impl SlabList {
+   pub const fn new() -> Self {
+      return SlabList{
+         head: ptr::null_mut(),
+         length: 0,
+      };
+   }
+
+   pub fn empty(&self) -> bool {
+      return self.head.is_null();
+   }
+
+   pub unsafe fn push(&mut self, slab: *mut Slab) {
+      (*slab).previous = ptr::null_mut();
+      (*slab).next = self.head;
+
+      if !self.head.is_null() {
+         (*self.head).previous = slab;
+      }
+
+      self.head = slab;
+      self.length += 1;
+   }
+
+   pub unsafe fn remove(&mut self, slab: *mut Slab) {
+      if (*slab).previous.is_null() {
+         self.head = (*slab).next;
+      } else {
+         (*(*slab).previous).next = (*slab).next;
+      }
+
+      if !(*slab).next.is_null() {
+         (*(*slab).next).previous = (*slab).previous;
+      }
+
+      self.length -= 1;
+   }
+
+   pub unsafe fn pop(&mut self) -> Option<*mut Slab> {
+      if self.empty() {
+         return None;
+      }
+
+      let slab = self.head;
+      self.remove(slab);
+      return Some(slab);
+   }
+}
+
+/// A cache of equally sized objects carved out of slabs taken from the buddy heap.
+///
+/// Slabs are aligned to their own size, so the slab owning an object is found by masking the
+/// object's address. At most one completely empty slab is kept around; any further empty slabs
+/// are returned to the heap.
+pub struct SlabCache {
+   pub name: &'static str,
+   pub object_size: usize,
+   pub slab_size: usize,
+
+   /// Offset of the first object from the start of the slab.
+   pub offset: usize,
+
+   /// Number of objects per slab.
+   pub capacity: usize,
+
+   pub partial: SlabList,
+   pub full: SlabList,
+   pub empty: SlabList,
+
+   pub allocations: usize,
+   pub frees: usize,
+}
+
+unsafe impl Send for SlabCache {}
+
+impl SlabCache {
+   /// Creates an empty cache for objects of the given size and alignment.
+   pub const fn new(name: &'static str, size: usize, align: usize) -> Self {
+      let align = if align < size_of::<usize>() { size_of::<usize>() } else { align };
+      let object_size = (max_const(size, size_of::<usize>()) + align - 1) & !(align - 1);
+      let offset = (size_of::<Slab>() + align - 1) & !(align - 1);
+
+      // Aim for at least eight objects per slab.
+      let mut slab_size = SLAB_SIZE;
+      while slab_size < offset + object_size * 8 {
+         slab_size *= 2;
+      }
+
+      return SlabCache{
+         name,
+         object_size,
+         slab_size,
+         offset,
+         capacity: (slab_size - offset) / object_size,
+         partial: SlabList::new(),
+         full: SlabList::new(),
+         empty: SlabList::new(),
+         allocations: 0,
+         frees: 0,
+      };
+   }
+
+   /// Hands out a single object, taking a new slab from the heap if needed.
+   pub fn allocate(&mut self) -> Option<NonNull<u8>> {
+      unsafe {
+         if self.partial.empty() {
+            let slab = match self.empty.pop() {
+               Some(slab) => slab,
+               None => self.grow()?,
+            };
+
+            self.partial.push(slab);
+         }
+
+         let slab = self.partial.head;
+         let object = (*slab).free.pop()?;
+         (*slab).used += 1;
+
+         if (*slab).free.empty() {
+            self.partial.remove(slab);
+            self.full.push(slab);
+         }
+
+         self.allocations += 1;
+         return NonNull::new(object as *mut u8);
+      }
+   }
+
+   /// Returns an object to its slab.
+   ///
+   /// ## Safety
+   ///
+   /// `pointer` must have been handed out by this cache and not freed since.
+   pub unsafe fn deallocate(&mut self, pointer: NonNull<u8>) {
+      let slab = (pointer.as_ptr() as usize & !(self.slab_size - 1)) as *mut Slab;
+      let wasFull = (*slab).free.empty();
+
+      (*slab).free.push(pointer.as_ptr() as *mut usize);
+      (*slab).used -= 1;
+      self.frees += 1;
+
+      if wasFull {
+         self.full.remove(slab);
+         self.partial.push(slab);
+      }
+
+      if (*slab).used == 0 {
+         self.partial.remove(slab);
+
+         if self.empty.empty() {
+            self.empty.push(slab);
+         } else {
+            self.release(slab);
+         }
+      }
+   }
+
+   /// Returns every completely empty slab to the heap.
+   pub fn shrink(&mut self) {
+      unsafe {
+         while let Some(slab) = self.empty.pop() {
+            self.release(slab);
+         }
+      }
+   }
+
+   /// Returns this cache's statistics.
+   pub fn stats(&self) -> CacheStats {
+      let slabs = self.partial.length + self.full.length + self.empty.length;
+
+      return CacheStats{
+         name: self.name,
+         object_size: self.object_size,
+         slabs,
+         objects: slabs * self.capacity,
+         in_use: self.allocations - self.frees,
+         allocations: self.allocations,
+         frees: self.frees,
+      };
+   }
+
+   fn slab_layout(&self) -> Layout {
+      return unsafe { Layout::from_size_align_unchecked(self.slab_size, self.slab_size) };
+   }
+
+   unsafe fn grow(&mut self) -> Option<*mut Slab> {
+      let memory = allocate_or_grow(self.slab_layout()).ok()?;
+      let slab = memory.as_ptr() as *mut Slab;
+
+      slab.write(Slab{
+         next: ptr::null_mut(),
+         previous: ptr::null_mut(),
+         free: LinkedList::new(),
+         used: 0,
+      });
+
+      // Push in reverse so objects are handed out in address order.
+      for index in (0..self.capacity).rev() {
+         let object = memory.as_ptr().add(self.offset + index * self.object_size);
+         (*slab).free.push(object as *mut usize);
+      }
+
+      return Some(slab);
+   }
+
+   unsafe fn release(&mut self, slab: *mut Slab) {
+      release(NonNull::new_unchecked(slab as *mut u8), self.slab_layout());
+   }
+}
+
+/// The set of general-purpose caches, one per entry in [`SIZE_CLASSES`].
+pub struct SlabAllocator {
+   pub caches: [SlabCache; SIZE_CLASSES.len()],
+}
+
+impl SlabAllocator {
+   pub const fn new() -> Self {
+      const UNUSED: SlabCache = SlabCache::new("", 0, 0);
+      let mut caches = [UNUSED; SIZE_CLASSES.len()];
+
+      let mut index = 0;
+      while index < SIZE_CLASSES.len() {
+         let size = SIZE_CLASSES[index];
+         caches[index] = SlabCache::new(CLASS_NAMES[index], size, class_align(size));
+         index += 1;
+      }
+
+      return SlabAllocator{ caches };
+   }
+
+   /// Allocates from the cache for size class `class`.
+   pub fn allocate(&mut self, class: usize) -> Option<NonNull<u8>> {
+      return self.caches[class].allocate();
+   }
+
+   /// Frees an object belonging to size class `class`.
+   ///
+   /// ## Safety
+   ///
+   /// `pointer` must have been allocated from the same size class.
+   pub unsafe fn deallocate(&mut self, class: usize, pointer: NonNull<u8>) {
+      self.caches[class].deallocate(pointer);
+   }
+
+   /// Returns every completely empty slab to the heap.
+   pub fn shrink(&mut self) {
+      for cache in self.caches.iter_mut() {
+         cache.shrink();
+      }
+   }
+}
+
+/// A named cache dedicated to objects of type `T`.
+///
+/// ```ignore
+/// static TASKS: ObjectCache<TaskControlBlock> = ObjectCache::new("task");
+/// TASKS.register();
+/// let task = TASKS.allocate(TaskControlBlock::default()).unwrap();
+/// ```
+pub struct ObjectCache<T> {
+   pub cache: Mutex<SlabCache>,
+   marker: PhantomData<T>,
+}
+
+unsafe impl<T: Send> Sync for ObjectCache<T> {}
+
+impl<T> ObjectCache<T> {
+   pub const fn new(name: &'static str) -> Self {
+      return ObjectCache{
+         cache: Mutex::new(SlabCache::new(name, size_of::<T>(), align_of::<T>())),
+         marker: PhantomData,
+      };
+   }
+
+   /// Makes this cache visible to [`statistics`].
+   pub fn register(&'static self) {
+      let mut caches = NAMED_CACHES.lock();
+
+      if let Some(slot) = caches.iter_mut().find(|slot| slot.is_none()) {
+         *slot = Some(&self.cache);
+      } else {
+         log::warn!("too many named slab caches; {} will not be tracked", self.cache.lock().name);
+      }
+   }
+
+   /// Moves `value` into a freshly allocated object.
+   pub fn allocate(&self, value: T) -> Option<NonNull<T>> {
+      let pointer = self.cache.lock().allocate()?.cast::<T>();
+      unsafe { pointer.as_ptr().write(value) };
+      return Some(pointer);
+   }
+
+   /// Drops the object at `pointer` and returns its memory to the cache.
+   ///
+   /// ## Safety
+   ///
+   /// `pointer` must have come from [`allocate`](ObjectCache::allocate) on this cache.
+   pub unsafe fn deallocate(&self, pointer: NonNull<T>) {
+      ptr::drop_in_place(pointer.as_ptr());
+      self.cache.lock().deallocate(pointer.cast::<u8>());
+   }
+
+   /// Returns this cache's statistics.
+   pub fn stats(&self) -> CacheStats {
+      return self.cache.lock().stats();
+   }
+}
+
+const CLASS_NAMES: [&str; SIZE_CLASSES.len()] = [
+   "size-8", "size-16", "size-24", "size-32", "size-48", "size-64", "size-96", "size-128",
+   "size-192", "size-256", "size-384", "size-512", "size-768", "size-1024", "size-1536",
+   "size-2048",
+];
+
+const fn max_const(a: usize, b: usize) -> usize {
+   return if a > b { a } else { b };
+}
+
+// IMPORTS //
+
+use {
+   crate::{
+      alloc::{heap::{allocate_or_grow, release}, Layout},
+      array::linked_list::LinkedList,
+   },
+   core::{
+      marker::PhantomData,
+      mem::{align_of, size_of},
+      ptr::{self, NonNull},
+   },
+   spin::Mutex,
+};
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::test::{init_host_heap, Live, Random},
+      std::vec::Vec,
+   };
+
+   #[test]
+   fn size_classes_fit_their_layouts() {
+      let mut random = Random::new(0x51ab);
+
+      for _ in 0..8192 {
+         let layout = Layout::from_size_align(random.below(4096), 1 << random.below(13)).unwrap();
+
+         match size_class(layout) {
+            Some(class) => {
+               assert!(SIZE_CLASSES[class] >= layout.size());
+               assert_eq!(SIZE_CLASSES[class] % layout.align(), 0);
+
+               // No smaller class would have done.
+               assert!(SIZE_CLASSES[..class].iter().all(|&size| size < layout.size() || size % layout.align() != 0));
+            }
+            None => assert!(SIZE_CLASSES.iter().all(|&size| size < layout.size() || size % layout.align() != 0)),
+         }
+      }
+   }
+
+   #[test]
+   fn cache_recycles_slabs() {
+      init_host_heap();
+
+      let mut cache = SlabCache::new("test", 48, 16);
+      let count = cache.capacity * 3 + 1;
+      let layout = Layout::from_size_align(48, 16).unwrap();
+
+      let mut live: Vec<Live> = (0..count)
+         .map(|index| Live::new(cache.allocate().unwrap(), layout, index as u8))
+         .collect();
+
+      assert!(live.iter().all(|entry| entry.pointer.as_ptr() as usize % 16 == 0));
+      assert_eq!(cache.stats().slabs, 4);
+      assert_eq!(cache.stats().in_use, count);
+
+      for entry in live.drain(..) {
+         entry.check();
+         unsafe { cache.deallocate(entry.pointer) };
+      }
+
+      // Only a single empty slab is kept around.
+      assert_eq!(cache.stats().in_use, 0);
+      assert_eq!(cache.stats().slabs, 1);
+
+      cache.shrink();
+      assert_eq!(cache.stats().slabs, 0);
+   }
+}
@@ -0,0 +1,33 @@
+/// Runs `f` with interrupts disabled on this CPU, restoring the previous state afterwards.
+///
+/// Use this around locks that interrupt handlers also take. Host unit tests have no interrupts
+/// to disable, so there it just calls `f`.
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+   #[cfg(all(target_arch = "x86_64", not(test)))]
+   return x86_64::instructions::interrupts::without_interrupts(f);
+
+   #[cfg(any(not(target_arch = "x86_64"), test))]
+   return f();
+}
+
+/// Halts the CPU until the next interrupt, unless `ready` reports there is work to do.
+///
+/// `ready` is checked with interrupts disabled and the CPU re-enables them and halts in one
+/// step (`sti; hlt`), so an interrupt that makes work ready cannot slip in between.
+pub fn halt_unless(ready: impl FnOnce() -> bool) {
+   #[cfg(all(target_arch = "x86_64", not(test)))]
+   {
+      use x86_64::instructions::interrupts;
+
+      interrupts::disable();
+      match ready() {
+         true => interrupts::enable(),
+         false => interrupts::enable_and_hlt(),
+      }
+   }
+
+   #[cfg(any(not(target_arch = "x86_64"), test))]
+   if !ready() {
+      core::hint::spin_loop();
+   }
+}
@@ -253,35 +253,35 @@ impl<T, A: Allocator> RawArray<T, A> {
          return;
       }
 
-      let mut pointer = unsafe {
-         allocate_array::<T>(&mut self.allocator, new_capacity)
-            .expect("Allocation error")
-      };
+      let layout = Layout::from_type_array::<T>(new_capacity);
 
-      if self.capacity > 0 {
+      // Growing through `reallocate` lets the allocator extend the buffer in place.
+      let pointer = if self.capacity == 0 {
+         self.allocator.allocate(layout)
+      } else {
          unsafe {
-            ptr::copy(self.pointer.as_ptr() as *const T, pointer.as_ptr(), self.capacity);
-
-            self
-               .allocator
-               .deallocate_aligned(
-                  self.pointer.cast::<u8>().as_ptr(),
-                  Layout::from_size(self.capacity)
-               );
+            self.allocator.reallocate(
+               self.pointer.cast::<u8>().as_ptr(),
+               self.capacity * size_of::<T>(),
+               layout,
+            )
          }
-      }
+      };
 
-      self.pointer = pointer.cast::<T>();
+      self.pointer = pointer.expect("Allocation error").cast::<T>();
       self.capacity = new_capacity;
    }
 }
 
 impl<T, A: Allocator> Drop for RawArray<T, A> {
    fn drop(&mut self) {
-      if !self.pointer.as_ptr().is_null() {
+      if self.capacity > 0 && size_of::<T>() != 0 {
          unsafe {
             self.allocator
-               .deallocate_aligned(self.pointer.cast::<u8>().as_ptr(), Layout::from_size(self.capacity));
+               .deallocate(
+                  self.pointer.cast::<u8>().as_ptr(),
+                  Layout::from_type_array::<T>(self.capacity),
+               );
          }
       }
    }
@@ -576,7 +576,7 @@ pub use self::linked_list::LinkedList;
 // IMPORTS //
 
 use {
-   crate::alloc::{allocate_array, Allocator, GlobalAllocator, Layout},
+   crate::alloc::{Allocator, GlobalAllocator, Layout},
    core::{
       borrow::Borrow,
       iter::{FromIterator, IntoIterator},
@@ -587,3 +587,109 @@ use {
       slice,
    },
 };
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::test::{init_host_heap, Random},
+      std::{rc::Rc, vec::Vec},
+   };
+
+   #[test]
+   fn array_matches_vec() {
+      init_host_heap();
+
+      let mut random = Random::new(0xa7a7);
+      let mut array: Array<u64> = Array::new();
+      let mut model: Vec<u64> = Vec::new();
+
+      for _ in 0..10_000 {
+         match random.below(8) {
+            0..=3 => {
+               let value = random.next();
+               array.push(value);
+               model.push(value);
+            }
+            4 | 5 => assert_eq!(array.pop(), model.pop()),
+            6 => {
+               let size = random.below(model.len() * 2 + 8);
+               let value = random.next();
+               array.resize(size, value);
+               model.resize(size, value);
+            }
+            _ => {
+               let capacity = random.below(1024);
+               array.reserve(capacity);
+               assert!(array.capacity() >= capacity);
+            }
+         }
+
+         assert_eq!(array.len(), model.len());
+         assert!(array.capacity() >= array.len());
+      }
+
+      assert_eq!(&array[..], &model[..]);
+      array.clear();
+      assert!(array.is_empty());
+   }
+
+   #[test]
+   fn array_drops_every_element_once() {
+      init_host_heap();
+
+      let value = Rc::new(());
+      let mut array = Array::new();
+
+      array.extend((0..100).map(|_| value.clone()));
+      assert_eq!(Rc::strong_count(&value), 101);
+
+      array.resize(40, value.clone());
+      assert_eq!(Rc::strong_count(&value), 41);
+
+      drop(array.pop());
+      assert_eq!(Rc::strong_count(&value), 40);
+
+      drop(array);
+      assert_eq!(Rc::strong_count(&value), 1);
+   }
+
+   #[test]
+   fn zero_sized_elements_never_allocate() {
+      let mut array = Array::new();
+
+      for _ in 0..1000 {
+         array.push(());
+      }
+
+      assert_eq!(array.len(), 1000);
+      assert_eq!(array.capacity(), !0);
+      assert_eq!(array.pop(), Some(()));
+   }
+
+   #[test]
+   fn small_array_spills_to_the_heap() {
+      init_host_heap();
+
+      let value = Rc::new(());
+      let mut array: SmallArray4<Rc<()>> = SmallArray::new();
+
+      for _ in 0..4 {
+         array.push(value.clone());
+      }
+      assert_eq!(array.capacity(), 4);
+
+      for _ in 0..12 {
+         array.push(value.clone());
+      }
+      assert_eq!(array.len(), 16);
+      assert!(array.capacity() >= 16);
+      assert_eq!(Rc::strong_count(&value), 17);
+
+      array.resize(2, value.clone());
+      assert_eq!(Rc::strong_count(&value), 3);
+
+      drop(array);
+      assert_eq!(Rc::strong_count(&value), 1);
+   }
+}
@@ -152,3 +152,47 @@ use {
       ptr,
    },
 };
+
+#[cfg(test)]
+mod tests {
+   use {super::*, std::vec::Vec};
+
+   #[test]
+   fn push_and_pop_are_last_in_first_out() {
+      let mut nodes = [0usize; 8];
+      let mut list = LinkedList::new();
+      assert!(list.empty());
+
+      for node in nodes.iter_mut() {
+         unsafe { list.push(node) };
+      }
+
+      let popped: Vec<*mut usize> = core::iter::from_fn(|| list.pop()).collect();
+      let expected: Vec<*mut usize> = nodes.iter_mut().rev().map(|node| node as *mut usize).collect();
+
+      assert_eq!(popped, expected);
+      assert!(list.empty());
+   }
+
+   #[test]
+   fn iterator_mut_unlinks_nodes() {
+      let mut nodes = [0usize; 8];
+      let addresses: Vec<*mut usize> = nodes.iter_mut().map(|node| node as *mut usize).collect();
+
+      let mut list = LinkedList::new();
+      for &node in addresses.iter().rev() {
+         unsafe { list.push(node) };
+      }
+
+      // Unlink every odd node while walking the list.
+      for (index, node) in list.iterator_mut().enumerate() {
+         if index % 2 == 1 {
+            node.pop();
+         }
+      }
+
+      let remaining: Vec<*mut usize> = list.iterator().collect();
+      let expected: Vec<*mut usize> = addresses.iter().copied().step_by(2).collect();
+      assert_eq!(remaining, expected);
+   }
+}
@@ -3,13 +3,11 @@
 #![allow(nonstandard_style)]
 #![warn(missing_docs, missing_abi)]
 #![feature(coerce_unsized)]
-#![feature(custom_test_frameworks)]
 #![feature(decl_macro)]
 #![feature(naked_functions)]
 #![feature(unsize)]
-#![reexport_test_harness_main="test_main"]
-#![test_runner(crate::test::test_runner)]
-#![no_std]
+// Unit tests build against the host's `std` and run under the standard test harness.
+#![cfg_attr(not(test), no_std)]
 
 // MODULES //
 
@@ -73,6 +71,9 @@ pub mod tasks;
 /// Facilities for interacting with standard input/output.
 pub mod terminal;
 
+/// The monotonic kernel clock: timer ticks, uptime and high-resolution timestamps.
+pub mod time;
+
 /// Testing utilities.
 pub mod test;
 
@@ -70,3 +70,40 @@ impl PowersOf2 for usize {
 // IMPORTS //
 
 use core::{mem::size_of, num::Wrapping};
+
+#[cfg(test)]
+mod tests {
+   use {super::*, crate::test::Random};
+
+   #[test]
+   fn power_of_two_matches_core() {
+      let mut random = Random::new(0x5eed);
+
+      for value in (0..1024).chain((0..4096).map(|_| random.magnitude())) {
+         assert_eq!(value.powerOf2(), value.is_power_of_two(), "{}", value);
+      }
+   }
+
+   #[test]
+   fn next_power_of_two_matches_core() {
+      let mut random = Random::new(0x5eed);
+      assert_eq!(0usize.nextPowerOf2(), 1);
+
+      for _ in 0..4096 {
+         let value = random.magnitude() >> 1;
+         assert_eq!(value.nextPowerOf2(), value.next_power_of_two(), "{}", value);
+      }
+   }
+
+   #[test]
+   fn log2_rounds_down() {
+      let mut random = Random::new(0x5eed);
+      assert_eq!(0usize.log2(), 0);
+
+      for _ in 0..4096 {
+         let value = random.magnitude().max(1);
+         assert_eq!(value.log2() as u32, value.ilog2(), "{}", value);
+         assert_eq!(previous_po2(value), 1 << value.ilog2(), "{}", value);
+      }
+   }
+}
@@ -120,14 +120,13 @@ impl<T: ?Sized, A: Allocator> BorrowMut<T> for Unique<T, A> {
 
 impl<T: ?Sized, A: Allocator> Drop for Unique<T, A> {
    fn drop(&mut self) {
-      let size = unsafe { size_of_val(self.pointer.as_ref()) };
-      let layout = Layout::from_size(size);
+      let layout = unsafe { Layout::for_value(self.pointer.as_ref()) };
 
       unsafe {
          drop_in_place(self.pointer.as_ptr());
          self
             .allocator
-            .deallocate(self.pointer.cast().as_ptr(), layout);
+            .deallocate_aligned(self.pointer.cast().as_ptr(), layout);
       }
    }
 }
@@ -147,7 +146,6 @@ use {
       borrow::{Borrow, BorrowMut},
       convert::{AsMut, AsRef},
       marker::{PhantomData, Unsize},
-      mem::size_of_val,
       ops::{CoerceUnsized, Deref, DerefMut},
       pin::Pin,
       ptr::{drop_in_place, write, NonNull},
@@ -314,3 +314,50 @@ use {
       ptr, str,
    },
 };
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::test::{init_host_heap, Random},
+   };
+
+   #[test]
+   fn push_matches_std() {
+      init_host_heap();
+
+      let mut random = Random::new(0x57a1);
+      let mut string = String::new();
+      let mut model = std::string::String::new();
+
+      for _ in 0..4096 {
+         let c = match random.below(4) {
+            0 => char::from(b' ' + random.below(95) as u8),
+            _ => char::from_u32(random.below(0x11_0000) as u32).unwrap_or('\u{FFFD}'),
+         };
+
+         string.push(c);
+         model.push(c);
+      }
+
+      assert_eq!(string.as_str(), model.as_str());
+      assert_eq!(string.chars().count(), 4096);
+   }
+
+   #[test]
+   fn from_and_conversions() {
+      init_host_heap();
+
+      let string = String::from("hello, wörld");
+      assert_eq!(string, "hello, wörld");
+      assert_eq!(string.len(), "hello, wörld".len());
+
+      let mut bytes: Array<u8> = Array::new();
+      bytes.extend(b"\xff\xfe".iter());
+      assert!(String::try_from(bytes).is_err());
+
+      let mut wide = StringWide::from("añ😀");
+      wide.push('!');
+      assert_eq!(&wide[..], &"añ😀!".encode_utf16().collect::<std::vec::Vec<u16>>()[..]);
+   }
+}
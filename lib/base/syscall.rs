@@ -1,35 +1,97 @@
+/// number of the system call `read`
+pub const SYSNO_READ: usize = 0;
+
 /// number of the system call `write`
 pub const SYSNO_WRITE: usize = 1;
 
 /// number of the system call `close`
 pub const SYSNO_CLOSE: usize = 3;
 
+/// number of the system call `fstat`
+pub const SYSNO_FSTAT: usize = 5;
+
+/// number of the system call `mmap`
+pub const SYSNO_MMAP: usize = 9;
+
+/// number of the system call `munmap`
+pub const SYSNO_MUNMAP: usize = 11;
+
+/// number of the system call `brk`
+pub const SYSNO_BRK: usize = 12;
+
+/// number of the system call `ioctl`
 pub const SYSNO_IOCTL: usize = 16;
 
+/// number of the system call `writev`
 pub const SYSNO_WRITEV: usize = 20;
 
 /// number of the system call `exit`
 pub const SYSNO_EXIT: usize = 60;
 
+/// set architecture-specific thread state
 pub const SYSNO_ARCH_PRCTL: usize = 158;
 
 /// set pointer to thread ID
 pub const SYSNO_SET_TID_ADDRESS: usize = 218;
 
+/// number of the system call `clock_gettime`
+pub const SYSNO_CLOCK_GETTIME: usize = 228;
+
 /// exit all threads in a process
 pub const SYSNO_EXIT_GROUP: usize = 231;
 
+/// number of the system call `openat`
+pub const SYSNO_OPENAT: usize = 257;
+
 /// total number of system calls
 pub const NUM_SYSCALLS: usize = 400;
 
-pub fn make_syscall(call: usize) {}
+/// Makes system call `call` with no arguments, returning the raw result.
+pub fn make_syscall(call: usize) -> u64 {
+   return syscall0(call as u64);
+}
+
+// ERROR NUMBERS //
+
+/// Operation not permitted.
+pub const EPERM: i64 = 1;
+
+/// No such file or directory.
+pub const ENOENT: i64 = 2;
+
+/// Bad file descriptor.
+pub const EBADF: i64 = 9;
+
+/// Out of memory.
+pub const ENOMEM: i64 = 12;
+
+/// Bad address.
+pub const EFAULT: i64 = 14;
+
+/// No such device.
+pub const ENODEV: i64 = 19;
+
+/// Invalid argument.
+pub const EINVAL: i64 = 22;
+
+/// Not a terminal.
+pub const ENOTTY: i64 = 25;
+
+/// Function not implemented.
+pub const ENOSYS: i64 = 38;
+
+/// A system call implementation.
+///
+/// Takes the six argument registers in order (`rdi`, `rsi`, `rdx`, `r10`, `r8`, `r9`) and
+/// returns the result, or a negated error number, in `rax`.
+pub type SyscallHandler = extern "C" fn(u64, u64, u64, u64, u64, u64) -> i64;
 
 #[naked]
 #[no_mangle]
 #[allow(unused_assignments)]
 pub unsafe extern "C" fn sys_invalid() {
    asm!("mov rdi, rax",
-   "call {}",
+   "jmp {}",
    sym invalid_syscall,
    options(noreturn),
    );
@@ -41,14 +103,23 @@ pub struct SyscallTable{
    pub handle: [*const usize; NUM_SYSCALLS],
 }
 
+// Handlers are plain code pointers, never written after the table is built.
+unsafe impl Sync for SyscallTable {}
+
 impl SyscallTable {
    pub const fn new() -> Self {
-      let mut table = SyscallTable{
+      let table = SyscallTable{
          handle: [sys_invalid as *const _; NUM_SYSCALLS],
       };
 
       return table;
    }
+
+   /// Routes system call `number` to `handler`.
+   pub const fn with(mut self, number: usize, handler: SyscallHandler) -> Self {
+      self.handle[number] = handler as *const _;
+      return self;
+   }
 }
 
 // X86 SYSTEM CALLS //
@@ -57,23 +128,23 @@ impl SyscallTable {
 #[macro_export]
 macro_rules! syscall {
 	($arg0:expr) => {
-		$crate::arch::x86_64::syscall::syscall0($arg0 as u64)
+		$crate::syscall::syscall0($arg0 as u64)
 	};
 
 	($arg0:expr, $arg1:expr) => {
-		$crate::arch::x86_64::syscall::syscall1($arg0 as u64, $arg1 as u64)
+		$crate::syscall::syscall1($arg0 as u64, $arg1 as u64)
 	};
 
 	($arg0:expr, $arg1:expr, $arg2:expr) => {
-		$crate::arch::x86_64::syscall::syscall2($arg0 as u64, $arg1 as u64, $arg2 as u64)
+		$crate::syscall::syscall2($arg0 as u64, $arg1 as u64, $arg2 as u64)
 	};
 
 	($arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr) => {
-		$crate::arch::x86_64::syscall::syscall3($arg0 as u64, $arg1 as u64, $arg2 as u64, $arg3 as u64)
+		$crate::syscall::syscall3($arg0 as u64, $arg1 as u64, $arg2 as u64, $arg3 as u64)
 	};
 
 	($arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr) => {
-		$crate::arch::x86_64::syscall::syscall4(
+		$crate::syscall::syscall4(
 			$arg0 as u64,
 			$arg1 as u64,
 			$arg2 as u64,
@@ -83,7 +154,7 @@ macro_rules! syscall {
 	};
 
 	($arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr, $arg5:expr) => {
-		$crate::arch::x86_64::syscall::syscall5(
+		$crate::syscall::syscall5(
 			$arg0 as u64,
 			$arg1 as u64,
 			$arg2 as u64,
@@ -94,19 +165,7 @@ macro_rules! syscall {
 	};
 
 	($arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr, $arg5:expr, $arg6:expr) => {
-		$crate::arch::x86_64::syscall::syscall6(
-			$arg0 as u64,
-			$arg1 as u64,
-			$arg2 as u64,
-			$arg3 as u64,
-			$arg4 as u64,
-			$arg5 as u64,
-			$arg6 as u64,
-		)
-	};
-
-	($arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr, $arg5:expr, $arg6:expr, $arg7:expr) => {
-		$crate::arch::x86_64::syscall::syscall7(
+		$crate::syscall::syscall6(
 			$arg0 as u64,
 			$arg1 as u64,
 			$arg2 as u64,
@@ -114,7 +173,6 @@ macro_rules! syscall {
 			$arg4 as u64,
 			$arg5 as u64,
 			$arg6 as u64,
-			$arg7 as u64,
 		)
 	};
 }
@@ -254,13 +312,22 @@ pub fn syscall6(
 }
 
 
+/// How many invalid system calls are logged before the rest are dropped silently, so a
+/// program probing for system calls in a loop cannot flood the terminal.
+const INVALID_SYSCALL_WARNINGS: u64 = 16;
+
+/// Reached through [`sys_invalid`] for unimplemented system calls, which fail with `ENOSYS`.
 #[no_mangle]
-pub extern "C" fn invalid_syscall(sys_no: u64) -> ! {
-   log::error!("Invalid syscall: {}", sys_no);
-   //crate::tasks::do_exit(); // TODO: uncomment when proper scheduling is added
-   loop{
-      unsafe{ asm!("hlt", options(nomem, nostack)); }
+pub extern "C" fn invalid_syscall(sys_no: u64) -> i64 {
+   static WARNED: AtomicU64 = AtomicU64::new(0);
+
+   match WARNED.fetch_add(1, Ordering::Relaxed) {
+      count if count < INVALID_SYSCALL_WARNINGS => log::warn!("Invalid syscall: {}", sys_no),
+      INVALID_SYSCALL_WARNINGS => log::warn!("Invalid syscall: {}; further ones are not logged", sys_no),
+      _ => {}
    }
+
+   return -ENOSYS;
 }
 
 
@@ -328,7 +395,8 @@ pub fn pause() {
    }
 }
 
-#[no_mangle]
+// Host test builds link against libc, which already exports `shutdown`.
+#[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn shutdown() -> ! {
    // TODO: implement a proper shutdown sequence.
    crate::println!("Shutting down...");
@@ -337,7 +405,10 @@ pub extern "C" fn shutdown() -> ! {
 
 // IMPORTS //
 
-use core::arch::asm;
+use core::{
+   arch::asm,
+   sync::atomic::{AtomicU64, Ordering},
+};
 
 // MODULES //
 
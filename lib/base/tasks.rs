@@ -1,111 +1,194 @@
-/// Polls all pending tasks on global executor and remove completed tasks.
+/// Polls every woken task on the global executor until none are left ready, then returns.
 pub fn run_tasks() {
-   executor::DEFAULT_EXECUTOR.lock().run();
+   executor::DEFAULT_EXECUTOR.run_ready();
+}
+
+/// Runs the global executor forever, halting the CPU whenever no task is ready.
+pub fn run() -> ! {
+   executor::DEFAULT_EXECUTOR.run();
 }
 
 /// Adds task for a future to the global executor queue.
-pub fn add_future<T>(future: impl Future<Output = T> + 'static + Send)
+///
+/// Await the returned handle for the future's output, or drop it to let the task run detached.
+pub fn add_future<T>(future: impl Future<Output = T> + 'static + Send) -> JoinHandle<T>
 where
    T: Send + 'static, {
-   executor::DEFAULT_EXECUTOR.lock().add_async(Box::pin(future));
+   return spawn(None, future, false);
 }
 
-/// Drops completed tasks and checks if any uncompleted tasks remain.
+/// Like [`add_future`], giving the task a name that shows up in [`task_list`].
+pub fn add_named_future<T>(name: &str, future: impl Future<Output = T> + 'static + Send) -> JoinHandle<T>
+where
+   T: Send + 'static, {
+   return spawn(Some(String::from(name)), future, false);
+}
+
+/// Checks if every task on the global executor has completed.
 pub fn completed() -> bool {
-   let mut executor = executor::DEFAULT_EXECUTOR.lock();
-   executor.collect();
-   return executor.tasks.is_empty();
+   return executor::DEFAULT_EXECUTOR.is_empty();
 }
 
 /// Adds task for a future to the executor queue and immediately polls it.
-pub fn poll_now<T>(future: impl Future<Output = T> + 'static + Send)
+pub fn poll_now<T>(future: impl Future<Output = T> + 'static + Send) -> JoinHandle<T>
 where
    T: Send + 'static, {
-   executor::DEFAULT_EXECUTOR.lock().poll_now(Box::pin(future));
+   return spawn(None, future, true);
 }
 
-pub type TaskList = VecDeque<Box<dyn Pendable + core::marker::Send + core::marker::Sync>>;
+/// The task being polled, if called from inside one.
+pub fn current_task() -> Option<TaskId> {
+   return executor::current();
+}
 
-/// Container for [`Future`] and [`Future`]'s state, like [`Task::completed`].
-///
-/// Task is our unit of execution and holds a future to wait on.
-pub struct Task<T> {
-   pub future: Spinlock<Pin<Box<dyn Future<Output = T> + Send + 'static>>>,
-   pub completed: AtomicBool,
+/// A snapshot of every task that has not completed yet, in spawn order.
+pub fn task_list() -> Vec<TaskInfo> {
+   return executor::DEFAULT_EXECUTOR.task_list();
 }
 
-pub trait Pendable {
-   /// Updates future progress via calling [`Future::poll()`].
-   ///
-   /// Shall contain future's status internally and corresponding to [`Future::poll`] state.
-   fn update(&self);
+fn spawn<T>(name: Option<String>, future: impl Future<Output = T> + 'static + Send, now: bool) -> JoinHandle<T>
+where
+   T: Send + 'static, {
+   let (harness, state) = join::harness(future);
+   let task = Task::new(name, Box::pin(harness));
+   let id = task.id;
+
+   match now {
+      true => executor::DEFAULT_EXECUTOR.poll_now(task),
+      false => executor::DEFAULT_EXECUTOR.spawn(task),
+   }
 
-   /// Returns `true` if the state is corresponding to [`core::task::Poll::Ready`] otherwise - false.
-   ///
-   /// Needed to determine, which task we shall drop.
-   fn is_done(&self) -> bool;
+   return join::handle(id, state);
 }
 
-impl<T> Wake for Task<T> {
-   fn wake(self: Arc<Self>) {
-      self.update();
+/// Identifies a task for as long as the kernel runs; IDs are never reused.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(u64);
+
+impl TaskId {
+   fn new() -> Self {
+      static NEXT: AtomicU64 = AtomicU64::new(1);
+      return TaskId(NEXT.fetch_add(1, Ordering::Relaxed));
    }
 
-   fn wake_by_ref(self: &Arc<Self>) {
-      self.update();
+   /// The raw ID.
+   pub fn as_u64(self) -> u64 {
+      return self.0;
    }
 }
 
-impl<T> ArcWake for Task<T> {
-   fn wake(self: Arc<Self>) {
-      self.update();
+impl Display for TaskId {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return write!(f, "#{}", self.0);
    }
+}
 
-   fn wake_by_ref(arc_self: &Arc<Self>) {
-      arc_self.update();
-   }
+/// Container for a spawned [`Future`], our unit of execution.
+pub struct Task {
+   /// The task's ID, which is all its [`Waker`](core::task::Waker)s carry.
+   pub id: TaskId,
+
+   /// The name given at spawn, if any.
+   pub name: Option<String>,
+
+   future: Spinlock<Pin<Box<dyn Future<Output = ()> + Send + 'static>>>,
+
+   /// Number of times the future has been polled.
+   polls: AtomicU64,
+
+   /// Total time spent polling the future, in nanoseconds.
+   busy: AtomicU64,
 }
 
-impl<T> Pendable for Arc<Task<T>> {
-   fn update(&self) {
-      let mut future = self.future.lock();
-      let waker = waker_ref(self);
-      let context = &mut Context::from_waker(&waker);
-      self.completed.store(
-         !matches!(future.as_mut().poll(context), Poll::Pending),
-         Ordering::Relaxed,
-      );
+impl Task {
+   /// Wraps `future` in a task with a fresh ID.
+   pub fn new(name: Option<String>, future: Pin<Box<dyn Future<Output = ()> + Send + 'static>>) -> Self {
+      return Task{
+         id: TaskId::new(),
+         name,
+         future: Spinlock::new(future),
+         polls: AtomicU64::new(0),
+         busy: AtomicU64::new(0),
+      };
+   }
+
+   /// Polls the future once with `context`, returning `true` once it has completed.
+   pub fn poll(&self, context: &mut Context<'_>) -> bool {
+      let start = time::uptime();
+      let done = self.future.lock().as_mut().poll(context).is_ready();
+      let spent = time::uptime().saturating_sub(start);
+
+      self.polls.fetch_add(1, Ordering::Relaxed);
+      self.busy.fetch_add(spent.as_nanos() as u64, Ordering::Relaxed);
+      return done;
    }
 
-   fn is_done(&self) -> bool {
-      return self.completed.load(Ordering::Relaxed);
+   /// A snapshot of the task's statistics.
+   pub fn info(&self) -> TaskInfo {
+      return TaskInfo{
+         id: self.id,
+         name: self.name.clone(),
+         polls: self.polls.load(Ordering::Relaxed),
+         busy: Duration::from_nanos(self.busy.load(Ordering::Relaxed)),
+      };
    }
 }
 
+/// What [`task_list`] reports about a task.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaskInfo {
+   /// The task's ID.
+   pub id: TaskId,
+
+   /// The name given at spawn, if any.
+   pub name: Option<String>,
+
+   /// Number of times the task has been polled.
+   pub polls: u64,
+
+   /// Total time spent polling the task.
+   pub busy: Duration,
+}
+
 // MODULES //
 
 pub mod executor;
+
+/// Awaiting and aborting spawned tasks.
+pub mod join;
+
 pub mod keyboard;
 
+/// Channels and locks that suspend the waiting task instead of spinning.
+pub mod sync;
+
+/// Timers for sleeping, timeouts and intervals, driven by the timer interrupt.
+pub mod timer;
+
 // IMPORTS //
 
 use {
+   crate::time,
    core::{
+      fmt::{self, Display},
       future::Future,
       pin::Pin,
-      sync::atomic::{AtomicBool, Ordering},
-      task::{Context, Poll},
+      sync::atomic::{AtomicU64, Ordering},
+      task::Context,
+      time::Duration,
    },
-   futures_util::task::{ArcWake, waker_ref},
    spinning_top::Spinlock,
    std_alloc::{
       boxed::Box,
-      collections::VecDeque,
-      sync::Arc,
-      task::Wake
+      string::String,
+      vec::Vec,
    },
 };
 
 // EXPORTS //
 
-pub use self::executor::Executor;
+pub use self::{
+   executor::Executor,
+   join::{JoinError, JoinHandle},
+   timer::{interval, sleep, sleep_until, timeout, Elapsed, Interval, Sleep, Timeout},
+};
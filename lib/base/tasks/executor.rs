@@ -1,94 +1,330 @@
 /// The default global task executor.
-pub static DEFAULT_EXECUTOR: Spinlock<Executor> = Spinlock::new(Executor::new());
+///
+/// Wakers only carry a task ID, so this is the one executor every waker reports to.
+pub static DEFAULT_EXECUTOR: Executor = Executor::new();
+
+/// Number of wake-ups that can be queued before the executor falls back to polling every task.
+pub const READY_QUEUE_SIZE: usize = 1024;
+
+/// IDs of tasks woken since they were last polled; may hold duplicates.
+static READY_QUEUE: OnceCell<ArrayQueue<TaskId>> = OnceCell::uninit();
+
+/// Set when a wake-up did not fit in [`READY_QUEUE`], so the next run polls every task.
+static READY_OVERFLOW: AtomicBool = AtomicBool::new(false);
+
+/// What [`Executor::run`] does when no task is ready, if not halting.
+static IDLE_HANDLER: OnceCell<IdleHandler> = OnceCell::uninit();
+
+/// Raw ID of the task being polled, or zero outside of any task.
+static CURRENT: AtomicU64 = AtomicU64::new(0);
 
 /// Our executor type.
+///
+/// Tasks are only polled after something wakes them. Waking pushes the task's ID onto a
+/// lock-free queue and never polls or allocates, so wakers may be fired from interrupt handlers.
 pub struct Executor {
-   /// A collection of tasks to execute.
-   pub tasks: TaskList,
+   /// Every task that has not completed yet.
+   tasks: Spinlock<BTreeMap<TaskId, Arc<Task>>>,
 }
 
 impl Executor {
-   pub const fn new() -> Self {
+   const fn new() -> Self {
       return Executor{
-         tasks: VecDeque::new(),
+         tasks: Spinlock::new(BTreeMap::new()),
       };
    }
 
-   pub fn add_task<T>(&mut self, task: Arc<Task<T>>)
-   where
-      T: Send + 'static, {
-      self.tasks.push_back(Box::new(task));
+   /// Adds `task`, which is polled on the next run.
+   pub fn spawn(&self, task: Task) {
+      initialise_ready_queue();
+
+      let id = task.id;
+      self.tasks.lock().insert(id, Arc::new(task));
+      wake_task(id);
    }
 
-   /// Add a [`Task<T>`](crate::task::Task) to the executor queue and immediately poll it.
-   pub fn poll_now<T>(&mut self, future: Pin<Box<dyn Future<Output = T> + 'static + Send>>)
-   where
-      T: Send + 'static {
-      let task = Arc::new(Task{
-         future: Spinlock::new(future),
-         completed: AtomicBool::new(false),
-      });
+   /// Adds `task` and immediately polls it.
+   pub fn poll_now(&self, task: Task) {
+      initialise_ready_queue();
 
-      task.update();
-      self.add_task(task);
+      let id = task.id;
+      self.tasks.lock().insert(id, Arc::new(task));
+      self.poll(id);
    }
 
-   /// Add task for a future to the executor queue.
-   pub fn add_async<T>(&mut self, future: Pin<Box<dyn Future<Output = T> + 'static + Send>>)
-   where
-      T: Send + 'static {
-      let task = Arc::new(Task{
-         future: Spinlock::new(future),
-         completed: AtomicBool::new(false),
-      });
+   /// Polls woken tasks until none are ready.
+   pub fn run_ready(&self) {
+      let queue = match READY_QUEUE.try_get() {
+         Ok(queue) => queue,
+         Err(_) => return,
+      };
+
+      loop {
+         if READY_OVERFLOW.swap(false, Ordering::AcqRel) {
+            let ids: Vec<TaskId> = self.tasks.lock().keys().copied().collect();
+            for id in ids {
+               self.poll(id);
+            }
+         }
 
-      self.add_task(task);
+         match queue.pop() {
+            Some(id) => self.poll(id),
+            None if READY_OVERFLOW.load(Ordering::Acquire) => continue,
+            None => return,
+         }
+      }
    }
 
-   /// Polls all pending tasks on global executor and remove completed tasks.
-   ///
-   /// You may notice, that when all tasks will done, we keep them, although this is objectively
-   /// useless. I think, finding our each completed task from [`Wake::wake_by_ref()`] at [`Executor::tasks`]
-   /// and drop it is too expensive, so we just mark them via [`Task<T>::completed`] field as done.
-   /// When all tasks will done and we add new task and run them, old completed tasks will be removed
-   /// from [`Executor::tasks`] or [`Executor::collect()`].
-   pub fn run(&mut self) {
-      for _ in 0..self.tasks.len() {
-         let task = self.tasks.pop_front().unwrap();
-         if !task.is_done() {
-            task.update();
-            self.tasks.push_back(task);
+   /// Runs tasks forever, halting the CPU until the next interrupt whenever none are ready,
+   /// or calling the [idle handler](set_idle_handler) if one is registered.
+   pub fn run(&self) -> ! {
+      let ready = || {
+         READY_OVERFLOW.load(Ordering::Acquire)
+            || READY_QUEUE.try_get().map_or(false, |queue| !queue.is_empty())
+      };
+
+      loop {
+         self.run_ready();
+
+         // Checked with interrupts off, so a wake-up between the check and the halt still ends it.
+         match IDLE_HANDLER.try_get() {
+            Ok(idle) => idle(&ready),
+            Err(_) => halt_unless(ready),
          }
       }
    }
 
-   /// Removes completed task from [`Executor::tasks`].
+   /// Number of tasks that have not completed.
+   pub fn len(&self) -> usize {
+      return self.tasks.lock().len();
+   }
+
+   /// Whether every task has completed.
+   pub fn is_empty(&self) -> bool {
+      return self.tasks.lock().is_empty();
+   }
+
+   /// A snapshot of every task that has not completed, in spawn order.
+   pub fn task_list(&self) -> Vec<TaskInfo> {
+      let tasks: Vec<Arc<Task>> = self.tasks.lock().values().cloned().collect();
+      return tasks.iter().map(|task| task.info()).collect();
+   }
+
+   /// Polls the task `id` once, forgetting it if it completes.
    ///
-   /// As you may also notice, same as [`Executor::run()`], but don't poll tasks.
-   /// Only drop completed tasks.
-   pub fn collect(&mut self) {
-      for _ in 0..self.tasks.len() {
-         let task = self.tasks.pop_front().unwrap();
-         if !task.is_done() {
-            self.tasks.push_back(task);
-         }
+   /// The task list is unlocked while polling, so tasks may spawn others.
+   fn poll(&self, id: TaskId) {
+      // Stale IDs of completed tasks can still be queued.
+      let task = match self.tasks.lock().get(&id) {
+         Some(task) => task.clone(),
+         None => return,
+      };
+
+      // If the poll unwinds, forget the task so its join handle reports the panic.
+      let unwinding = Forget{ executor: self, id };
+      let previous = CURRENT.swap(id.as_u64(), Ordering::Relaxed);
+
+      let waker = task_waker(id);
+      let done = task.poll(&mut Context::from_waker(&waker));
+
+      CURRENT.store(previous, Ordering::Relaxed);
+      mem::forget(unwinding);
+
+      if done {
+         self.tasks.lock().remove(&id);
       }
    }
 }
 
+/// Removes a task from its executor when dropped.
+struct Forget<'a> {
+   executor: &'a Executor,
+   id: TaskId,
+}
+
+impl Drop for Forget<'_> {
+   fn drop(&mut self) {
+      CURRENT.store(0, Ordering::Relaxed);
+      self.executor.tasks.lock().remove(&self.id);
+   }
+}
+
+/// The task being polled, if called from inside one.
+pub fn current() -> Option<TaskId> {
+   return match CURRENT.load(Ordering::Relaxed) {
+      0 => None,
+      id => Some(TaskId(id)),
+   };
+}
+
+/// Creates the ready queue; done on the first spawn, since interrupt handlers cannot allocate.
+fn initialise_ready_queue() {
+   let _ = READY_QUEUE.try_init_once(|| ArrayQueue::new(READY_QUEUE_SIZE));
+}
+
+/// Queues task `id` to be polled by [`DEFAULT_EXECUTOR`].
+///
+/// Never blocks or allocates, so it is safe from interrupt handlers.
+pub fn wake_task(id: TaskId) {
+   if let Ok(queue) = READY_QUEUE.try_get() {
+      if queue.push(id).is_err() {
+         READY_OVERFLOW.store(true, Ordering::Release);
+      }
+   }
+}
+
+/// Called by [`Executor::run`] when no task is ready, with a check for whether one has been
+/// woken since. Returns once a task may be ready.
+pub type IdleHandler = fn(ready: &dyn Fn() -> bool);
+
+/// Registers what [`Executor::run`] does instead of halting when no task is ready, so the CPU
+/// can be given to other threads. Only the first handler registered is kept.
+pub fn set_idle_handler(handler: IdleHandler) {
+   let _ = IDLE_HANDLER.try_init_once(|| handler);
+}
+
+/// A waker for task `id`; cloning and dropping it are free.
+fn task_waker(id: TaskId) -> Waker {
+   return unsafe { Waker::from_raw(raw_waker(id.as_u64() as *const ())) };
+}
+
+fn raw_waker(data: *const ()) -> RawWaker {
+   return RawWaker::new(data, &WAKER_VTABLE);
+}
+
+static WAKER_VTABLE: RawWakerVTable = RawWakerVTable::new(clone_waker, wake_waker, wake_waker, drop_waker);
+
+unsafe fn clone_waker(data: *const ()) -> RawWaker {
+   return raw_waker(data);
+}
+
+unsafe fn wake_waker(data: *const ()) {
+   wake_task(TaskId(data as u64));
+}
+
+unsafe fn drop_waker(_: *const ()) {}
+
 // IMPORTS //
 
 use {
-   super::{Pendable, Task, TaskList},
+   super::{Task, TaskId, TaskInfo},
+   crate::arch::halt_unless,
+   conquer_once::spin::OnceCell,
    core::{
-      future::Future,
-      pin::Pin,
-      sync::atomic::AtomicBool,
+      mem,
+      sync::atomic::{AtomicBool, AtomicU64, Ordering},
+      task::{Context, RawWaker, RawWakerVTable, Waker},
    },
+   crossbeam_queue::ArrayQueue,
    spinning_top::Spinlock,
    std_alloc::{
-      boxed::Box,
-      collections::VecDeque,
+      collections::BTreeMap,
       sync::Arc,
+      vec::Vec,
    },
 };
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::tasks::{self, JoinError},
+      core::{future::Future, pin::Pin, sync::atomic::AtomicUsize, task::Poll},
+      futures_util::task::noop_waker_ref,
+      std::sync::{Mutex, MutexGuard},
+   };
+
+   /// Tests share [`DEFAULT_EXECUTOR`], so they take turns running it.
+   fn serial() -> MutexGuard<'static, ()> {
+      static SERIAL: Mutex<()> = Mutex::new(());
+      return SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+   }
+
+   /// Pends until `ready` is set, counting its polls and keeping its last waker.
+   struct Gate {
+      ready: Arc<AtomicBool>,
+      polls: Arc<AtomicUsize>,
+      waker: Arc<Mutex<Option<Waker>>>,
+   }
+
+   impl Future for Gate {
+      type Output = ();
+
+      fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+         self.polls.fetch_add(1, Ordering::SeqCst);
+         if self.ready.load(Ordering::SeqCst) {
+            return Poll::Ready(());
+         }
+
+         *self.waker.lock().unwrap() = Some(cx.waker().clone());
+         return Poll::Pending;
+      }
+   }
+
+   #[test]
+   fn only_woken_tasks_are_polled() {
+      let _serial = serial();
+      let ready = Arc::new(AtomicBool::new(false));
+      let polls = Arc::new(AtomicUsize::new(0));
+      let waker = Arc::new(Mutex::new(None));
+
+      let id = tasks::add_future(Gate{
+         ready: ready.clone(),
+         polls: polls.clone(),
+         waker: waker.clone(),
+      }).id();
+
+      DEFAULT_EXECUTOR.run_ready();
+      assert_eq!(polls.load(Ordering::SeqCst), 1);
+
+      // Nothing woke it, so it is left alone.
+      DEFAULT_EXECUTOR.run_ready();
+      assert_eq!(polls.load(Ordering::SeqCst), 1);
+
+      ready.store(true, Ordering::SeqCst);
+      let waker = waker.lock().unwrap().take().unwrap();
+      waker.wake_by_ref();
+      waker.wake();
+
+      DEFAULT_EXECUTOR.run_ready();
+      assert_eq!(polls.load(Ordering::SeqCst), 2);
+      assert!(!DEFAULT_EXECUTOR.tasks.lock().contains_key(&id));
+   }
+
+   fn poll<T>(handle: &mut tasks::JoinHandle<T>) -> Poll<Result<T, JoinError>> {
+      return Pin::new(handle).poll(&mut Context::from_waker(noop_waker_ref()));
+   }
+
+   #[test]
+   fn join_handles_yield_results_and_aborts() {
+      let _serial = serial();
+      let mut answer = tasks::add_named_future("answer", async { 42 });
+      let listed = tasks::task_list().into_iter().find(|info| info.id == answer.id()).unwrap();
+      assert_eq!(listed.name.as_deref(), Some("answer"));
+
+      DEFAULT_EXECUTOR.run_ready();
+      assert!(answer.is_finished());
+      assert_eq!(poll(&mut answer), Poll::Ready(Ok(42)));
+
+      let mut stuck = tasks::add_future(core::future::pending::<()>());
+      DEFAULT_EXECUTOR.run_ready();
+      assert_eq!(poll(&mut stuck), Poll::Pending);
+
+      stuck.abort();
+      DEFAULT_EXECUTOR.run_ready();
+      assert_eq!(poll(&mut stuck), Poll::Ready(Err(JoinError::Aborted)));
+      assert!(tasks::task_list().iter().all(|info| info.id != stuck.id()));
+   }
+
+   #[test]
+   fn panicking_tasks_report_the_panic() {
+      let _serial = serial();
+      let mut handle = tasks::add_future(async { panic!("task failure") });
+
+      let id = handle.id();
+      let unwound = std::panic::catch_unwind(|| DEFAULT_EXECUTOR.poll(id));
+
+      assert!(unwound.is_err());
+      assert_eq!(poll(&mut handle), Poll::Ready(Err(JoinError::Panicked)));
+   }
+}
@@ -0,0 +1,169 @@
+/// An owned permission to await a task's result or abort it.
+///
+/// Dropping the handle detaches the task, which keeps running.
+pub struct JoinHandle<T> {
+   id: TaskId,
+   state: Arc<JoinState<T>>,
+}
+
+impl<T> JoinHandle<T> {
+   /// The ID of the task.
+   pub fn id(&self) -> TaskId {
+      return self.id;
+   }
+
+   /// Stops the task the next time the executor reaches it, dropping its future.
+   ///
+   /// Awaiting the handle afterwards yields [`JoinError::Aborted`], unless the task had already
+   /// completed.
+   pub fn abort(&self) {
+      self.state.aborted.store(true, Ordering::Release);
+      executor::wake_task(self.id);
+   }
+
+   /// Whether the task has completed, been aborted or panicked.
+   pub fn is_finished(&self) -> bool {
+      return self.state.finished.load(Ordering::Acquire);
+   }
+}
+
+impl<T> Future for JoinHandle<T> {
+   type Output = Result<T, JoinError>;
+
+   fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+      if !self.is_finished() {
+         self.state.waker.register(cx.waker());
+
+         if !self.is_finished() {
+            return Poll::Pending;
+         }
+      }
+
+      return match self.state.result.lock().take() {
+         Some(result) => Poll::Ready(result),
+         None => panic!("JoinHandle polled after completion"),
+      };
+   }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return f.debug_struct("JoinHandle")
+         .field("id", &self.id)
+         .field("finished", &self.is_finished())
+         .finish();
+   }
+}
+
+/// Why a task finished without a result.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum JoinError {
+   /// [`JoinHandle::abort`] stopped the task.
+   Aborted,
+
+   /// The task panicked while being polled.
+   ///
+   /// Only seen where panics unwind; the kernel halts on panic.
+   Panicked,
+}
+
+impl Display for JoinError {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return match self {
+         JoinError::Aborted => write!(f, "task was aborted"),
+         JoinError::Panicked => write!(f, "task panicked"),
+      };
+   }
+}
+
+/// Wraps `future` so its output, or the reason it has none, reaches the returned state.
+pub(super) fn harness<F>(future: F) -> (Harness<F>, Arc<JoinState<F::Output>>)
+where
+   F: Future, {
+   let state = Arc::new(JoinState{
+      result: Spinlock::new(None),
+      finished: AtomicBool::new(false),
+      aborted: AtomicBool::new(false),
+      waker: AtomicWaker::new(),
+   });
+
+   return (Harness{ future, state: state.clone(), done: false }, state);
+}
+
+pub(super) fn handle<T>(id: TaskId, state: Arc<JoinState<T>>) -> JoinHandle<T> {
+   return JoinHandle{ id, state };
+}
+
+/// What a task and its [`JoinHandle`] share.
+pub(super) struct JoinState<T> {
+   result: Spinlock<Option<Result<T, JoinError>>>,
+   finished: AtomicBool,
+   aborted: AtomicBool,
+   waker: AtomicWaker,
+}
+
+impl<T> JoinState<T> {
+   fn finish(&self, result: Result<T, JoinError>) {
+      *self.result.lock() = Some(result);
+      self.finished.store(true, Ordering::Release);
+      self.waker.wake();
+   }
+}
+
+/// The future a spawned task actually runs.
+pub(super) struct Harness<F: Future> {
+   future: F,
+   state: Arc<JoinState<F::Output>>,
+   done: bool,
+}
+
+impl<F: Future> Future for Harness<F> {
+   type Output = ();
+
+   fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+      // Safety: `future` is never moved out of the pinned harness.
+      let this = unsafe { self.get_unchecked_mut() };
+
+      if this.state.aborted.load(Ordering::Acquire) {
+         this.done = true;
+         this.state.finish(Err(JoinError::Aborted));
+         return Poll::Ready(());
+      }
+
+      let future = unsafe { Pin::new_unchecked(&mut this.future) };
+      return match future.poll(cx) {
+         Poll::Ready(output) => {
+            this.done = true;
+            this.state.finish(Ok(output));
+            Poll::Ready(())
+         }
+         Poll::Pending => Poll::Pending,
+      };
+   }
+}
+
+impl<F: Future> Drop for Harness<F> {
+   // A harness is only dropped unfinished when the executor gives up on a task whose poll
+   // unwound.
+   fn drop(&mut self) {
+      if !self.done {
+         self.state.finish(Err(JoinError::Panicked));
+      }
+   }
+}
+
+// IMPORTS //
+
+use {
+   super::{executor, TaskId},
+   core::{
+      fmt::{self, Display},
+      future::Future,
+      pin::Pin,
+      sync::atomic::{AtomicBool, Ordering},
+      task::{Context, Poll},
+   },
+   futures_util::task::AtomicWaker,
+   spinning_top::Spinlock,
+   std_alloc::sync::Arc,
+};
@@ -1,6 +1,26 @@
+/// Number of scancodes buffered between the keyboard interrupt and the decoder.
+pub const SCANCODE_QUEUE_SIZE: usize = 100;
+
+/// Number of key events buffered for each subscriber before new events are dropped.
+pub const EVENT_QUEUE_SIZE: usize = 64;
+
 pub static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
 pub static KEYBOARD_WAKER: AtomicWaker = AtomicWaker::new();
 
+/// Set once a [`ScancodeStream`] exists, since the scancode queue only has one consumer.
+static STREAM_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// The layout key events are decoded with; see [`set_layout`].
+static LAYOUT: AtomicU8 = AtomicU8::new(KeyLayout::Us104 as u8);
+
+/// Every live [`KeyEventStream`].
+static SUBSCRIBERS: Spinlock<Vec<Weak<Subscriber>>> = Spinlock::new(Vec::new());
+
+/// Creates the scancode queue, so scancodes arriving before anyone listens are kept.
+pub fn initialise() {
+   let _ = SCANCODE_QUEUE.try_init_once(|| ArrayQueue::new(SCANCODE_QUEUE_SIZE));
+}
+
 /// Called by the keyboard interrupt handler
 ///
 /// Must not block or allocate.
@@ -16,14 +36,219 @@ pub fn add_scancode(scancode: u8) {
    }
 }
 
+/// Selects the layout used to decode subsequent key presses.
+pub fn set_layout(layout: KeyLayout) {
+   LAYOUT.store(layout as u8, Ordering::Relaxed);
+}
+
+/// The layout key presses are currently decoded with.
+pub fn layout() -> KeyLayout {
+   return KeyLayout::from_u8(LAYOUT.load(Ordering::Relaxed));
+}
+
+/// Returns a stream of every key event decoded from now on.
+///
+/// Any number of tasks may subscribe; each gets its own copy of every event. Events are only
+/// produced while [`decode_scancodes`] is running.
+pub fn subscribe() -> KeyEventStream {
+   let subscriber = Arc::new(Subscriber{
+      events: ArrayQueue::new(EVENT_QUEUE_SIZE),
+      waker: AtomicWaker::new(),
+   });
+
+   SUBSCRIBERS.lock().push(Arc::downgrade(&subscriber));
+   return KeyEventStream{ subscriber };
+}
+
+/// Decodes scancodes from the keyboard and hands the resulting events to every subscriber.
+///
+/// Runs for as long as the kernel does; spawn it once with [`add_future`](super::add_future).
+pub async fn decode_scancodes() {
+   let mut scancodes = ScancodeStream::new();
+   let mut keyboard = Keyboard::new(ScancodeSet1::new(), ActiveLayout, HandleControl::Ignore);
+
+   while let Some(scancode) = scancodes.next().await {
+      let event = match keyboard.add_byte(scancode) {
+         Ok(Some(event)) => event,
+         Ok(None) => continue,
+         Err(error) => {
+            log::warn!("dropping malformed scancode {:#x}: {:?}", scancode, error);
+            continue;
+         }
+      };
+
+      let (code, state) = (event.code, event.state);
+      let key = keyboard.process_keyevent(event);
+
+      publish(KeyPress{ code, state, key });
+   }
+}
+
+/// Prints every key pressed to the terminal.
+pub async fn print_keypresses() {
+   let mut events = subscribe();
+
+   while let Some(event) = events.next().await {
+      match event.key {
+         Some(DecodedKey::Unicode(character)) => print!("{}", character),
+         Some(DecodedKey::RawKey(key)) => print!("{:?}", key),
+         None => {}
+      }
+   }
+}
+
+fn publish(event: KeyPress) {
+   // Wake subscribers without holding the lock, since waking may poll them.
+   let subscribers: Vec<Arc<Subscriber>> = {
+      let mut subscribers = SUBSCRIBERS.lock();
+      subscribers.retain(|subscriber| subscriber.strong_count() > 0);
+      subscribers.iter().filter_map(Weak::upgrade).collect()
+   };
+
+   for subscriber in subscribers {
+      if subscriber.events.push(event).is_err() {
+         log::warn!("key event queue full; dropping {:?}", event.code);
+      }
+
+      subscriber.waker.wake();
+   }
+}
+
+/// A decoded key event.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct KeyPress {
+   /// The physical key.
+   pub code: KeyCode,
+
+   /// Whether the key went up or down.
+   pub state: KeyState,
+
+   /// What the key means under the active layout and modifiers, for key-down events that
+   /// produce something.
+   pub key: Option<DecodedKey>,
+}
+
+impl KeyPress {
+   /// The character the key typed, if any.
+   pub fn character(&self) -> Option<char> {
+      return match self.key {
+         Some(DecodedKey::Unicode(character)) => Some(character),
+         _ => None,
+      };
+   }
+}
+
+/// Keyboard layouts scancodes can be decoded with.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KeyLayout {
+   /// US 104-key QWERTY.
+   Us104,
+
+   /// UK 105-key QWERTY.
+   Uk105,
+
+   /// German 105-key QWERTZ.
+   De105,
+
+   /// French AZERTY.
+   Azerty,
+
+   /// Japanese 109-key.
+   Jis109,
+
+   /// US 104-key Dvorak.
+   Dvorak104,
+
+   /// US 104-key Programmer Dvorak.
+   DvorakProgrammer104,
+
+   /// Colemak.
+   Colemak,
+}
+
+impl KeyLayout {
+   fn from_u8(value: u8) -> Self {
+      return match value {
+         1 => KeyLayout::Uk105,
+         2 => KeyLayout::De105,
+         3 => KeyLayout::Azerty,
+         4 => KeyLayout::Jis109,
+         5 => KeyLayout::Dvorak104,
+         6 => KeyLayout::DvorakProgrammer104,
+         7 => KeyLayout::Colemak,
+         _ => KeyLayout::Us104,
+      };
+   }
+}
+
+/// Decodes with whichever layout [`set_layout`] last selected, so the layout can change without
+/// losing modifier state.
+struct ActiveLayout;
+
+impl KeyboardLayout for ActiveLayout {
+   fn map_keycode(&self, keycode: KeyCode, modifiers: &Modifiers, handle_ctrl: HandleControl) -> DecodedKey {
+      return match layout() {
+         KeyLayout::Us104 => layouts::Us104Key.map_keycode(keycode, modifiers, handle_ctrl),
+         KeyLayout::Uk105 => layouts::Uk105Key.map_keycode(keycode, modifiers, handle_ctrl),
+         KeyLayout::De105 => layouts::De105Key.map_keycode(keycode, modifiers, handle_ctrl),
+         KeyLayout::Azerty => layouts::Azerty.map_keycode(keycode, modifiers, handle_ctrl),
+         KeyLayout::Jis109 => layouts::Jis109Key.map_keycode(keycode, modifiers, handle_ctrl),
+         KeyLayout::Dvorak104 => layouts::Dvorak104Key.map_keycode(keycode, modifiers, handle_ctrl),
+         KeyLayout::DvorakProgrammer104 => layouts::DVP104Key.map_keycode(keycode, modifiers, handle_ctrl),
+         KeyLayout::Colemak => layouts::Colemak.map_keycode(keycode, modifiers, handle_ctrl),
+      };
+   }
+}
+
+struct Subscriber {
+   events: ArrayQueue<KeyPress>,
+   waker: AtomicWaker,
+}
+
+/// A stream of key events; see [`subscribe`].
+pub struct KeyEventStream {
+   subscriber: Arc<Subscriber>,
+}
+
+impl KeyEventStream {
+   /// Polls for the next key event, registering the task to be woken when one arrives.
+   pub fn poll_event(&mut self, cx: &mut Context<'_>) -> Poll<Option<KeyPress>> {
+      if let Some(event) = self.subscriber.events.pop() {
+         return Poll::Ready(Some(event));
+      }
+
+      self.subscriber.waker.register(cx.waker());
+      return match self.subscriber.events.pop() {
+         Some(event) => {
+            self.subscriber.waker.take();
+            Poll::Ready(Some(event))
+         }
+         None => Poll::Pending,
+      };
+   }
+}
+
+impl Stream for KeyEventStream {
+   type Item = KeyPress;
+
+   fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+      return self.get_mut().poll_event(cx);
+   }
+}
+
 pub struct ScancodeStream {
    _private: (),
 }
 
 impl ScancodeStream {
    pub fn new() -> Self {
-      SCANCODE_QUEUE.try_init_once(|| ArrayQueue::new(100))
-         .expect("ScancodeStream initializer should only be called once");
+      assert!(
+         !STREAM_TAKEN.swap(true, Ordering::AcqRel),
+         "ScancodeStream initializer should only be called once",
+      );
+
+      initialise();
       return ScancodeStream{ _private: () };
    }
 }
@@ -52,28 +277,13 @@ impl Stream for ScancodeStream {
    }
 }
 
-pub async fn print_keypresses() {
-   let mut scancodes = ScancodeStream::new();
-   let mut keyboard = Keyboard::new(ScancodeSet1::new(), layouts::Us104Key, HandleControl::Ignore);
-
-   while let Some(scancode) = scancodes.next().await {
-      if let Ok(Some(event)) = keyboard.add_byte(scancode) {
-         if let Some(key) = keyboard.process_keyevent(event) {
-            match key {
-               DecodedKey::Unicode(character) => print!("{}", character),
-               DecodedKey::RawKey(key) => print!("{:?}", key)
-            }
-         }
-      }
-   }
-}
-
 // IMPORTS //
 
 use {
    crate::print,
    core::{
       pin::Pin,
+      sync::atomic::{AtomicBool, AtomicU8, Ordering},
       task::{Context, Poll},
    },
    conquer_once::spin::OnceCell,
@@ -86,7 +296,63 @@ use {
       layouts,
       DecodedKey,
       HandleControl,
+      KeyCode,
       Keyboard,
+      KeyboardLayout,
+      KeyState,
+      Modifiers,
       ScancodeSet1,
    },
+   spinning_top::Spinlock,
+   std_alloc::{
+      sync::{Arc, Weak},
+      vec::Vec,
+   },
 };
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      futures_util::task::noop_waker_ref,
+   };
+
+   fn poll(stream: &mut KeyEventStream) -> Poll<Option<KeyPress>> {
+      return Pin::new(stream).poll_next(&mut Context::from_waker(noop_waker_ref()));
+   }
+
+   #[test]
+   fn layouts_round_trip() {
+      for value in 0..8 {
+         assert_eq!(KeyLayout::from_u8(value) as u8, value);
+      }
+   }
+
+   #[test]
+   fn only_unicode_keys_have_characters() {
+      let typed = KeyPress{ code: KeyCode::A, state: KeyState::Down, key: Some(DecodedKey::Unicode('a')) };
+      let raw = KeyPress{ code: KeyCode::F1, state: KeyState::Down, key: Some(DecodedKey::RawKey(KeyCode::F1)) };
+      let released = KeyPress{ code: KeyCode::A, state: KeyState::Up, key: None };
+
+      assert_eq!(typed.character(), Some('a'));
+      assert_eq!(raw.character(), None);
+      assert_eq!(released.character(), None);
+   }
+
+   #[test]
+   fn every_subscriber_sees_every_event() {
+      let mut first = subscribe();
+      let mut second = subscribe();
+      drop(subscribe());
+
+      let event = KeyPress{ code: KeyCode::A, state: KeyState::Down, key: Some(DecodedKey::Unicode('a')) };
+      publish(event);
+
+      assert_eq!(poll(&mut first), Poll::Ready(Some(event)));
+      assert_eq!(poll(&mut second), Poll::Ready(Some(event)));
+      assert_eq!(poll(&mut first), Poll::Pending);
+
+      // The dropped subscriber was pruned.
+      assert!(SUBSCRIBERS.lock().iter().all(|subscriber| subscriber.strong_count() > 0));
+   }
+}
@@ -0,0 +1,21 @@
+// MODULES //
+
+/// Channels that carry many values from any number of senders to one receiver.
+pub mod mpsc;
+
+/// Channels that carry a single value, such as the reply to a request.
+pub mod oneshot;
+
+mod mutex;
+mod notify;
+mod rwlock;
+mod semaphore;
+
+// EXPORTS //
+
+pub use self::{
+   mutex::{Mutex, MutexGuard},
+   notify::{Notified, Notify},
+   rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+   semaphore::{Acquire, AcquireError, Semaphore, SemaphorePermit, TryAcquireError},
+};
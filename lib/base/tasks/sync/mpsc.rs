@@ -0,0 +1,364 @@
+/// Creates a channel holding at most `capacity` values, whose senders wait for room.
+///
+/// [`Sender::try_send`] never blocks or allocates, so interrupt handlers can feed the channel.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+   assert!(capacity > 0, "channel capacity must be non-zero");
+
+   let shared = Shared::new(Buffer::Bounded(ArrayQueue::new(capacity)), Some(Semaphore::new(capacity)));
+   return (Sender{ shared: shared.clone() }, Receiver{ shared });
+}
+
+/// Creates a channel with no limit on the values it holds.
+///
+/// Sending allocates, so interrupt handlers should use a bounded [`channel`] instead.
+pub fn unbounded_channel<T>() -> (UnboundedSender<T>, Receiver<T>) {
+   let shared = Shared::new(Buffer::Unbounded(SegQueue::new()), None);
+   return (UnboundedSender{ shared: shared.clone() }, Receiver{ shared });
+}
+
+struct Shared<T> {
+   buffer: Buffer<T>,
+
+   /// Free slots in a bounded buffer; closed along with the channel.
+   capacity: Option<Semaphore>,
+
+   senders: AtomicUsize,
+   receiver_alive: AtomicBool,
+   receiver_waker: AtomicWaker,
+}
+
+enum Buffer<T> {
+   Bounded(ArrayQueue<T>),
+   Unbounded(SegQueue<T>),
+}
+
+impl<T> Shared<T> {
+   fn new(buffer: Buffer<T>, capacity: Option<Semaphore>) -> Arc<Self> {
+      return Arc::new(Shared{
+         buffer,
+         capacity,
+         senders: AtomicUsize::new(1),
+         receiver_alive: AtomicBool::new(true),
+         receiver_waker: AtomicWaker::new(),
+      });
+   }
+
+   fn is_closed(&self) -> bool {
+      return !self.receiver_alive.load(Ordering::Acquire);
+   }
+
+   /// Queues `value`; bounded senders must already hold a slot.
+   fn push(&self, value: T) {
+      match &self.buffer {
+         Buffer::Bounded(queue) => {
+            if queue.push(value).is_err() {
+               unreachable!("bounded channel overfilled");
+            }
+         }
+         Buffer::Unbounded(queue) => queue.push(value),
+      }
+
+      self.receiver_waker.wake();
+   }
+
+   fn pop(&self) -> Option<T> {
+      let value = match &self.buffer {
+         Buffer::Bounded(queue) => queue.pop(),
+         Buffer::Unbounded(queue) => queue.pop(),
+      }?;
+
+      if let Some(capacity) = &self.capacity {
+         capacity.add_permits(1);
+      }
+
+      return Some(value);
+   }
+
+   fn add_sender(&self) {
+      self.senders.fetch_add(1, Ordering::Relaxed);
+   }
+
+   fn remove_sender(&self) {
+      if self.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+         self.receiver_waker.wake();
+      }
+   }
+}
+
+/// Sends values into a bounded [`channel`]; clone it for more producers.
+pub struct Sender<T> {
+   shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+   /// Waits for room, then sends `value`, handing it back if the receiver is gone.
+   pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+      let capacity = self.shared.capacity.as_ref().expect("bounded channel without capacity");
+
+      match capacity.acquire().await {
+         Ok(permit) => permit.forget(),
+         Err(_) => return Err(SendError(value)),
+      }
+
+      self.shared.push(value);
+      return Ok(());
+   }
+
+   /// Sends `value` if there is room right now.
+   pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+      let capacity = self.shared.capacity.as_ref().expect("bounded channel without capacity");
+
+      match capacity.try_acquire() {
+         Ok(permit) => permit.forget(),
+         Err(TryAcquireError::NoPermits) => return Err(TrySendError::Full(value)),
+         Err(TryAcquireError::Closed) => return Err(TrySendError::Closed(value)),
+      }
+
+      self.shared.push(value);
+      return Ok(());
+   }
+
+   /// Whether the receiver has been dropped.
+   pub fn is_closed(&self) -> bool {
+      return self.shared.is_closed();
+   }
+}
+
+impl<T> Clone for Sender<T> {
+   fn clone(&self) -> Self {
+      self.shared.add_sender();
+      return Sender{ shared: self.shared.clone() };
+   }
+}
+
+impl<T> Drop for Sender<T> {
+   fn drop(&mut self) {
+      self.shared.remove_sender();
+   }
+}
+
+/// Sends values into an [`unbounded_channel`]; clone it for more producers.
+pub struct UnboundedSender<T> {
+   shared: Arc<Shared<T>>,
+}
+
+impl<T> UnboundedSender<T> {
+   /// Sends `value`, handing it back if the receiver is gone.
+   pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+      if self.shared.is_closed() {
+         return Err(SendError(value));
+      }
+
+      self.shared.push(value);
+      return Ok(());
+   }
+
+   /// Whether the receiver has been dropped.
+   pub fn is_closed(&self) -> bool {
+      return self.shared.is_closed();
+   }
+}
+
+impl<T> Clone for UnboundedSender<T> {
+   fn clone(&self) -> Self {
+      self.shared.add_sender();
+      return UnboundedSender{ shared: self.shared.clone() };
+   }
+}
+
+impl<T> Drop for UnboundedSender<T> {
+   fn drop(&mut self) {
+      self.shared.remove_sender();
+   }
+}
+
+/// Receives the values of a channel in the order they were sent.
+///
+/// Also a [`Stream`] that ends once every sender is gone and the buffer is drained.
+pub struct Receiver<T> {
+   shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+   /// Waits for the next value, or `None` once every sender is gone and nothing is left.
+   pub async fn recv(&mut self) -> Option<T> {
+      return poll_fn(|cx| self.poll_recv(cx)).await;
+   }
+
+   /// Takes the next value if there is one.
+   pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+      if let Some(value) = self.shared.pop() {
+         return Ok(value);
+      }
+
+      if self.shared.senders.load(Ordering::Acquire) == 0 {
+         // A final send may have landed between the pop and the check.
+         return self.shared.pop().ok_or(TryRecvError::Closed);
+      }
+
+      return Err(TryRecvError::Empty);
+   }
+
+   /// Polls for the next value, as [`recv`](Self::recv) does.
+   pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+      match self.try_recv() {
+         Ok(value) => return Poll::Ready(Some(value)),
+         Err(TryRecvError::Closed) => return Poll::Ready(None),
+         Err(TryRecvError::Empty) => {}
+      }
+
+      self.shared.receiver_waker.register(cx.waker());
+
+      return match self.try_recv() {
+         Ok(value) => Poll::Ready(Some(value)),
+         Err(TryRecvError::Closed) => Poll::Ready(None),
+         Err(TryRecvError::Empty) => Poll::Pending,
+      };
+   }
+
+   /// Stops accepting values; those already sent can still be received.
+   pub fn close(&mut self) {
+      self.shared.receiver_alive.store(false, Ordering::Release);
+
+      if let Some(capacity) = &self.shared.capacity {
+         capacity.close();
+      }
+   }
+}
+
+impl<T> Stream for Receiver<T> {
+   type Item = T;
+
+   fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+      return self.poll_recv(cx);
+   }
+}
+
+impl<T> Drop for Receiver<T> {
+   fn drop(&mut self) {
+      self.close();
+   }
+}
+
+/// Error returned when sending to a channel whose receiver is gone; holds the unsent value.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Debug for SendError<T> {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return write!(f, "SendError(..)");
+   }
+}
+
+impl<T> Display for SendError<T> {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return write!(f, "channel closed");
+   }
+}
+
+/// Errors returned by [`Sender::try_send`]; both hold the unsent value.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum TrySendError<T> {
+   /// The channel is at capacity.
+   Full(T),
+
+   /// The receiver is gone.
+   Closed(T),
+}
+
+impl<T> fmt::Debug for TrySendError<T> {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return match self {
+         TrySendError::Full(_) => write!(f, "Full(..)"),
+         TrySendError::Closed(_) => write!(f, "Closed(..)"),
+      };
+   }
+}
+
+impl<T> Display for TrySendError<T> {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return match self {
+         TrySendError::Full(_) => write!(f, "channel full"),
+         TrySendError::Closed(_) => write!(f, "channel closed"),
+      };
+   }
+}
+
+/// Errors returned by [`Receiver::try_recv`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TryRecvError {
+   /// Nothing is waiting to be received.
+   Empty,
+
+   /// Every sender is gone and nothing is left.
+   Closed,
+}
+
+impl Display for TryRecvError {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return match self {
+         TryRecvError::Empty => write!(f, "channel empty"),
+         TryRecvError::Closed => write!(f, "channel closed"),
+      };
+   }
+}
+
+// IMPORTS //
+
+use {
+   super::{Semaphore, TryAcquireError},
+   core::{
+      fmt::{self, Display},
+      future::poll_fn,
+      pin::Pin,
+      sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+      task::{Context, Poll},
+   },
+   crossbeam_queue::{ArrayQueue, SegQueue},
+   futures_util::{stream::Stream, task::AtomicWaker},
+   std_alloc::sync::Arc,
+};
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::test::poll_once,
+      core::pin::pin,
+   };
+
+   #[test]
+   fn bounded_senders_wait_for_room() {
+      let (sender, mut receiver) = channel(1);
+      sender.try_send(1).unwrap();
+      assert_eq!(sender.try_send(2), Err(TrySendError::Full(2)));
+
+      let mut blocked = pin!(sender.send(3));
+      assert!(poll_once(blocked.as_mut()).is_pending());
+
+      assert_eq!(receiver.try_recv(), Ok(1));
+      assert_eq!(poll_once(blocked.as_mut()), Poll::Ready(Ok(())));
+      assert_eq!(receiver.try_recv(), Ok(3));
+      assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+
+      drop(receiver);
+      assert_eq!(sender.try_send(4), Err(TrySendError::Closed(4)));
+   }
+
+   #[test]
+   fn unbounded_receivers_drain_before_closing() {
+      let (sender, mut receiver) = unbounded_channel();
+      let second = sender.clone();
+
+      for value in 0..100 {
+         sender.send(value).unwrap();
+      }
+      drop(sender);
+      drop(second);
+
+      for value in 0..100 {
+         assert_eq!(poll_once(pin!(receiver.recv())), Poll::Ready(Some(value)));
+      }
+      assert_eq!(poll_once(pin!(receiver.recv())), Poll::Ready(None));
+   }
+}
@@ -0,0 +1,123 @@
+/// A mutual-exclusion lock that suspends the waiting task instead of spinning.
+///
+/// Waiters get the lock in the order they asked for it. The guard may be held across `.await`.
+pub struct Mutex<T: ?Sized> {
+   semaphore: Semaphore,
+   value: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+   /// Creates an unlocked mutex holding `value`.
+   pub const fn new(value: T) -> Self {
+      return Mutex{ semaphore: Semaphore::new(1), value: UnsafeCell::new(value) };
+   }
+
+   /// Consumes the mutex, returning the value.
+   pub fn into_inner(self) -> T {
+      return self.value.into_inner();
+   }
+}
+
+impl<T: ?Sized> Mutex<T> {
+   /// Waits until the lock is free, then takes it.
+   pub async fn lock(&self) -> MutexGuard<'_, T> {
+      let permit = match self.semaphore.acquire().await {
+         Ok(permit) => permit,
+         Err(_) => unreachable!("mutex semaphores are never closed"),
+      };
+
+      permit.forget();
+      return MutexGuard{ mutex: self };
+   }
+
+   /// Takes the lock if it is free and nobody is waiting for it.
+   pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+      let permit = self.semaphore.try_acquire().ok()?;
+      permit.forget();
+      return Some(MutexGuard{ mutex: self });
+   }
+
+   /// A mutable reference to the value; no locking is needed since the borrow is exclusive.
+   pub fn get_mut(&mut self) -> &mut T {
+      return self.value.get_mut();
+   }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return match self.try_lock() {
+         Some(guard) => f.debug_struct("Mutex").field("value", &&*guard).finish(),
+         None => f.debug_struct("Mutex").field("value", &format_args!("<locked>")).finish(),
+      };
+   }
+}
+
+/// Exclusive access to a [`Mutex`]'s value, released when dropped.
+#[must_use = "the lock is released as soon as the guard is dropped"]
+pub struct MutexGuard<'a, T: ?Sized> {
+   mutex: &'a Mutex<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for MutexGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+   type Target = T;
+
+   fn deref(&self) -> &T {
+      return unsafe { &*self.mutex.value.get() };
+   }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+   fn deref_mut(&mut self) -> &mut T {
+      return unsafe { &mut *self.mutex.value.get() };
+   }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+   fn drop(&mut self) {
+      self.mutex.semaphore.add_permits(1);
+   }
+}
+
+// IMPORTS //
+
+use {
+   super::Semaphore,
+   core::{
+      cell::UnsafeCell,
+      fmt,
+      ops::{Deref, DerefMut},
+   },
+};
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::test::poll_once,
+      core::pin::pin,
+   };
+
+   #[test]
+   fn lock_waits_for_the_guard() {
+      let mutex = Mutex::new(0);
+      let mut guard = mutex.try_lock().unwrap();
+      *guard += 1;
+
+      {
+         let mut waiting = pin!(mutex.lock());
+         assert!(poll_once(waiting.as_mut()).is_pending());
+         assert!(mutex.try_lock().is_none());
+
+         drop(guard);
+         let core::task::Poll::Ready(mut guard) = poll_once(waiting.as_mut()) else { panic!("lock not handed over") };
+         *guard += 1;
+      }
+
+      assert_eq!(mutex.into_inner(), 2);
+   }
+}
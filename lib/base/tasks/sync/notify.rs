@@ -0,0 +1,265 @@
+/// Wakes tasks waiting for an event, without carrying any data.
+///
+/// A [`notify_one`](Notify::notify_one) with nobody waiting is remembered, so the next
+/// [`notified`](Notify::notified) completes at once; that makes it suitable for an interrupt
+/// handler telling a driver task that there is work. Notifying never allocates.
+pub struct Notify {
+   state: Spinlock<State>,
+}
+
+/// Most wakers [`Notify::notify_waiters`] holds at once.
+const WAKE_BATCH: usize = 32;
+
+struct State {
+   /// Set by a `notify_one` that found no waiter.
+   permit: bool,
+   waiters: VecDeque<Waiter>,
+   next_id: u64,
+}
+
+struct Waiter {
+   id: u64,
+   waker: Waker,
+
+   /// How the waiter was notified; it stays queued until it sees this.
+   notified: Option<Notification>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Notification {
+   One,
+   All,
+}
+
+impl Notify {
+   /// Creates a notifier with no stored notification.
+   pub const fn new() -> Self {
+      return Notify{
+         state: Spinlock::new(State{ permit: false, waiters: VecDeque::new(), next_id: 0 }),
+      };
+   }
+
+   /// Completes after the next notification.
+   pub fn notified(&self) -> Notified<'_> {
+      return Notified{ notify: self, id: None, done: false };
+   }
+
+   /// Wakes the longest-waiting task, or lets the next [`notified`](Self::notified) through if
+   /// nobody is waiting.
+   pub fn notify_one(&self) {
+      if let Some(waker) = without_interrupts(|| self.state.lock().notify_one()) {
+         waker.wake();
+      }
+   }
+
+   /// Wakes every task waiting right now; later waiters are not affected.
+   pub fn notify_waiters(&self) {
+      const NONE: Option<Waker> = None;
+
+      // Only waiters queued before this call; anyone arriving between batches has a later id.
+      let last = without_interrupts(|| self.state.lock().next_id);
+
+      // Wakers run outside the lock, collected a batch at a time so this stays allocation-free.
+      loop {
+         let mut wakers = [NONE; WAKE_BATCH];
+         let count = without_interrupts(|| {
+            let mut state = self.state.lock();
+            let pending = state.waiters.iter_mut()
+               .filter(|waiter| waiter.id < last && waiter.notified.is_none());
+
+            let mut count = 0;
+            for (waiter, slot) in pending.zip(wakers.iter_mut()) {
+               waiter.notified = Some(Notification::All);
+               *slot = Some(waiter.waker.clone());
+               count += 1;
+            }
+
+            return count;
+         });
+
+         for waker in wakers.iter_mut().filter_map(Option::take) {
+            waker.wake();
+         }
+
+         if count < WAKE_BATCH {
+            return;
+         }
+      }
+   }
+}
+
+impl State {
+   fn notify_one(&mut self) -> Option<Waker> {
+      return match self.waiters.iter_mut().find(|waiter| waiter.notified.is_none()) {
+         Some(waiter) => {
+            waiter.notified = Some(Notification::One);
+            Some(waiter.waker.clone())
+         }
+         None => {
+            self.permit = true;
+            None
+         }
+      };
+   }
+}
+
+impl Default for Notify {
+   fn default() -> Self {
+      return Notify::new();
+   }
+}
+
+/// Future returned by [`Notify::notified`].
+pub struct Notified<'a> {
+   notify: &'a Notify,
+   id: Option<u64>,
+   done: bool,
+}
+
+impl Future for Notified<'_> {
+   type Output = ();
+
+   fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+      if self.done {
+         return Poll::Ready(());
+      }
+
+      let notify = self.notify;
+      let ready = without_interrupts(|| {
+         let mut state = notify.state.lock();
+
+         let id = match self.id {
+            Some(id) => id,
+            None if state.permit => {
+               state.permit = false;
+               return true;
+            }
+            None => {
+               let id = state.next_id;
+               state.next_id += 1;
+               state.waiters.push_back(Waiter{ id, waker: cx.waker().clone(), notified: None });
+               self.id = Some(id);
+               return false;
+            }
+         };
+
+         let position = match state.waiters.iter().position(|waiter| waiter.id == id) {
+            Some(position) => position,
+            None => return true,
+         };
+
+         if state.waiters[position].notified.is_some() {
+            state.waiters.remove(position);
+            return true;
+         }
+
+         let waiter = &mut state.waiters[position];
+         if !waiter.waker.will_wake(cx.waker()) {
+            waiter.waker = cx.waker().clone();
+         }
+         return false;
+      });
+
+      if ready {
+         self.done = true;
+         self.id = None;
+         return Poll::Ready(());
+      }
+
+      return Poll::Pending;
+   }
+}
+
+impl Drop for Notified<'_> {
+   fn drop(&mut self) {
+      let id = match (self.done, self.id) {
+         (false, Some(id)) => id,
+         _ => return,
+      };
+
+      // A `notify_one` meant for us must not be lost, so hand it on.
+      let next = without_interrupts(|| {
+         let mut state = self.notify.state.lock();
+         let position = state.waiters.iter().position(|waiter| waiter.id == id)?;
+         let waiter = state.waiters.remove(position)?;
+
+         return match waiter.notified {
+            Some(Notification::One) => state.notify_one(),
+            _ => None,
+         };
+      });
+
+      if let Some(waker) = next {
+         waker.wake();
+      }
+   }
+}
+
+// IMPORTS //
+
+use {
+   crate::arch::without_interrupts,
+   core::{
+      future::Future,
+      pin::Pin,
+      task::{Context, Poll, Waker},
+   },
+   spinning_top::Spinlock,
+   std_alloc::collections::VecDeque,
+};
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::test::poll_once,
+      core::pin::pin,
+   };
+
+   #[test]
+   fn notify_one_is_remembered() {
+      let notify = Notify::new();
+      notify.notify_one();
+
+      assert!(poll_once(pin!(notify.notified())).is_ready());
+      assert!(poll_once(pin!(notify.notified())).is_pending());
+   }
+
+   #[test]
+   fn dropped_waiters_pass_notifications_on() {
+      let notify = Notify::new();
+      let mut second = pin!(notify.notified());
+
+      {
+         let mut first = pin!(notify.notified());
+         assert!(poll_once(first.as_mut()).is_pending());
+         assert!(poll_once(second.as_mut()).is_pending());
+         notify.notify_one();
+      }
+
+      assert!(poll_once(second.as_mut()).is_ready());
+   }
+
+   #[test]
+   fn notify_waiters_wakes_only_current_waiters() {
+      let notify = Notify::new();
+      let mut waiting = pin!(notify.notified());
+      assert!(poll_once(waiting.as_mut()).is_pending());
+
+      notify.notify_waiters();
+      assert!(poll_once(waiting.as_mut()).is_ready());
+      assert!(poll_once(pin!(notify.notified())).is_pending());
+   }
+
+   #[test]
+   fn notify_waiters_wakes_more_than_a_batch() {
+      let notify = Notify::new();
+      let mut waiting: std::vec::Vec<_> = (0..WAKE_BATCH * 2 + 1).map(|_| Box::pin(notify.notified())).collect();
+      for waiter in waiting.iter_mut() {
+         assert!(poll_once(waiter.as_mut()).is_pending());
+      }
+
+      notify.notify_waiters();
+      assert!(waiting.iter_mut().all(|waiter| poll_once(waiter.as_mut()).is_ready()));
+   }
+}
@@ -0,0 +1,154 @@
+/// Creates a channel that carries a single value.
+///
+/// Sending never blocks or allocates, so the sender may live in an interrupt handler.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+   let shared = Arc::new(Shared{
+      value: Spinlock::new(None),
+      complete: AtomicBool::new(false),
+      waker: AtomicWaker::new(),
+   });
+
+   return (Sender{ shared: shared.clone() }, Receiver{ shared });
+}
+
+struct Shared<T> {
+   value: Spinlock<Option<T>>,
+
+   /// Set once the sender has sent or been dropped.
+   complete: AtomicBool,
+   waker: AtomicWaker,
+}
+
+/// Sends the value of a [`channel`].
+pub struct Sender<T> {
+   shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+   /// Sends `value`, handing it back if the receiver is gone.
+   pub fn send(self, value: T) -> Result<(), T> {
+      if Arc::strong_count(&self.shared) == 1 {
+         return Err(value);
+      }
+
+      without_interrupts(|| *self.shared.value.lock() = Some(value));
+      return Ok(());
+   }
+
+   /// Whether the receiver has been dropped.
+   pub fn is_closed(&self) -> bool {
+      return Arc::strong_count(&self.shared) == 1;
+   }
+}
+
+impl<T> Drop for Sender<T> {
+   fn drop(&mut self) {
+      self.shared.complete.store(true, Ordering::Release);
+      self.shared.waker.wake();
+   }
+}
+
+/// Receives the value of a [`channel`]; await it for the value.
+pub struct Receiver<T> {
+   shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+   /// Takes the value if it has been sent.
+   pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+      if !self.shared.complete.load(Ordering::Acquire) {
+         return Err(TryRecvError::Empty);
+      }
+
+      return without_interrupts(|| self.shared.value.lock().take()).ok_or(TryRecvError::Closed);
+   }
+}
+
+impl<T> Future for Receiver<T> {
+   type Output = Result<T, RecvError>;
+
+   fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+      if !self.shared.complete.load(Ordering::Acquire) {
+         self.shared.waker.register(cx.waker());
+
+         if !self.shared.complete.load(Ordering::Acquire) {
+            return Poll::Pending;
+         }
+      }
+
+      return Poll::Ready(match self.try_recv() {
+         Ok(value) => Ok(value),
+         Err(_) => Err(RecvError),
+      });
+   }
+}
+
+/// Error returned when the sender was dropped without sending.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RecvError;
+
+impl Display for RecvError {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return write!(f, "sender dropped without sending");
+   }
+}
+
+/// Errors returned by [`Receiver::try_recv`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TryRecvError {
+   /// Nothing has been sent yet.
+   Empty,
+
+   /// The sender was dropped without sending, or the value was already taken.
+   Closed,
+}
+
+impl Display for TryRecvError {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return match self {
+         TryRecvError::Empty => write!(f, "nothing sent yet"),
+         TryRecvError::Closed => write!(f, "channel closed"),
+      };
+   }
+}
+
+// IMPORTS //
+
+use {
+   crate::arch::without_interrupts,
+   core::{
+      fmt::{self, Display},
+      future::Future,
+      pin::Pin,
+      sync::atomic::{AtomicBool, Ordering},
+      task::{Context, Poll},
+   },
+   futures_util::task::AtomicWaker,
+   spinning_top::Spinlock,
+   std_alloc::sync::Arc,
+};
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::test::poll_once,
+   };
+
+   #[test]
+   fn values_and_drops_reach_the_receiver() {
+      let (sender, mut receiver) = channel();
+      assert!(poll_once(Pin::new(&mut receiver)).is_pending());
+
+      sender.send(7).unwrap();
+      assert_eq!(poll_once(Pin::new(&mut receiver)), Poll::Ready(Ok(7)));
+
+      let (sender, mut receiver) = channel::<u8>();
+      drop(sender);
+      assert_eq!(receiver.try_recv(), Err(TryRecvError::Closed));
+
+      let (sender, receiver) = channel();
+      drop(receiver);
+      assert_eq!(sender.send(1), Err(1));
+   }
+}
@@ -0,0 +1,155 @@
+/// A reader-writer lock that suspends waiting tasks instead of spinning.
+///
+/// Readers and writers are served in arrival order, so a stream of readers cannot starve a
+/// writer.
+pub struct RwLock<T: ?Sized> {
+   semaphore: Semaphore,
+   value: UnsafeCell<T>,
+}
+
+/// Number of readers that may hold the lock at once; a writer takes all of them.
+const MAX_READERS: usize = Semaphore::MAX_PERMITS;
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+   /// Creates an unlocked lock holding `value`.
+   pub const fn new(value: T) -> Self {
+      return RwLock{ semaphore: Semaphore::new(MAX_READERS), value: UnsafeCell::new(value) };
+   }
+
+   /// Consumes the lock, returning the value.
+   pub fn into_inner(self) -> T {
+      return self.value.into_inner();
+   }
+}
+
+impl<T: ?Sized> RwLock<T> {
+   /// Waits for shared access.
+   pub async fn read(&self) -> RwLockReadGuard<'_, T> {
+      acquire(&self.semaphore, 1).await;
+      return RwLockReadGuard{ lock: self };
+   }
+
+   /// Waits for exclusive access.
+   pub async fn write(&self) -> RwLockWriteGuard<'_, T> {
+      acquire(&self.semaphore, MAX_READERS).await;
+      return RwLockWriteGuard{ lock: self };
+   }
+
+   /// Takes shared access if it is free and no writer is waiting.
+   pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+      self.semaphore.try_acquire_many(1).ok()?.forget();
+      return Some(RwLockReadGuard{ lock: self });
+   }
+
+   /// Takes exclusive access if nobody holds or is waiting for the lock.
+   pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+      self.semaphore.try_acquire_many(MAX_READERS).ok()?.forget();
+      return Some(RwLockWriteGuard{ lock: self });
+   }
+
+   /// A mutable reference to the value; no locking is needed since the borrow is exclusive.
+   pub fn get_mut(&mut self) -> &mut T {
+      return self.value.get_mut();
+   }
+}
+
+async fn acquire(semaphore: &Semaphore, permits: usize) {
+   match semaphore.acquire_many(permits).await {
+      Ok(permit) => permit.forget(),
+      Err(_) => unreachable!("lock semaphores are never closed"),
+   }
+}
+
+/// Shared access to an [`RwLock`]'s value, released when dropped.
+#[must_use = "the lock is released as soon as the guard is dropped"]
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+   lock: &'a RwLock<T>,
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+   type Target = T;
+
+   fn deref(&self) -> &T {
+      return unsafe { &*self.lock.value.get() };
+   }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+   fn drop(&mut self) {
+      self.lock.semaphore.add_permits(1);
+   }
+}
+
+/// Exclusive access to an [`RwLock`]'s value, released when dropped.
+#[must_use = "the lock is released as soon as the guard is dropped"]
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+   lock: &'a RwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for RwLockWriteGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+   type Target = T;
+
+   fn deref(&self) -> &T {
+      return unsafe { &*self.lock.value.get() };
+   }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+   fn deref_mut(&mut self) -> &mut T {
+      return unsafe { &mut *self.lock.value.get() };
+   }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+   fn drop(&mut self) {
+      self.lock.semaphore.add_permits(MAX_READERS);
+   }
+}
+
+// IMPORTS //
+
+use {
+   super::Semaphore,
+   core::{
+      cell::UnsafeCell,
+      ops::{Deref, DerefMut},
+   },
+};
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::test::poll_once,
+      core::pin::pin,
+   };
+
+   #[test]
+   fn readers_share_and_writers_wait() {
+      let lock = RwLock::new(1);
+      let first = lock.try_read().unwrap();
+      let second = lock.try_read().unwrap();
+      assert_eq!(*first + *second, 2);
+
+      let mut writer = pin!(lock.write());
+      assert!(poll_once(writer.as_mut()).is_pending());
+
+      // A queued writer holds back new readers.
+      assert!(lock.try_read().is_none());
+
+      drop(first);
+      assert!(poll_once(writer.as_mut()).is_pending());
+      drop(second);
+
+      let core::task::Poll::Ready(mut guard) = poll_once(writer.as_mut()) else { panic!("writer starved") };
+      *guard = 5;
+      drop(guard);
+
+      assert_eq!(*lock.try_read().unwrap(), 5);
+   }
+}
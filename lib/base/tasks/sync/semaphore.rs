@@ -0,0 +1,363 @@
+/// A counting semaphore whose waiters are served in the order they arrived.
+///
+/// Permits can be added from interrupt handlers; only acquiring needs a task.
+pub struct Semaphore {
+   state: Spinlock<State>,
+}
+
+struct State {
+   permits: usize,
+   closed: bool,
+   waiters: VecDeque<Waiter>,
+   next_id: u64,
+}
+
+struct Waiter {
+   id: u64,
+   needed: usize,
+   waker: Waker,
+}
+
+impl Semaphore {
+   /// The most permits a semaphore can hold.
+   pub const MAX_PERMITS: usize = usize::MAX >> 3;
+
+   /// Creates a semaphore holding `permits` permits.
+   pub const fn new(permits: usize) -> Self {
+      assert!(permits <= Self::MAX_PERMITS, "too many permits");
+
+      return Semaphore{
+         state: Spinlock::new(State{
+            permits,
+            closed: false,
+            waiters: VecDeque::new(),
+            next_id: 0,
+         }),
+      };
+   }
+
+   /// Number of permits free right now.
+   pub fn available_permits(&self) -> usize {
+      return without_interrupts(|| self.state.lock().permits);
+   }
+
+   /// Waits for one permit.
+   pub fn acquire(&self) -> Acquire<'_> {
+      return self.acquire_many(1);
+   }
+
+   /// Waits for `permits` permits, taken all at once.
+   ///
+   /// Panics if `permits` exceeds [`MAX_PERMITS`](Self::MAX_PERMITS), since the wait could
+   /// never end.
+   pub fn acquire_many(&self, permits: usize) -> Acquire<'_> {
+      assert!(permits <= Self::MAX_PERMITS, "too many permits");
+
+      return Acquire{ semaphore: self, needed: permits, id: None, done: false };
+   }
+
+   /// Takes `permits` permits if they are free and nobody is queued ahead.
+   pub fn try_acquire_many(&self, permits: usize) -> Result<SemaphorePermit<'_>, TryAcquireError> {
+      return without_interrupts(|| {
+         let mut state = self.state.lock();
+
+         if state.closed {
+            return Err(TryAcquireError::Closed);
+         }
+
+         if !state.waiters.is_empty() || state.permits < permits {
+            return Err(TryAcquireError::NoPermits);
+         }
+
+         state.permits -= permits;
+         return Ok(SemaphorePermit{ semaphore: self, permits });
+      });
+   }
+
+   /// Takes one permit if it is free and nobody is queued ahead.
+   pub fn try_acquire(&self) -> Result<SemaphorePermit<'_>, TryAcquireError> {
+      return self.try_acquire_many(1);
+   }
+
+   /// Returns `permits` permits to the semaphore, waking the first waiter if it can now proceed.
+   ///
+   /// Never allocates, so interrupt handlers may call it.
+   pub fn add_permits(&self, permits: usize) {
+      let waker = without_interrupts(|| {
+         let mut state = self.state.lock();
+         state.permits = min(state.permits.saturating_add(permits), Self::MAX_PERMITS);
+         state.front_satisfied()
+      });
+
+      if let Some(waker) = waker {
+         waker.wake();
+      }
+   }
+
+   /// Fails every current and future acquire; permits already held stay valid.
+   pub fn close(&self) {
+      let waiters = without_interrupts(|| {
+         let mut state = self.state.lock();
+         state.closed = true;
+         mem::take(&mut state.waiters)
+      });
+
+      for waiter in waiters {
+         waiter.waker.wake();
+      }
+   }
+
+   /// Whether [`close`](Self::close) has been called.
+   pub fn is_closed(&self) -> bool {
+      return without_interrupts(|| self.state.lock().closed);
+   }
+}
+
+impl State {
+   /// The first waiter's waker, if the free permits cover what it asked for.
+   fn front_satisfied(&self) -> Option<Waker> {
+      return self.waiters.front()
+         .filter(|waiter| waiter.needed <= self.permits)
+         .map(|waiter| waiter.waker.clone());
+   }
+}
+
+/// Future returned by [`Semaphore::acquire`] and [`Semaphore::acquire_many`].
+///
+/// Dropping it gives up its place in the queue.
+pub struct Acquire<'a> {
+   semaphore: &'a Semaphore,
+   needed: usize,
+
+   /// Our entry in the waiter queue, once queued.
+   id: Option<u64>,
+   done: bool,
+}
+
+impl<'a> Future for Acquire<'a> {
+   type Output = Result<SemaphorePermit<'a>, AcquireError>;
+
+   fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+      let semaphore = self.semaphore;
+      let needed = self.needed;
+
+      let (result, next) = without_interrupts(|| {
+         let mut state = semaphore.state.lock();
+
+         if state.closed {
+            return (Some(Err(AcquireError)), None);
+         }
+
+         let first = match (state.waiters.front(), self.id) {
+            (None, _) => true,
+            (Some(waiter), Some(id)) => waiter.id == id,
+            (Some(_), None) => false,
+         };
+
+         if first && state.permits >= needed {
+            state.permits -= needed;
+            if self.id.is_some() {
+               state.waiters.pop_front();
+            }
+
+            // Whoever is next may be satisfied by what is left.
+            let next = state.front_satisfied();
+            return (Some(Ok(SemaphorePermit{ semaphore, permits: needed })), next);
+         }
+
+         match self.id {
+            Some(id) => {
+               if let Some(waiter) = state.waiters.iter_mut().find(|waiter| waiter.id == id) {
+                  if !waiter.waker.will_wake(cx.waker()) {
+                     waiter.waker = cx.waker().clone();
+                  }
+               }
+            }
+            None => {
+               let id = state.next_id;
+               state.next_id += 1;
+               state.waiters.push_back(Waiter{ id, needed, waker: cx.waker().clone() });
+               self.id = Some(id);
+            }
+         }
+
+         return (None, None);
+      });
+
+      if let Some(waker) = next {
+         waker.wake();
+      }
+
+      return match result {
+         Some(result) => {
+            self.done = true;
+            self.id = None;
+            Poll::Ready(result)
+         }
+         None => Poll::Pending,
+      };
+   }
+}
+
+impl Drop for Acquire<'_> {
+   fn drop(&mut self) {
+      let id = match (self.done, self.id) {
+         (false, Some(id)) => id,
+         _ => return,
+      };
+
+      // If we were at the front, the permits we were waiting on may suit whoever is next.
+      let next = without_interrupts(|| {
+         let mut state = self.semaphore.state.lock();
+         let position = state.waiters.iter().position(|waiter| waiter.id == id)?;
+         state.waiters.remove(position);
+
+         return match position {
+            0 => state.front_satisfied(),
+            _ => None,
+         };
+      });
+
+      if let Some(waker) = next {
+         waker.wake();
+      }
+   }
+}
+
+/// Permits taken from a [`Semaphore`], returned when dropped.
+#[must_use = "the permits are returned as soon as this is dropped"]
+pub struct SemaphorePermit<'a> {
+   semaphore: &'a Semaphore,
+   permits: usize,
+}
+
+impl SemaphorePermit<'_> {
+   /// Number of permits held.
+   pub fn permits(&self) -> usize {
+      return self.permits;
+   }
+
+   /// Keeps the permits out of the semaphore for good.
+   pub fn forget(mut self) {
+      self.permits = 0;
+   }
+}
+
+impl Drop for SemaphorePermit<'_> {
+   fn drop(&mut self) {
+      if self.permits > 0 {
+         self.semaphore.add_permits(self.permits);
+      }
+   }
+}
+
+/// Error returned when acquiring from a closed [`Semaphore`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AcquireError;
+
+impl Display for AcquireError {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return write!(f, "semaphore closed");
+   }
+}
+
+/// Errors returned by [`Semaphore::try_acquire`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TryAcquireError {
+   /// Not enough permits are free, or other tasks are queued first.
+   NoPermits,
+
+   /// The semaphore has been closed.
+   Closed,
+}
+
+impl Display for TryAcquireError {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return match self {
+         TryAcquireError::NoPermits => write!(f, "no permits available"),
+         TryAcquireError::Closed => write!(f, "semaphore closed"),
+      };
+   }
+}
+
+// IMPORTS //
+
+use {
+   crate::arch::without_interrupts,
+   core::{
+      cmp::min,
+      fmt::{self, Display},
+      future::Future,
+      mem,
+      pin::Pin,
+      task::{Context, Poll, Waker},
+   },
+   spinning_top::Spinlock,
+   std_alloc::collections::VecDeque,
+};
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::test::poll_once,
+      core::pin::pin,
+   };
+
+   #[test]
+   fn waiters_are_served_in_order() {
+      let semaphore = Semaphore::new(1);
+      let held = semaphore.try_acquire().unwrap();
+
+      let mut big = pin!(semaphore.acquire_many(2));
+      let mut small = pin!(semaphore.acquire());
+      assert!(poll_once(big.as_mut()).is_pending());
+      assert!(poll_once(small.as_mut()).is_pending());
+
+      // The small request must not jump the queue even though a permit frees up.
+      drop(held);
+      assert!(poll_once(small.as_mut()).is_pending());
+      assert_eq!(semaphore.try_acquire().err(), Some(TryAcquireError::NoPermits));
+
+      semaphore.add_permits(1);
+      let Poll::Ready(Ok(permit)) = poll_once(big.as_mut()) else { panic!("big waiter starved") };
+      assert_eq!(permit.permits(), 2);
+      drop(permit);
+
+      assert!(matches!(poll_once(small.as_mut()), Poll::Ready(Ok(_))));
+   }
+
+   #[test]
+   fn dropped_waiters_leave_the_queue() {
+      let semaphore = Semaphore::new(0);
+
+      {
+         let mut first = pin!(semaphore.acquire());
+         assert!(poll_once(first.as_mut()).is_pending());
+      }
+
+      semaphore.add_permits(1);
+      let permit = semaphore.try_acquire().unwrap();
+      permit.forget();
+      assert_eq!(semaphore.available_permits(), 0);
+
+      let mut waiting = pin!(semaphore.acquire());
+      assert!(poll_once(waiting.as_mut()).is_pending());
+      semaphore.close();
+      assert!(matches!(poll_once(waiting.as_mut()), Poll::Ready(Err(AcquireError))));
+   }
+
+   #[test]
+   fn added_permits_saturate_at_the_maximum() {
+      let semaphore = Semaphore::new(Semaphore::MAX_PERMITS);
+      semaphore.add_permits(usize::MAX);
+      assert_eq!(semaphore.available_permits(), Semaphore::MAX_PERMITS);
+   }
+
+   #[test]
+   #[should_panic(expected = "too many permits")]
+   fn acquiring_more_than_the_maximum_panics() {
+      let semaphore = Semaphore::new(0);
+      let _ = semaphore.acquire_many(Semaphore::MAX_PERMITS + 1);
+   }
+}
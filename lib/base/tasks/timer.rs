@@ -0,0 +1,337 @@
+/// Pending timers, earliest deadline at the top.
+static TIMERS: Spinlock<BinaryHeap<Entry>> = Spinlock::new(BinaryHeap::new());
+
+/// Deadline of the earliest pending timer in nanoseconds of uptime, or `u64::MAX` if none, so
+/// the timer interrupt can skip the lock when nothing is due.
+static NEXT_DEADLINE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// Identifies the next queued timer, so a [`Sleep`] can find its entry again to cancel it.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Wakes every task whose timer has expired; called from the timer interrupt.
+pub fn wake_expired() {
+   let now = time::uptime();
+   if nanos(now) < NEXT_DEADLINE.load(Ordering::Acquire) {
+      return;
+   }
+
+   // Wake outside the lock to keep the time spent with interrupts disabled short.
+   while let Some(waker) = pop_expired(now) {
+      waker.wake();
+   }
+}
+
+/// Uptime at which the earliest pending timer expires.
+pub fn next_deadline() -> Option<Duration> {
+   return match NEXT_DEADLINE.load(Ordering::Acquire) {
+      u64::MAX => None,
+      deadline => Some(Duration::from_nanos(deadline)),
+   };
+}
+
+/// Completes once `duration` has passed.
+pub fn sleep(duration: Duration) -> Sleep {
+   return sleep_until(time::uptime().saturating_add(duration));
+}
+
+/// Completes once the uptime reaches `deadline`.
+pub fn sleep_until(deadline: Duration) -> Sleep {
+   return Sleep{ deadline, queued: None };
+}
+
+/// Runs `future`, giving up with [`Elapsed`] if it has not completed within `duration`.
+pub fn timeout<F: Future>(future: F, duration: Duration) -> Timeout<F> {
+   return Timeout{ future, sleep: sleep(duration) };
+}
+
+/// A stream yielding the uptime of every tick, one `period` apart, starting one `period` from
+/// now.
+///
+/// Ticks missed because the consumer fell behind are skipped rather than delivered in a burst.
+pub fn interval(period: Duration) -> Interval {
+   assert!(period > Duration::ZERO, "interval period must be non-zero");
+   return Interval{ period, sleep: sleep(period) };
+}
+
+fn pop_expired(now: Duration) -> Option<Waker> {
+   return without_interrupts(|| {
+      let mut timers = TIMERS.lock();
+      let waker = match timers.peek() {
+         Some(entry) if entry.deadline <= now => timers.pop().map(|entry| entry.waker),
+         _ => None,
+      };
+
+      update_next_deadline(&timers);
+      return waker;
+   });
+}
+
+fn schedule(deadline: Duration, waker: Waker) -> u64 {
+   let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+   without_interrupts(|| {
+      let mut timers = TIMERS.lock();
+      timers.push(Entry{ deadline, id, waker });
+      update_next_deadline(&timers);
+   });
+
+   return id;
+}
+
+/// Removes the timer `id` if it has not fired yet.
+fn cancel(id: u64) {
+   without_interrupts(|| {
+      let mut timers = TIMERS.lock();
+      timers.retain(|entry| entry.id != id);
+      update_next_deadline(&timers);
+   });
+}
+
+fn update_next_deadline(timers: &BinaryHeap<Entry>) {
+   let next = timers.peek().map_or(u64::MAX, |entry| nanos(entry.deadline));
+   NEXT_DEADLINE.store(next, Ordering::Release);
+}
+
+fn nanos(duration: Duration) -> u64 {
+   return u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+}
+
+/// A queued timer, ordered so the earliest deadline is the greatest.
+struct Entry {
+   deadline: Duration,
+   id: u64,
+   waker: Waker,
+}
+
+impl PartialEq for Entry {
+   fn eq(&self, other: &Self) -> bool {
+      return self.deadline == other.deadline;
+   }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+   fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+      return Some(self.cmp(other));
+   }
+}
+
+impl Ord for Entry {
+   fn cmp(&self, other: &Self) -> cmp::Ordering {
+      return other.deadline.cmp(&self.deadline);
+   }
+}
+
+/// Future returned by [`sleep`] and [`sleep_until`].
+///
+/// Dropping it cancels its timer.
+#[derive(Debug)]
+pub struct Sleep {
+   deadline: Duration,
+
+   /// Our queued timer and the waker it holds, so repeated polls from the same task queue it
+   /// only once.
+   queued: Option<(u64, Waker)>,
+}
+
+impl Sleep {
+   /// The uptime at which this completes.
+   pub fn deadline(&self) -> Duration {
+      return self.deadline;
+   }
+
+   /// Moves the deadline, for reuse without allocating a new timer.
+   pub fn reset(&mut self, deadline: Duration) {
+      self.cancel();
+      self.deadline = deadline;
+   }
+
+   fn cancel(&mut self) {
+      if let Some((id, _)) = self.queued.take() {
+         cancel(id);
+      }
+   }
+}
+
+impl Future for Sleep {
+   type Output = ();
+
+   fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+      if time::uptime() >= self.deadline {
+         self.cancel();
+         return Poll::Ready(());
+      }
+
+      let queued = matches!(&self.queued, Some((_, waker)) if waker.will_wake(cx.waker()));
+      if !queued {
+         self.cancel();
+         let id = schedule(self.deadline, cx.waker().clone());
+         self.queued = Some((id, cx.waker().clone()));
+      }
+
+      // The deadline may have passed between the check and queueing the timer.
+      if time::uptime() >= self.deadline {
+         self.cancel();
+         return Poll::Ready(());
+      }
+
+      return Poll::Pending;
+   }
+}
+
+impl Drop for Sleep {
+   fn drop(&mut self) {
+      self.cancel();
+   }
+}
+
+/// Future returned by [`timeout`].
+///
+/// Dropping it cancels its timer along with the wrapped future.
+pub struct Timeout<F> {
+   future: F,
+   sleep: Sleep,
+}
+
+impl<F: Future> Future for Timeout<F> {
+   type Output = Result<F::Output, Elapsed>;
+
+   fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+      // Safety: `future` is never moved out of the pinned `Timeout`.
+      let this = unsafe { self.get_unchecked_mut() };
+      let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+      if let Poll::Ready(output) = future.poll(cx) {
+         return Poll::Ready(Ok(output));
+      }
+
+      return match Pin::new(&mut this.sleep).poll(cx) {
+         Poll::Ready(()) => Poll::Ready(Err(Elapsed)),
+         Poll::Pending => Poll::Pending,
+      };
+   }
+}
+
+/// Stream returned by [`interval`].
+#[derive(Debug)]
+pub struct Interval {
+   period: Duration,
+   sleep: Sleep,
+}
+
+impl Interval {
+   /// The time between ticks.
+   pub fn period(&self) -> Duration {
+      return self.period;
+   }
+}
+
+impl Stream for Interval {
+   type Item = Duration;
+
+   fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+      if Pin::new(&mut self.sleep).poll(cx).is_pending() {
+         return Poll::Pending;
+      }
+
+      let tick = self.sleep.deadline();
+      let now = time::uptime();
+
+      let mut next = tick.saturating_add(self.period);
+      if next <= now {
+         let periods = (now - tick).as_nanos() / self.period.as_nanos() + 1;
+         let skipped = u64::try_from(periods * self.period.as_nanos()).unwrap_or(u64::MAX);
+         next = tick.saturating_add(Duration::from_nanos(skipped));
+      }
+
+      self.sleep.reset(next);
+      return Poll::Ready(Some(tick));
+   }
+}
+
+/// Error returned by [`Timeout`] when its deadline passes first.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl Display for Elapsed {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return write!(f, "deadline has elapsed");
+   }
+}
+
+// IMPORTS //
+
+use {
+   crate::{arch::without_interrupts, time},
+   core::{
+      cmp,
+      fmt::{self, Display},
+      future::Future,
+      pin::Pin,
+      sync::atomic::{AtomicU64, Ordering},
+      task::{Context, Poll, Waker},
+      time::Duration,
+   },
+   futures_util::stream::Stream,
+   spinning_top::Spinlock,
+   std_alloc::collections::BinaryHeap,
+};
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      futures_util::task::noop_waker_ref,
+   };
+
+   #[test]
+   fn entries_pop_earliest_first() {
+      let waker = noop_waker_ref();
+      let mut timers = BinaryHeap::new();
+
+      for millis in [30, 10, 20] {
+         timers.push(Entry{ deadline: Duration::from_millis(millis), id: millis, waker: waker.clone() });
+      }
+
+      let order: Vec<_> = core::iter::from_fn(|| timers.pop().map(|entry| entry.deadline.as_millis())).collect();
+      assert_eq!(order, [10, 20, 30]);
+   }
+
+   #[test]
+   fn expired_sleeps_are_ready() {
+      let mut context = Context::from_waker(noop_waker_ref());
+      let mut sleep = sleep_until(Duration::ZERO);
+      assert_eq!(Pin::new(&mut sleep).poll(&mut context), Poll::Ready(()));
+
+      let mut never = sleep_until(Duration::MAX);
+      assert_eq!(Pin::new(&mut never).poll(&mut context), Poll::Pending);
+
+      let mut elapsed = timeout(core::future::pending::<()>(), Duration::ZERO);
+      assert_eq!(Pin::new(&mut elapsed).poll(&mut context), Poll::Ready(Err(Elapsed)));
+
+      let mut ready = timeout(core::future::ready(7), Duration::MAX);
+      assert_eq!(Pin::new(&mut ready).poll(&mut context), Poll::Ready(Ok(7)));
+   }
+
+   #[test]
+   fn dropped_sleeps_leave_the_queue() {
+      let queued = |id| without_interrupts(|| TIMERS.lock().iter().any(|entry| entry.id == id));
+      let mut context = Context::from_waker(noop_waker_ref());
+
+      let mut sleep = sleep_until(Duration::MAX);
+      assert_eq!(Pin::new(&mut sleep).poll(&mut context), Poll::Pending);
+      let (id, _) = sleep.queued.clone().unwrap();
+      assert!(queued(id));
+
+      drop(sleep);
+      assert!(!queued(id));
+
+      let mut pending = timeout(core::future::pending::<()>(), Duration::from_secs(3600));
+      assert_eq!(Pin::new(&mut pending).poll(&mut context), Poll::Pending);
+      let (id, _) = pending.sleep.queued.clone().unwrap();
+
+      drop(pending);
+      assert!(!queued(id));
+   }
+}
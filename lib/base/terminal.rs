@@ -55,6 +55,23 @@ impl LockedWriter {
       };
    }
 
+   /// Writes `bytes` as they are, without requiring them to be valid UTF-8.
+   ///
+   /// The serial port receives the bytes unchanged; the framebuffer decodes them, showing
+   /// invalid sequences as a replacement character.
+   pub fn write_bytes(&self, bytes: &[u8]) {
+      if let Some(writer) = &self.writer {
+         writer.lock().write_bytes(bytes);
+      }
+
+      if let Some(serial) = &self.serial {
+         let mut serial = serial.lock();
+         for &byte in bytes {
+            serial.send(byte);
+         }
+      }
+   }
+
    /// Force-unlocks the logger to prevent a deadlock.
    ///
    /// ## Safety
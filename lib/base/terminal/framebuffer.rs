@@ -28,6 +28,8 @@ pub struct TerminalWriter {
    info: FrameBufferInfo,
    xpos: usize,
    ypos: usize,
+   pending: [u8; 4],
+   pendingLength: usize,
 }
 
 impl TerminalWriter {
@@ -38,6 +40,8 @@ impl TerminalWriter {
          info,
          xpos: 0,
          ypos: 0,
+         pending: [0; 4],
+         pendingLength: 0,
       };
       logger.clear();
       return logger;
@@ -69,6 +73,16 @@ impl TerminalWriter {
       self.info.height
    }
 
+   /// Number of characters that fit on one line.
+   pub fn columns(&self) -> usize {
+      return self.width().saturating_sub(2 * BORDER_PADDING) / (CHAR_RASTER_WIDTH + LETTER_SPACING);
+   }
+
+   /// Number of lines that fit on the screen.
+   pub fn rows(&self) -> usize {
+      return self.height().saturating_sub(2 * BORDER_PADDING) / (CHAR_RASTER_HEIGHT.val() + LINE_SPACING);
+   }
+
    /// Writes a single char to the framebuffer. Takes care of special control characters, such as
    /// newlines and carriage returns.
    pub fn write_char(&mut self, c: char) {
@@ -92,6 +106,52 @@ impl TerminalWriter {
       }
    }
 
+   /// Writes raw bytes to the framebuffer, decoding them as UTF-8.
+   ///
+   /// A character split across calls is held back until its remaining bytes arrive; bytes that
+   /// cannot form a character are shown as [`BACKUP_CHAR`].
+   pub fn write_bytes(&mut self, bytes: &[u8]) {
+      for &byte in bytes {
+         let continuation = byte & 0xC0 == 0x80;
+
+         if self.pendingLength > 0 && !continuation {
+            self.pendingLength = 0;
+            self.write_char(BACKUP_CHAR);
+         }
+
+         if self.pendingLength == 0 {
+            match byte {
+               0x00..=0x7F => {
+                  self.write_char(byte as char);
+                  continue;
+               }
+               0xC2..=0xF4 => {}
+               _ => {
+                  self.write_char(BACKUP_CHAR);
+                  continue;
+               }
+            }
+         }
+
+         self.pending[self.pendingLength] = byte;
+         self.pendingLength += 1;
+
+         match core::str::from_utf8(&self.pending[..self.pendingLength]) {
+            Ok(text) => {
+               self.pendingLength = 0;
+               if let Some(c) = text.chars().next() {
+                  self.write_char(c);
+               }
+            }
+            Err(error) if error.error_len().is_some() => {
+               self.pendingLength = 0;
+               self.write_char(BACKUP_CHAR);
+            }
+            Err(_) => {}
+         }
+      }
+   }
+
    /// Prints a rendered char into the framebuffer.
    /// Updates `self.xpos`.
    pub fn write_rendered_char(&mut self, rendered_char: RasterizedChar) {
@@ -5,3 +5,214 @@ pub fn test_runner(tests: &[&dyn Fn()]) {
       test();
    }
 }
+
+/// Size of the host memory handed to the kernel heap in test builds.
+#[cfg(all(test, feature = "allocators"))]
+pub const HOST_HEAP_SIZE: usize = 16 * 1024 * 1024;
+
+/// Backs the kernel heap with host memory so [`GlobalAllocator`](crate::alloc::GlobalAllocator)
+/// users like [`Array`](crate::array::Array) and [`String`](crate::string::String) can run under
+/// `cargo test`.
+///
+/// Safe to call from every test; the heap is only initialised once.
+#[cfg(all(test, feature = "allocators"))]
+pub fn init_host_heap() {
+   static INIT: std::sync::Once = std::sync::Once::new();
+
+   INIT.call_once(|| {
+      let arena = HostArena::new(HOST_HEAP_SIZE);
+      unsafe { crate::alloc::heap::init_heap(arena.start, arena.end()) }.unwrap();
+
+      // The heap outlives every test.
+      std::mem::forget(arena);
+   });
+}
+
+/// A small xorshift generator for property tests.
+///
+/// Deterministic for a given seed, so a failing case can be replayed.
+#[cfg(test)]
+pub struct Random(u64);
+
+#[cfg(test)]
+impl Random {
+   /// Creates a generator from `seed`.
+   pub fn new(seed: u64) -> Self {
+      return Random(seed | 1);
+   }
+
+   /// The next 64 random bits.
+   pub fn next(&mut self) -> u64 {
+      self.0 ^= self.0 << 13;
+      self.0 ^= self.0 >> 7;
+      self.0 ^= self.0 << 17;
+      return self.0;
+   }
+
+   /// A value in `0..bound`.
+   pub fn below(&mut self, bound: usize) -> usize {
+      return (self.next() % bound as u64) as usize;
+   }
+
+   /// A value whose bit length is itself uniformly distributed, so small and large magnitudes
+   /// are covered equally.
+   pub fn magnitude(&mut self) -> usize {
+      let bits = self.below(usize::BITS as usize + 1);
+      return match bits {
+         0 => 0,
+         bits => (self.next() as usize) >> (usize::BITS as usize - bits),
+      };
+   }
+}
+
+/// Page-aligned host memory for tests that drive a heap directly.
+#[cfg(test)]
+pub struct HostArena {
+   /// First byte of the arena.
+   pub start: usize,
+
+   /// Size of the arena, in bytes.
+   pub size: usize,
+}
+
+#[cfg(test)]
+impl HostArena {
+   /// Reserves `size` bytes of zeroed host memory.
+   pub fn new(size: usize) -> Self {
+      let start = unsafe { std::alloc::alloc_zeroed(Self::layout(size)) } as usize;
+      assert_ne!(start, 0, "unable to reserve a host arena");
+
+      return HostArena{ start, size };
+   }
+
+   /// One past the last byte of the arena.
+   pub fn end(&self) -> usize {
+      return self.start + self.size;
+   }
+
+   fn layout(size: usize) -> std::alloc::Layout {
+      return std::alloc::Layout::from_size_align(size, 4096).unwrap();
+   }
+}
+
+#[cfg(test)]
+impl Drop for HostArena {
+   fn drop(&mut self) {
+      unsafe { std::alloc::dealloc(self.start as *mut u8, Self::layout(self.size)) };
+   }
+}
+
+/// A live allocation made by a property test, filled with `fill` so overlapping blocks are
+/// caught when either is checked.
+#[cfg(all(test, feature = "allocators"))]
+pub struct Live {
+   /// Start of the allocation.
+   pub pointer: core::ptr::NonNull<u8>,
+
+   /// Layout the allocation was made with.
+   pub layout: crate::alloc::Layout,
+
+   /// Byte every location in the allocation should hold.
+   pub fill: u8,
+}
+
+#[cfg(all(test, feature = "allocators"))]
+impl Live {
+   /// Fills the allocation at `pointer` and starts tracking it.
+   pub fn new(pointer: core::ptr::NonNull<u8>, layout: crate::alloc::Layout, fill: u8) -> Self {
+      unsafe { core::ptr::write_bytes(pointer.as_ptr(), fill, layout.size()) };
+      return Live{ pointer, layout, fill };
+   }
+
+   /// Panics if anything else has written to the allocation.
+   pub fn check(&self) {
+      let bytes = unsafe { core::slice::from_raw_parts(self.pointer.as_ptr(), self.layout.size()) };
+      assert!(bytes.iter().all(|&byte| byte == self.fill), "allocation at {:p} was overwritten", self.pointer);
+   }
+}
+
+/// A heap that [`random_allocations`] can drive.
+#[cfg(all(test, feature = "allocators"))]
+pub trait TestHeap {
+   /// Allocates a block for `layout`.
+   unsafe fn allocate(
+      &mut self,
+      layout: crate::alloc::Layout,
+   ) -> Result<core::ptr::NonNull<u8>, crate::alloc::AllocationError>;
+
+   /// Resizes the block at `pointer` to `new_size` bytes without moving it, if possible.
+   unsafe fn reallocate_in_place(
+      &mut self,
+      pointer: core::ptr::NonNull<u8>,
+      layout: crate::alloc::Layout,
+      new_size: usize,
+   ) -> bool;
+
+   /// Frees the block at `pointer`.
+   unsafe fn deallocate(&mut self, pointer: core::ptr::NonNull<u8>, layout: crate::alloc::Layout);
+
+   /// Bytes handed out, and bytes managed, by the heap.
+   fn usage(&self) -> (usize, usize);
+}
+
+/// Runs a seeded mix of allocations, in-place resizes and frees against `heap`, which manages
+/// exactly `arena`, then frees whatever is still live.
+///
+/// Panics if any block is misaligned, falls outside the arena or overlaps another.
+#[cfg(all(test, feature = "allocators"))]
+pub fn random_allocations<H: TestHeap>(heap: &mut H, arena: &HostArena, seed: u64) {
+   let mut random = Random::new(seed);
+   let mut live: std::vec::Vec<Live> = std::vec::Vec::new();
+
+   for step in 0..20_000 {
+      match random.below(8) {
+         0..=3 => {
+            let bits = random.below(14);
+            let size = 1 + random.below(1 << bits);
+            let layout = crate::alloc::Layout::from_size_align(size, 1 << random.below(13)).unwrap();
+
+            let Ok(pointer) = (unsafe { heap.allocate(layout) }) else { continue };
+            let address = pointer.as_ptr() as usize;
+
+            assert_eq!(address % layout.align, 0);
+            assert!(address >= arena.start && address + size <= arena.end());
+            live.push(Live::new(pointer, layout, step as u8));
+         }
+         4 if !live.is_empty() => {
+            let index = random.below(live.len());
+            let entry = &mut live[index];
+            let newSize = 1 + random.below(entry.layout.size * 2);
+
+            entry.check();
+            if unsafe { heap.reallocate_in_place(entry.pointer, entry.layout, newSize) } {
+               let layout = crate::alloc::Layout::from_size_align(newSize, entry.layout.align).unwrap();
+               *entry = Live::new(entry.pointer, layout, entry.fill);
+            }
+         }
+         _ if !live.is_empty() => {
+            let entry = live.swap_remove(random.below(live.len()));
+
+            entry.check();
+            unsafe { heap.deallocate(entry.pointer, entry.layout) };
+         }
+         _ => {}
+      }
+
+      let (allocated, total) = heap.usage();
+      assert!(allocated <= total);
+   }
+
+   for entry in live.drain(..) {
+      entry.check();
+      unsafe { heap.deallocate(entry.pointer, entry.layout) };
+   }
+}
+
+/// Polls `future` once with a waker that does nothing, for driving futures by hand.
+#[cfg(test)]
+pub fn poll_once<F: core::future::Future + ?Sized>(
+   future: core::pin::Pin<&mut F>,
+) -> core::task::Poll<F::Output> {
+   let waker = futures_util::task::noop_waker_ref();
+   return future.poll(&mut core::task::Context::from_waker(waker));
+}
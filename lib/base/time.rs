@@ -0,0 +1,146 @@
+/// Number of timer interrupts since boot.
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Length of one timer tick, in nanoseconds; zero until [`set_tick_frequency`] is called.
+static TICK_NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// Frequency of the high-resolution counter in Hz; zero until one is calibrated.
+static COUNTER_FREQUENCY: AtomicU64 = AtomicU64::new(0);
+
+/// Counter value corresponding to an uptime of zero.
+static COUNTER_BASE: AtomicU64 = AtomicU64::new(0);
+
+/// Reads the high-resolution counter, once one is registered with [`set_counter`].
+static COUNTER: Once<fn() -> u64> = Once::new();
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// Sets how often the timer interrupt calls [`tick`].
+pub fn set_tick_frequency(hz: u32) {
+   TICK_NANOS.store(NANOS_PER_SECOND / max(hz as u64, 1), Ordering::Relaxed);
+}
+
+/// The length of one timer tick, or zero before the timer is running.
+pub fn tick_length() -> Duration {
+   return Duration::from_nanos(TICK_NANOS.load(Ordering::Relaxed));
+}
+
+/// Advances the tick counter by one; called from the timer interrupt.
+///
+/// Must not block or allocate.
+pub fn tick() {
+   TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Number of timer ticks since the timer started.
+pub fn ticks() -> u64 {
+   return TICKS.load(Ordering::Relaxed);
+}
+
+/// Registers a free-running counter ticking at `frequency` Hz, such as the TSC.
+///
+/// Uptime is measured from the tick count so far, so switching to the counter does not make
+/// the clock jump.
+pub fn set_counter(read: fn() -> u64, frequency: u64) {
+   if frequency == 0 {
+      return;
+   }
+
+   let ticked = ticks_to_duration(ticks());
+   let elapsed = (ticked.as_nanos() * frequency as u128 / NANOS_PER_SECOND as u128) as u64;
+
+   let read = *COUNTER.call_once(|| read);
+   COUNTER_BASE.store(read().wrapping_sub(elapsed), Ordering::Relaxed);
+   COUNTER_FREQUENCY.store(frequency, Ordering::Release);
+}
+
+/// Frequency of the high-resolution counter, if one has been calibrated.
+pub fn counter_frequency() -> Option<u64> {
+   return match COUNTER_FREQUENCY.load(Ordering::Acquire) {
+      0 => None,
+      frequency => Some(frequency),
+   };
+}
+
+/// Raw value of the high-resolution counter, if one has been calibrated.
+pub fn timestamp() -> Option<u64> {
+   counter_frequency()?;
+   return COUNTER.get().map(|read| read());
+}
+
+/// Converts a difference between two [`timestamp`]s into a duration.
+pub fn timestamp_duration(cycles: u64) -> Duration {
+   return match counter_frequency() {
+      Some(frequency) => counter_to_duration(cycles, frequency),
+      None => Duration::ZERO,
+   };
+}
+
+/// Time since the kernel clock started.
+///
+/// Nanosecond-resolution once a high-resolution counter is calibrated; until then it only
+/// advances once per timer tick.
+pub fn uptime() -> Duration {
+   if let (Some(frequency), Some(read)) = (counter_frequency(), COUNTER.get()) {
+      let elapsed = read().wrapping_sub(COUNTER_BASE.load(Ordering::Relaxed));
+      return counter_to_duration(elapsed, frequency);
+   }
+
+   return ticks_to_duration(ticks());
+}
+
+fn ticks_to_duration(ticks: u64) -> Duration {
+   return Duration::from_nanos(ticks.saturating_mul(TICK_NANOS.load(Ordering::Relaxed)));
+}
+
+fn counter_to_duration(cycles: u64, frequency: u64) -> Duration {
+   let seconds = cycles / frequency;
+   let nanos = (cycles % frequency) as u128 * NANOS_PER_SECOND as u128 / frequency as u128;
+   return Duration::new(seconds, nanos as u32);
+}
+
+// IMPORTS //
+
+use {
+   core::{
+      cmp::max,
+      sync::atomic::{AtomicU64, Ordering},
+      time::Duration,
+   },
+   spin::Once,
+};
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn counter_cycles_convert_exactly() {
+      assert_eq!(counter_to_duration(3_000_000_000, 1_000_000_000), Duration::from_secs(3));
+      assert_eq!(counter_to_duration(1_500, 1_000), Duration::from_millis(1_500));
+      assert_eq!(counter_to_duration(u64::MAX, 3_000_000_000).as_secs(), u64::MAX / 3_000_000_000);
+   }
+
+   #[test]
+   fn uptime_follows_ticks_then_the_counter() {
+      static COUNTER_VALUE: AtomicU64 = AtomicU64::new(5_000);
+
+      set_tick_frequency(100);
+      assert_eq!(tick_length(), Duration::from_millis(10));
+
+      let start = ticks();
+      for _ in 0..3 {
+         tick();
+      }
+      assert_eq!(ticks() - start, 3);
+
+      // Switching to the counter keeps the uptime measured so far.
+      let before = uptime();
+      set_counter(|| COUNTER_VALUE.load(Ordering::Relaxed), 1_000);
+      assert_eq!(uptime(), before);
+
+      COUNTER_VALUE.fetch_add(250, Ordering::Relaxed);
+      assert_eq!(uptime(), before + Duration::from_millis(250));
+      assert_eq!(timestamp_duration(250), Duration::from_millis(250));
+   }
+}
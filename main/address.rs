@@ -12,5 +12,23 @@ pub const NUM_PRIORITIES: usize = 32;
 /// Start address of user space.
 pub const USER_SPACE_START: usize = 0x20000000000usize;
 
+/// End of user space: one page short of the top of the lower half, as on Linux, so that every
+/// user address and the end itself are canonical.
+pub const USER_SPACE_END: usize = 0x7ffffffff000usize;
+
+/// Start of the higher half. Everything of the kernel's lives up here, so user address spaces
+/// can share it while owning the lower half outright.
+pub const KERNEL_SPACE_START: u64 = 0xffff_8000_0000_0000;
+
+/// End of the part of the higher half the bootloader may place its own mappings in. The
+/// kernel's windows for the heap, device registers and thread stacks sit above it.
+pub const BOOT_MAPPINGS_END: u64 = 0xffff_c000_0000_0000;
+
 /// Initial value of the stack pointer.
 pub const USER_STACK: usize = USER_SPACE_START + 0x800000000usize;
+
+/// Lowest address `mmap` places mappings at when not given one.
+pub const USER_MMAP_START: usize = USER_STACK + 0x100000000usize;
+
+/// Most a user stack may grow to below [`USER_STACK`].
+pub const USER_STACK_SIZE: usize = 0x800000usize;
@@ -1,4 +1,308 @@
-pub fn initialise(rsdp: u64) {
+/// Maximum number of I/O APICs the kernel will drive.
+pub const MAX_IO_APICS: usize = 8;
+
+/// Number of legacy ISA IRQs, which the MADT may route to different global system interrupts.
+pub const ISA_IRQS: usize = 16;
+
+// Local APIC register offsets.
+const LAPIC_ID: usize = 0x20;
+const LAPIC_TPR: usize = 0x80;
+const LAPIC_EOI: usize = 0xB0;
+const LAPIC_SVR: usize = 0xF0;
+const LAPIC_ESR: usize = 0x280;
+const LAPIC_LVT_TIMER: usize = 0x320;
+const LAPIC_LVT_ERROR: usize = 0x370;
+
+const LAPIC_SVR_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+
+// I/O APIC registers.
+const IOAPIC_VERSION: u32 = 0x01;
+const IOAPIC_REDIRECTION: u32 = 0x10;
+
+const REDIRECTION_ACTIVE_LOW: u64 = 1 << 13;
+const REDIRECTION_LEVEL: u64 = 1 << 15;
+const REDIRECTION_MASKED: u64 = 1 << 16;
+
+/// Virtual address of the local APIC's registers, or zero before [`initialise`].
+static LOCAL_APIC: AtomicUsize = AtomicUsize::new(0);
+
+/// The I/O APICs described by the MADT.
+static IO_APICS: Mutex<[Option<IoApic>; MAX_IO_APICS]> = Mutex::new([None; MAX_IO_APICS]);
+
+/// How each ISA IRQ is wired to the I/O APICs.
+static ISA_ROUTES: Once<[Route; ISA_IRQS]> = Once::new();
+
+/// Brings up the local APIC and every I/O APIC described by the ACPI tables at `rsdp`.
+///
+/// Every I/O APIC input is programmed to arrive on [`IRQ_BASE`] plus its IRQ number, masked
+/// until a handler is registered. Needs the heap and [`memory::map_mmio`]. On error, the local
+/// APIC is left disabled and [`enabled`] keeps returning `false`.
+///
+/// ## Safety
+///
+/// Must be called once, with interrupts disabled.
+pub unsafe fn initialise(rsdp: u64) -> Result<(), ApicError> {
+   let tables = AcpiTables::from_rsdp(PhysicalMemory, rsdp as usize).map_err(ApicError::Acpi)?;
+   let platform = tables.platform_info().map_err(ApicError::Acpi)?;
+
+   let InterruptModel::Apic(apic) = platform.interrupt_model else {
+      return Err(ApicError::Unsupported);
+   };
+
+   if apic.io_apics.is_empty() {
+      return Err(ApicError::NoIoApic);
+   }
+
+   // Map every register window before touching the hardware, so a failed mapping leaves the
+   // 8259s in charge and the local APIC disabled.
+   let base = memory::map_mmio(PhysAddr::new(apic.local_apic_address), 4096)
+      .map_err(|_| ApicError::Mapping)?;
+
+   let mut ioApicBases = [0; MAX_IO_APICS];
+   for (ioApicBase, description) in ioApicBases.iter_mut().zip(apic.io_apics.iter()) {
+      *ioApicBase = memory::map_mmio(PhysAddr::new(description.address as u64), 4096)
+         .map_err(|_| ApicError::Mapping)?
+         .as_u64() as usize;
+   }
+
+   // The local APIC.
+   LOCAL_APIC.store(base.as_u64() as usize, Ordering::Release);
+
+   wrmsr(IA32_APIC_BASE, rdmsr(IA32_APIC_BASE) | APIC_BASE_ENABLE);
+   write_local(LAPIC_SVR, LAPIC_SVR_ENABLE | SPURIOUS_VECTOR as u32);
+   write_local(LAPIC_TPR, 0);
+   write_local(LAPIC_LVT_TIMER, LVT_MASKED);
+   write_local(LAPIC_LVT_ERROR, ERROR_VECTOR as u32);
+   write_local(LAPIC_ESR, 0);
+
+   let destination = (read_local(LAPIC_ID) >> 24) as u8;
+   log::info!("Local APIC {} enabled at {:#x}", destination, apic.local_apic_address);
+
+   // The I/O APICs, with every input masked.
+   {
+      let mut ioApics = IO_APICS.lock();
+
+      let ioApicDescriptions = ioApicBases.iter().zip(apic.io_apics.iter());
+      for (slot, (&base, description)) in ioApics.iter_mut().zip(ioApicDescriptions) {
+         let mut ioApic = IoApic{
+            base,
+            id: description.id,
+            gsiBase: description.global_system_interrupt_base,
+            inputs: 0,
+            destination,
+         };
+         ioApic.inputs = ((ioApic.read(IOAPIC_VERSION) >> 16) & 0xFF) + 1;
+
+         for input in 0..ioApic.inputs {
+            let irq = ioApic.gsiBase + input;
+            let vector = IRQ_BASE as u64 + min(irq, IRQ_COUNT as u32 - 1) as u64;
+            ioApic.write_entry(input, vector | REDIRECTION_MASKED);
+         }
+
+         log::info!(
+            "I/O APIC {} at {:#x}: GSI {}-{}",
+            ioApic.id, description.address, ioApic.gsiBase, ioApic.gsiBase + ioApic.inputs - 1,
+         );
+
+         *slot = Some(ioApic);
+      }
+
+      if apic.io_apics.len() > MAX_IO_APICS {
+         log::warn!("Ignoring {} I/O APICs", apic.io_apics.len() - MAX_IO_APICS);
+      }
+   }
+
+   // ISA IRQs are edge-triggered and active-high unless the MADT says otherwise.
+   let mut routes = [Route::default(); ISA_IRQS];
+   for (irq, route) in routes.iter_mut().enumerate() {
+      route.gsi = irq as u32;
+   }
+
+   for source in apic.interrupt_source_overrides.iter() {
+      let Some(route) = routes.get_mut(source.isa_source as usize) else { continue };
+
+      route.gsi = source.global_system_interrupt;
+      route.activeLow = matches!(source.polarity, Polarity::ActiveLow);
+      route.level = matches!(source.trigger_mode, TriggerMode::Level);
+   }
+
+   ISA_ROUTES.call_once(|| routes);
+
+   if apic.also_has_legacy_pics {
+      pic::initialise(IRQ_BASE);
+      pic::disable();
+   }
+
+   return Ok(());
+}
+
+/// Returns `true` once [`initialise`] has enabled the local APIC.
+pub fn enabled() -> bool {
+   return LOCAL_APIC.load(Ordering::Acquire) != 0;
+}
+
+/// Signals the end of the interrupt currently being serviced.
+pub fn end_of_interrupt() {
+   unsafe { write_local(LAPIC_EOI, 0) };
+}
+
+/// Masks or unmasks `irq`, an ISA IRQ or, above 15, a global system interrupt.
+///
+/// Returns `false` if no I/O APIC handles the line.
+pub fn set_masked(irq: u8, masked: bool) -> bool {
+   let route = match ISA_ROUTES.get().and_then(|routes| routes.get(irq as usize)) {
+      Some(&route) => route,
+      None => Route{ gsi: irq as u32, activeLow: true, level: true },
+   };
+
+   return without_interrupts(|| {
+      let mut ioApics = IO_APICS.lock();
+      let Some(ioApic) = ioApics.iter_mut().flatten().find(|ioApic| ioApic.handles(route.gsi)) else {
+         return false;
+      };
+
+      let mut entry = IRQ_BASE as u64 + irq as u64;
+      if route.activeLow {
+         entry |= REDIRECTION_ACTIVE_LOW;
+      }
+      if route.level {
+         entry |= REDIRECTION_LEVEL;
+      }
+      if masked {
+         entry |= REDIRECTION_MASKED;
+      }
+
+      unsafe { ioApic.write_entry(route.gsi - ioApic.gsiBase, entry) };
+      return true;
+   });
+}
+
+unsafe fn read_local(register: usize) -> u32 {
+   let base = LOCAL_APIC.load(Ordering::Acquire);
+   return ptr::read_volatile((base + register) as *const u32);
+}
+
+unsafe fn write_local(register: usize, value: u32) {
+   let base = LOCAL_APIC.load(Ordering::Acquire);
+   ptr::write_volatile((base + register) as *mut u32, value);
+}
+
+/// Errors raised while bringing up the APICs.
+#[derive(Debug)]
+pub enum ApicError {
+   /// The ACPI tables could not be parsed.
+   Acpi(AcpiError),
+
+   /// The firmware does not describe an APIC interrupt model.
+   Unsupported,
+
+   /// The MADT lists no I/O APIC.
+   NoIoApic,
+
+   /// APIC registers could not be mapped.
+   Mapping,
+}
+
+impl Display for ApicError {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return match self {
+         ApicError::Acpi(error) => write!(f, "unable to read the ACPI tables: {:?}", error),
+         ApicError::Unsupported => write!(f, "the platform does not use the APIC interrupt model"),
+         ApicError::NoIoApic => write!(f, "no I/O APIC was found"),
+         ApicError::Mapping => write!(f, "unable to map the APIC registers"),
+      };
+   }
+}
+
+/// How a single IRQ reaches an I/O APIC input.
+#[derive(Copy, Clone, Debug, Default)]
+struct Route {
+   gsi: u32,
+   activeLow: bool,
+   level: bool,
+}
+
+/// An I/O APIC and the range of global system interrupts it serves.
+#[derive(Copy, Clone, Debug)]
+struct IoApic {
+   base: usize,
+   id: u8,
+   gsiBase: u32,
+   inputs: u32,
+
+   /// Local APIC every input is delivered to.
+   destination: u8,
+}
+
+impl IoApic {
+   fn handles(&self, gsi: u32) -> bool {
+      return gsi >= self.gsiBase && gsi < self.gsiBase + self.inputs;
+   }
+
+   unsafe fn read(&self, register: u32) -> u32 {
+      ptr::write_volatile(self.base as *mut u32, register);
+      return ptr::read_volatile((self.base + 0x10) as *const u32);
+   }
+
+   unsafe fn write(&mut self, register: u32, value: u32) {
+      ptr::write_volatile(self.base as *mut u32, register);
+      ptr::write_volatile((self.base + 0x10) as *mut u32, value);
+   }
+
+   /// Programs the redirection entry for `input`, delivering to this CPU in fixed mode.
+   unsafe fn write_entry(&mut self, input: u32, entry: u64) {
+      let register = IOAPIC_REDIRECTION + input * 2;
+
+      // Mask first so the entry never fires half-written.
+      self.write(register, REDIRECTION_MASKED as u32);
+      self.write(register + 1, (self.destination as u32) << 24);
+      self.write(register, entry as u32);
+   }
+}
+
+/// Maps ACPI tables through the bootloader's physical memory mapping.
+#[derive(Copy, Clone, Debug)]
+struct PhysicalMemory;
+
+impl AcpiHandler for PhysicalMemory {
+   unsafe fn map_physical_region<T>(&self, physical_address: usize, size: usize) -> PhysicalMapping<Self, T> {
+      let virt = memory::physical_offset() + physical_address as u64;
+
+      return PhysicalMapping::new(
+         physical_address,
+         NonNull::new(virt.as_mut_ptr()).expect("ACPI table at a null address"),
+         size,
+         size,
+         *self,
+      );
+   }
+
+   fn unmap_physical_region<T>(_: &PhysicalMapping<Self, T>) {}
 }
 
 // IMPORTS //
+
+use {
+   crate::{
+      interrupts::{ERROR_VECTOR, IRQ_BASE, IRQ_COUNT, SPURIOUS_VECTOR},
+      memory,
+      pic,
+   },
+   acpi::{
+      platform::interrupt::{InterruptModel, Polarity, TriggerMode},
+      AcpiError, AcpiHandler, AcpiTables, PhysicalMapping,
+   },
+   base::log,
+   core::{
+      cmp::min,
+      fmt::{self, Display},
+      ptr::{self, NonNull},
+      sync::atomic::{AtomicUsize, Ordering},
+   },
+   spin::{Mutex, Once},
+   x86::msr::{rdmsr, wrmsr, IA32_APIC_BASE},
+   x86_64::{instructions::interrupts::without_interrupts, PhysAddr},
+};
@@ -5,33 +5,24 @@ pub fn initialise_platform() {
 
    COM2.lock().initialise();
 
-   log::debug!("Initialise timer, PIT, et cetera.");
+   log::debug!("Enable the x87 and SSE units for user code.");
 
-   let latch = ((CLOCK_TICK_RATE + TIMER_FREQUENCY / 2) / TIMER_FREQUENCY) as u16;
+   unsafe { context::initialise_fpu() };
 
-   unsafe {
-      /*
-       * Port 0x43 is for initializing the PIT:
-       *
-       * 0x34 means the following:
-       * 0b...     (step-by-step binary representation)
-       * ...  00  - channel 0
-       * ...  11  - write two values to counter register:
-       *            first low-, then high-byte
-       * ... 010  - mode number 2: "rate generator" / frequency divider
-       * ...   0  - binary counter (the alternative is BCD)
-       */
-      outb(0x43, 0x34);
+   log::debug!("Initialise system call entry.");
 
-      wait_100k();
+   syscall::initialise();
 
-      /* Port 0x40 is for the counter register of channel 0 */
+   log::debug!("Initialise timer, PIT, et cetera.");
 
-      outb(0x40, (latch & 0xFF) as u8); /* low byte  */
+   if let Err(error) = timer::initialise() {
+      log::warn!("No timer interrupt: {}", error);
+   }
 
-      wait_100k();
+   log::debug!("Initialise PS/2 controller.");
 
-      outb(0x40, (latch >> 8) as u8); /* high byte */
+   if let Err(error) = ps2::initialise() {
+      log::warn!("No PS/2 keyboard: {}", error);
    }
 
    log::info!("Successfully initialised x86_64 platform modules.");
@@ -40,15 +31,20 @@ pub fn initialise_platform() {
 // IMPORTS //
 
 use {
-   self::{timer::*},
    base::{
       log,
       uart::COM2,
    },
-   x86::io::*,
 };
 
 // MODULES //
 
+/// Saving and restoring kernel thread state.
+pub mod context;
+
+pub mod ps2;
+
+/// The `syscall` entry point and its model-specific registers.
 pub mod syscall;
+
 pub mod timer;
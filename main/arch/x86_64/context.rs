@@ -0,0 +1,194 @@
+/// Number of callee-saved registers [`switch_context`] keeps on a suspended thread's stack.
+const SAVED_REGISTERS: usize = 6;
+
+/// Suspends the running thread and resumes the one whose stack pointer is `new`.
+///
+/// The System V callee-saved registers are pushed onto the current stack and the resulting
+/// stack pointer stored through `old`; the registers saved on `new` are then popped and its
+/// return address taken. The kernel is built without SSE, so the x87 and SSE registers only
+/// ever hold user state, which the scheduler saves separately with [`save_fpu`].
+///
+/// ## Safety
+///
+/// Interrupts must be disabled, `old` must stay valid until the thread is resumed, and `new`
+/// must come from an earlier switch or from [`prepare_stack`].
+#[naked]
+pub unsafe extern "C" fn switch_context(old: *mut u64, new: u64) {
+   asm!(
+      "push rbp",
+      "push rbx",
+      "push r12",
+      "push r13",
+      "push r14",
+      "push r15",
+      "mov [rdi], rsp",
+      "mov rsp, rsi",
+      "pop r15",
+      "pop r14",
+      "pop r13",
+      "pop r12",
+      "pop rbx",
+      "pop rbp",
+      "ret",
+      options(noreturn),
+   );
+}
+
+/// The x87 and SSE registers of a suspended thread, in the layout `fxsave` writes.
+#[repr(C, align(16))]
+pub struct FpuState([u8; 512]);
+
+impl FpuState {
+   /// The state `fninit` leaves with the default MXCSR: every exception masked, rounding to
+   /// nearest and every register empty.
+   pub const fn new() -> Self {
+      let mut area = [0; 512];
+
+      // The x87 control word, at offset 0.
+      area[0] = 0x7F;
+      area[1] = 0x03;
+
+      // MXCSR, at offset 24.
+      area[24] = 0x80;
+      area[25] = 0x1F;
+
+      return FpuState(area);
+   }
+}
+
+/// Lets the x87 and SSE instructions run, and `fxsave` cover the SSE registers.
+///
+/// ## Safety
+///
+/// Must be called once at boot, before any thread is switched.
+pub unsafe fn initialise_fpu() {
+   Cr0::update(|flags| {
+      flags.remove(Cr0Flags::EMULATE_COPROCESSOR | Cr0Flags::TASK_SWITCHED);
+      flags.insert(Cr0Flags::MONITOR_COPROCESSOR);
+   });
+   Cr4::update(|flags| flags.insert(Cr4Flags::OSFXSR | Cr4Flags::OSXMMEXCPT_ENABLE));
+
+   asm!("fninit", options(nomem, nostack));
+}
+
+/// Stores the x87 and SSE registers into `state`.
+pub fn save_fpu(state: &mut FpuState) {
+   unsafe { asm!("fxsave64 [{}]", in(reg) state as *mut FpuState, options(nostack)) };
+}
+
+/// Loads the x87 and SSE registers from `state`.
+pub fn restore_fpu(state: &FpuState) {
+   unsafe { asm!("fxrstor64 [{}]", in(reg) state as *const FpuState, options(nostack, readonly)) };
+}
+
+/// Lays out a fresh stack ending at `top` so that switching to it calls `entry(argument)`.
+///
+/// Returns the stack pointer to pass to [`switch_context`]. `entry` starts with interrupts
+/// still disabled and must never return.
+///
+/// ## Safety
+///
+/// `top` must be 16-byte aligned, with at least a page of writable memory below it.
+pub unsafe fn prepare_stack(top: VirtAddr, entry: extern "C" fn(usize) -> !, argument: usize) -> u64 {
+   let top = top.as_mut_ptr::<u64>();
+
+   // Popped by `switch_context` into r15, r14, r13, r12, rbx and rbp, then the return address.
+   let frame: [u64; SAVED_REGISTERS + 1] = [
+      0,
+      0,
+      0,
+      entry as u64,
+      argument as u64,
+      0,
+      thread_trampoline as u64,
+   ];
+
+   let stack = top.sub(frame.len());
+   ptr::copy_nonoverlapping(frame.as_ptr(), stack, frame.len());
+
+   return stack as u64;
+}
+
+/// First code run on a fresh stack: calls the entry point left in r12 with the argument in rbx.
+///
+/// The return address has just been popped, so the stack is 16-byte aligned for the call.
+#[naked]
+unsafe extern "C" fn thread_trampoline() -> ! {
+   asm!(
+      "mov rdi, rbx",
+      "call r12",
+      "ud2",
+      options(noreturn),
+   );
+}
+
+/// RFLAGS user code starts with: interrupts enabled, plus the always-set reserved bit 1.
+const USER_RFLAGS: u64 = (1 << 9) | (1 << 1);
+
+/// MXCSR user code starts with: every SIMD exception masked, rounding to nearest.
+const DEFAULT_MXCSR: u32 = 0x1F80;
+
+/// Drops the running thread to ring 3 at `entry`, with its stack pointer at `stack`.
+///
+/// Every general-purpose register is cleared first so no kernel values leak, the x87 and SSE
+/// state is reset to its defaults, and interrupts are enabled on arrival. Interrupts and exceptions raised in user mode switch to the stack
+/// set by [`gdt::set_kernel_stack`], which the scheduler points at the thread's kernel stack,
+/// so this must not be called from the boot thread.
+///
+/// ## Safety
+///
+/// `entry` and `stack` must be canonical addresses mapped user-accessible in the active page
+/// table, and nothing on the current kernel stack may be needed again.
+pub unsafe fn enter_user_mode(entry: VirtAddr, stack: VirtAddr) -> ! {
+   let selectors = gdt::SELECTORS.get().expect("GDT not loaded");
+   let data = selectors.user_data.0 as u64;
+   let code = selectors.user_code.0 as u64;
+   let mxcsr: u32 = DEFAULT_MXCSR;
+
+   asm!(
+      "cli",
+      "fninit",
+      "ldmxcsr [{mxcsr}]",
+      "mov ds, {data:x}",
+      "mov es, {data:x}",
+      "push {data}",
+      "push {stack}",
+      "push {flags}",
+      "push {code}",
+      "push {entry}",
+      "xor eax, eax",
+      "xor ebx, ebx",
+      "xor ecx, ecx",
+      "xor edx, edx",
+      "xor esi, esi",
+      "xor edi, edi",
+      "xor ebp, ebp",
+      "xor r8d, r8d",
+      "xor r9d, r9d",
+      "xor r10d, r10d",
+      "xor r11d, r11d",
+      "xor r12d, r12d",
+      "xor r13d, r13d",
+      "xor r14d, r14d",
+      "xor r15d, r15d",
+      "iretq",
+      data = in(reg) data,
+      stack = in(reg) stack.as_u64(),
+      flags = in(reg) USER_RFLAGS,
+      code = in(reg) code,
+      entry = in(reg) entry.as_u64(),
+      mxcsr = in(reg) &mxcsr,
+      options(noreturn),
+   );
+}
+
+// IMPORTS //
+
+use {
+   crate::gdt,
+   core::{arch::asm, ptr},
+   x86_64::{
+      registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags},
+      VirtAddr,
+   },
+};
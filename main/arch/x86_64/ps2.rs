@@ -0,0 +1,181 @@
+/// Data port of the 8042 PS/2 controller.
+pub const DATA_PORT: u16 = 0x60;
+
+/// Status (read) and command (write) port of the 8042 PS/2 controller.
+pub const COMMAND_PORT: u16 = 0x64;
+
+/// IRQ raised by the first PS/2 port, where the keyboard lives.
+pub const KEYBOARD_IRQ: u8 = 1;
+
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+
+const CONFIG_PORT1_IRQ: u8 = 1 << 0;
+const CONFIG_PORT2_IRQ: u8 = 1 << 1;
+const CONFIG_TRANSLATION: u8 = 1 << 6;
+
+const COMMAND_READ_CONFIG: u8 = 0x20;
+const COMMAND_WRITE_CONFIG: u8 = 0x60;
+const COMMAND_DISABLE_PORT2: u8 = 0xA7;
+const COMMAND_SELF_TEST: u8 = 0xAA;
+const COMMAND_TEST_PORT1: u8 = 0xAB;
+const COMMAND_DISABLE_PORT1: u8 = 0xAD;
+const COMMAND_ENABLE_PORT1: u8 = 0xAE;
+
+const SELF_TEST_PASSED: u8 = 0x55;
+const PORT_TEST_PASSED: u8 = 0x00;
+
+const DEVICE_RESET: u8 = 0xFF;
+const DEVICE_ACK: u8 = 0xFA;
+const DEVICE_RESET_PASSED: u8 = 0xAA;
+
+/// How many times to poll the status register before giving up on the controller.
+const TIMEOUT: usize = 100_000;
+
+/// Initialises the 8042 controller and the keyboard on its first port, then routes IRQ 1 into
+/// [`keyboard::add_scancode`].
+///
+/// Translation to scancode set 1 is left on, which is what the keyboard task decodes.
+pub fn initialise() -> Result<(), Ps2Error> {
+   without_interrupts(|| unsafe {
+      // Quiesce both ports and drop anything already buffered.
+      command(COMMAND_DISABLE_PORT1)?;
+      command(COMMAND_DISABLE_PORT2)?;
+      flush();
+
+      let mut config = query(COMMAND_READ_CONFIG)?;
+      config &= !(CONFIG_PORT1_IRQ | CONFIG_PORT2_IRQ);
+      config |= CONFIG_TRANSLATION;
+      command_with(COMMAND_WRITE_CONFIG, config)?;
+
+      // Some controllers reset themselves during the self-test, so restore the configuration.
+      match query(COMMAND_SELF_TEST)? {
+         SELF_TEST_PASSED => command_with(COMMAND_WRITE_CONFIG, config)?,
+         result => return Err(Ps2Error::SelfTest(result)),
+      }
+
+      match query(COMMAND_TEST_PORT1)? {
+         PORT_TEST_PASSED => {}
+         result => return Err(Ps2Error::PortTest(result)),
+      }
+
+      command(COMMAND_ENABLE_PORT1)?;
+
+      // A missing or unhappy keyboard is not fatal; one may still be plugged in later.
+      if let Err(error) = reset_keyboard() {
+         log::warn!("PS/2 keyboard did not reset: {}", error);
+      }
+      flush();
+
+      command_with(COMMAND_WRITE_CONFIG, config | CONFIG_PORT1_IRQ)?;
+      return Ok(());
+   })?;
+
+   keyboard::initialise();
+   interrupts::register_irq(KEYBOARD_IRQ, keyboard_interrupt).map_err(Ps2Error::Irq)?;
+
+   log::info!("PS/2 keyboard ready");
+   return Ok(());
+}
+
+fn keyboard_interrupt(_: u8) {
+   let scancode = unsafe { inb(DATA_PORT) };
+   keyboard::add_scancode(scancode);
+}
+
+unsafe fn reset_keyboard() -> Result<(), Ps2Error> {
+   write(DATA_PORT, DEVICE_RESET)?;
+
+   match read()? {
+      DEVICE_ACK => {}
+      response => return Err(Ps2Error::Device(response)),
+   }
+
+   return match read()? {
+      DEVICE_RESET_PASSED => Ok(()),
+      response => Err(Ps2Error::Device(response)),
+   };
+}
+
+unsafe fn command(command: u8) -> Result<(), Ps2Error> {
+   return write(COMMAND_PORT, command);
+}
+
+unsafe fn command_with(command: u8, data: u8) -> Result<(), Ps2Error> {
+   write(COMMAND_PORT, command)?;
+   return write(DATA_PORT, data);
+}
+
+/// Sends `command` and returns the controller's one-byte response.
+unsafe fn query(command: u8) -> Result<u8, Ps2Error> {
+   write(COMMAND_PORT, command)?;
+   return read();
+}
+
+unsafe fn write(port: u16, value: u8) -> Result<(), Ps2Error> {
+   for _ in 0..TIMEOUT {
+      if inb(COMMAND_PORT) & STATUS_INPUT_FULL == 0 {
+         outb(port, value);
+         return Ok(());
+      }
+   }
+
+   return Err(Ps2Error::Timeout);
+}
+
+unsafe fn read() -> Result<u8, Ps2Error> {
+   for _ in 0..TIMEOUT {
+      if inb(COMMAND_PORT) & STATUS_OUTPUT_FULL != 0 {
+         return Ok(inb(DATA_PORT));
+      }
+   }
+
+   return Err(Ps2Error::Timeout);
+}
+
+unsafe fn flush() {
+   while inb(COMMAND_PORT) & STATUS_OUTPUT_FULL != 0 {
+      inb(DATA_PORT);
+   }
+}
+
+/// Errors raised while bringing up the PS/2 controller.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Ps2Error {
+   /// The controller stopped responding.
+   Timeout,
+
+   /// The controller failed its self-test with the given result.
+   SelfTest(u8),
+
+   /// The first port failed its interface test with the given result.
+   PortTest(u8),
+
+   /// The keyboard answered a command with the given unexpected byte.
+   Device(u8),
+
+   /// The keyboard interrupt could not be registered.
+   Irq(IrqError),
+}
+
+impl Display for Ps2Error {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return match self {
+         Ps2Error::Timeout => write!(f, "the controller timed out"),
+         Ps2Error::SelfTest(result) => write!(f, "controller self-test failed ({:#x})", result),
+         Ps2Error::PortTest(result) => write!(f, "port test failed ({:#x})", result),
+         Ps2Error::Device(response) => write!(f, "unexpected device response {:#x}", response),
+         Ps2Error::Irq(error) => write!(f, "{}", error),
+      };
+   }
+}
+
+// IMPORTS //
+
+use {
+   crate::interrupts::{self, IrqError},
+   base::{log, tasks::keyboard},
+   core::fmt::{self, Display},
+   x86::io::{inb, outb},
+   x86_64::instructions::interrupts::without_interrupts,
+};
@@ -0,0 +1,106 @@
+/// RFLAGS bits cleared on entry: interrupts, trap, direction and alignment check.
+const SYSCALL_MASK: RFlags = RFlags::INTERRUPT_FLAG
+   .union(RFlags::TRAP_FLAG)
+   .union(RFlags::DIRECTION_FLAG)
+   .union(RFlags::ALIGNMENT_CHECK);
+
+/// Number of entries in the system call table, for the bounds check in [`syscall_entry`].
+static SYSCALL_COUNT: u64 = NUM_SYSCALLS as u64;
+
+/// User stack pointer, held only between `syscall` and the push onto the kernel stack, with
+/// interrupts disabled.
+static mut USER_RSP: u64 = 0;
+
+/// Enables the `syscall` instruction and points it at [`syscall_entry`].
+///
+/// Needs the GDT, since the segment selectors loaded on entry and exit come from it.
+pub fn initialise() {
+   let selectors = gdt::SELECTORS.get().expect("GDT not loaded");
+
+   unsafe {
+      Star::write(selectors.user_code, selectors.user_data, selectors.kernel_code, selectors.kernel_data)
+         .expect("GDT segments not laid out for SYSCALL/SYSRET");
+      LStar::write(VirtAddr::new(syscall_entry as usize as u64));
+      SFMask::write(SYSCALL_MASK);
+      Efer::update(|flags| flags.insert(EferFlags::SYSTEM_CALL_EXTENSIONS));
+   }
+
+   log::info!("System calls enabled.");
+}
+
+/// Where `syscall` lands, still on the user stack and with interrupts disabled.
+///
+/// Switches to the running thread's kernel stack (the same one the TSS gives interrupts),
+/// saves the registers Linux preserves across a system call, then calls the handler for `rax`
+/// from [`SYSCALL_TABLE`] with interrupts enabled, so a long system call can be preempted. The
+/// handler's result goes back to user mode in `rax` through `sysretq`.
+#[naked]
+unsafe extern "C" fn syscall_entry() -> ! {
+   asm!(
+      "mov [rip + {user_rsp}], rsp",
+      "mov rsp, [rip + {tss} + 4]",
+      "push qword ptr [rip + {user_rsp}]",
+      "push rcx",
+      "push r11",
+      "push rdi",
+      "push rsi",
+      "push rdx",
+      "push r10",
+      "push r8",
+      "push r9",
+      // Ten words keep the stack 16-byte aligned for the call.
+      "push rax",
+      "sti",
+      "cld",
+      // The fourth argument travels in r10, since `syscall` takes rcx.
+      "mov rcx, r10",
+      "cmp rax, [rip + {count}]",
+      "jae 2f",
+      "lea r11, [rip + {table}]",
+      "call [r11 + rax * 8]",
+      "jmp 3f",
+      "2:",
+      "mov rdi, rax",
+      "call {invalid}",
+      "3:",
+      "cli",
+      "add rsp, 8",
+      "pop r9",
+      "pop r8",
+      "pop r10",
+      "pop rdx",
+      "pop rsi",
+      "pop rdi",
+      "pop r11",
+      "pop rcx",
+      "pop rsp",
+      "sysretq",
+      user_rsp = sym USER_RSP,
+      tss = sym gdt::TSS,
+      count = sym SYSCALL_COUNT,
+      table = sym SYSCALL_TABLE,
+      invalid = sym invalid_syscall,
+      options(noreturn),
+   );
+}
+
+// IMPORTS //
+
+use {
+   crate::{
+      gdt,
+      process::syscalls::SYSCALL_TABLE,
+   },
+   base::{
+      log,
+      syscall::{invalid_syscall, NUM_SYSCALLS},
+   },
+   core::arch::asm,
+   x86_64::{
+      registers::{
+         model_specific::{Efer, EferFlags, LStar, SFMask, Star},
+         rflags::RFlags,
+      },
+      VirtAddr,
+   },
+};
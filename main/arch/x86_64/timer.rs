@@ -1,6 +1,130 @@
 pub const CLOCK_TICK_RATE: u32 = 1193182u32; // 8254 chip's internal oscillator frequency
 pub const TIMER_FREQUENCY: u32 = 100; // Timer frequency in Hertz.
 
+/// IRQ raised by PIT channel 0.
+pub const TIMER_IRQ: u8 = 0;
+
+/// How long the TSC is measured against PIT channel 2 for.
+pub const CALIBRATION_MILLIS: u32 = 10;
+
+/// Upper bound on status polls during calibration, in case channel 2 never fires.
+const CALIBRATION_POLLS: usize = 10_000_000;
+
+const PIT_CHANNEL0: u16 = 0x40;
+const PIT_CHANNEL2: u16 = 0x42;
+const PIT_COMMAND: u16 = 0x43;
+
+/// Controls the channel 2 gate (bit 0) and speaker (bit 1), and reads its output (bit 5).
+const SPEAKER_PORT: u16 = 0x61;
+const CHANNEL2_GATE: u8 = 1 << 0;
+const SPEAKER_ENABLE: u8 = 1 << 1;
+const CHANNEL2_OUTPUT: u8 = 1 << 5;
+
+/// Programs PIT channel 0 to interrupt at [`TIMER_FREQUENCY`], routes IRQ 0 into the kernel
+/// clock and calibrates the TSC so [`time::uptime`] has nanosecond resolution.
+pub fn initialise() -> Result<(), IrqError> {
+   let latch = ((CLOCK_TICK_RATE + TIMER_FREQUENCY / 2) / TIMER_FREQUENCY) as u16;
+
+   unsafe {
+      /*
+       * Port 0x43 is for initializing the PIT:
+       *
+       * 0x34 means the following:
+       * 0b...     (step-by-step binary representation)
+       * ...  00  - channel 0
+       * ...  11  - write two values to counter register:
+       *            first low-, then high-byte
+       * ... 010  - mode number 2: "rate generator" / frequency divider
+       * ...   0  - binary counter (the alternative is BCD)
+       */
+      outb(PIT_COMMAND, 0x34);
+
+      wait_100k();
+
+      /* Port 0x40 is for the counter register of channel 0 */
+
+      outb(PIT_CHANNEL0, (latch & 0xFF) as u8); /* low byte  */
+
+      wait_100k();
+
+      outb(PIT_CHANNEL0, (latch >> 8) as u8); /* high byte */
+   }
+
+   time::set_tick_frequency(TIMER_FREQUENCY);
+   interrupts::register_irq(TIMER_IRQ, timer_interrupt)?;
+
+   match calibrate_tsc() {
+      Some(frequency) => {
+         time::set_counter(read_tsc, frequency);
+         log::info!("TSC running at {}.{:03} MHz", frequency / 1_000_000, frequency / 1_000 % 1_000);
+
+         if !invariant_tsc() {
+            log::warn!("TSC is not invariant; timestamps may drift under power management");
+         }
+      }
+      None => log::warn!("TSC calibration failed; uptime limited to timer ticks"),
+   }
+
+   return Ok(());
+}
+
+fn timer_interrupt(_: u8) {
+   time::tick();
+   timer::wake_expired();
+   scheduler::tick();
+}
+
+/// Measures the TSC frequency by counting cycles while PIT channel 2 counts down
+/// [`CALIBRATION_MILLIS`].
+///
+/// Channel 2 is gated by software and polled, so this works before interrupts are enabled and
+/// leaves channel 0 alone.
+fn calibrate_tsc() -> Option<u64> {
+   let count = CLOCK_TICK_RATE * CALIBRATION_MILLIS / 1000;
+
+   return without_interrupts(|| unsafe {
+      // Gate channel 2 on with the speaker disconnected.
+      let speaker = inb(SPEAKER_PORT);
+      outb(SPEAKER_PORT, (speaker & !SPEAKER_ENABLE) | CHANNEL2_GATE);
+
+      // Channel 2, low byte then high byte, mode 0: output goes high once the count expires.
+      outb(PIT_COMMAND, 0xB0);
+      outb(PIT_CHANNEL2, (count & 0xFF) as u8);
+      outb(PIT_CHANNEL2, (count >> 8) as u8);
+
+      let start = rdtsc();
+      let mut expired = false;
+      for _ in 0..CALIBRATION_POLLS {
+         if inb(SPEAKER_PORT) & CHANNEL2_OUTPUT != 0 {
+            expired = true;
+            break;
+         }
+      }
+      let end = rdtsc();
+
+      outb(SPEAKER_PORT, speaker);
+
+      return match expired && end > start {
+         true => Some((end - start) * 1000 / CALIBRATION_MILLIS as u64),
+         false => None,
+      };
+   });
+}
+
+/// Whether the TSC ticks at a constant rate regardless of power state.
+fn invariant_tsc() -> bool {
+   let extended = unsafe { __cpuid(0x8000_0000) };
+   if extended.eax < 0x8000_0007 {
+      return false;
+   }
+
+   return unsafe { __cpuid(0x8000_0007) }.edx & (1 << 8) != 0;
+}
+
+fn read_tsc() -> u64 {
+   return unsafe { rdtsc() };
+}
+
 pub unsafe fn wait_100k() {
    let start = rdtsc();
    call_mb();
@@ -13,6 +137,12 @@ pub unsafe fn wait_100k() {
 // IMPORTS //
 
 use {
-   base::syscall::*,
-   x86::{time::rdtsc}
+   crate::{
+      interrupts::{self, IrqError},
+      process::scheduler,
+   },
+   base::{log, syscall::*, tasks::timer, time},
+   core::arch::x86_64::__cpuid,
+   x86::{io::{inb, outb}, time::rdtsc},
+   x86_64::instructions::interrupts::without_interrupts,
 };
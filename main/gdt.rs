@@ -1,5 +1,14 @@
 pub static DOUBLE_FAULT_IST_INDEX: u16 = 0;
 
+/// Interrupt stack used for non-maskable interrupts, which can arrive at any time.
+pub static NMI_IST_INDEX: u16 = 1;
+
+/// Interrupt stack used for machine-check exceptions.
+pub static MACHINE_CHECK_IST_INDEX: u16 = 2;
+
+/// Size of each interrupt stack table stack.
+pub const IST_STACK_SIZE: usize = 4096 * 5;
+
 pub static mut TSS: TaskStateSegment = {
    let tss = TaskStateSegment::new();
    tss
@@ -11,33 +20,85 @@ pub static mut GDT: GlobalDescriptorTable = {
    gdt
 };
 
+/// Segment selectors of the loaded GDT.
+pub static SELECTORS: Once<Selectors> = Once::new();
+
+/// Selectors for the segments in [`GDT`].
+#[derive(Copy, Clone, Debug)]
+pub struct Selectors {
+   pub kernel_code: SegmentSelector,
+   pub kernel_data: SegmentSelector,
+   pub tss: SegmentSelector,
+   pub user_data: SegmentSelector,
+   pub user_code: SegmentSelector,
+}
+
 pub fn initialise() {
    unsafe {
       TSS.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-         const STACK_SIZE: usize = 4096 * 5;
-         static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+         static mut STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+         stack_top(&STACK)
+      };
+
+      TSS.interrupt_stack_table[NMI_IST_INDEX as usize] = {
+         static mut STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+         stack_top(&STACK)
+      };
 
-         let stack_start = VirtAddr::from_ptr(&STACK);
-         let stack_end = stack_start + STACK_SIZE;
-         stack_end
+      TSS.interrupt_stack_table[MACHINE_CHECK_IST_INDEX as usize] = {
+         static mut STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+         stack_top(&STACK)
       };
 
-      GDT.add_entry(Descriptor::tss_segment(&TSS));
+      let kernel_code = SegmentSelector::new(1, PrivilegeLevel::Ring0);
+      let kernel_data = GDT.add_entry(Descriptor::kernel_data_segment());
+      let tss = GDT.add_entry(Descriptor::tss_segment(&TSS));
+
+      // SYSRET loads SS and CS from consecutive entries, data first.
+      let user_data = GDT.add_entry(Descriptor::user_data_segment());
+      let user_code = GDT.add_entry(Descriptor::user_code_segment());
 
       GDT.load();
+
+      // Reload the segment registers, which still hold the bootloader's selectors, and the
+      // task register so the interrupt stacks above are used.
+      CS::set_reg(kernel_code);
+      SS::set_reg(kernel_data);
+      DS::set_reg(kernel_data);
+      ES::set_reg(kernel_data);
+      load_tss(tss);
+
+      SELECTORS.call_once(|| Selectors{ kernel_code, kernel_data, tss, user_data, user_code });
    }
 
    log::info!("Successfully initialised global descriptor table!");
 }
 
+/// Sets the stack the CPU switches to when an interrupt or exception arrives in user mode.
+///
+/// The scheduler points this at each thread's kernel stack as it switches to it.
+pub fn set_kernel_stack(top: VirtAddr) {
+   unsafe { TSS.privilege_stack_table[0] = top };
+}
+
+fn stack_top(stack: &'static [u8; IST_STACK_SIZE]) -> VirtAddr {
+   return VirtAddr::from_ptr(stack) + IST_STACK_SIZE;
+}
+
 // IMPORTS //
 
 use {
    base::log,
+   spin::Once,
    x86_64::{
+      PrivilegeLevel,
       VirtAddr,
+      instructions::{
+         segmentation::{Segment, CS, DS, ES, SS},
+         tables::load_tss,
+      },
       structures::{
-         gdt::{GlobalDescriptorTable, Descriptor},
+         gdt::{GlobalDescriptorTable, Descriptor, SegmentSelector},
          tss::TaskStateSegment,
       },
    }
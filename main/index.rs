@@ -6,6 +6,7 @@
 #![feature(cfg_match)]
 #![feature(const_mut_refs)]
 #![feature(custom_test_frameworks)]
+#![feature(naked_functions)]
 #![feature(panic_info_message)]
 #![reexport_test_harness_main="test_main"]
 #![test_runner(base::test::test_runner)]
@@ -18,6 +19,9 @@ pub static BOOTLOADER_CONFIG: BootloaderConfig = {
    config.mappings.framebuffer = Mapping::FixedAddress(0x8000000);
    config.mappings.physical_memory = Some(Mapping::Dynamic);
    config.mappings.page_table_recursive = Some(Mapping::Dynamic);
+   // Keep the bootloader's mappings out of user space and clear of the kernel's own windows.
+   config.mappings.dynamic_range_start = Some(address::KERNEL_SPACE_START);
+   config.mappings.dynamic_range_end = Some(address::BOOT_MAPPINGS_END - 0x1000);
    // TODO: write out the other necessary memory mappings.
    config
 };
@@ -43,12 +47,26 @@ pub fn main(info: &'static mut BootInfo) -> ! {
    let physical_offset = VirtAddr::new(physical_offset.into_option().unwrap());
    let mut mapper = unsafe{ memory::initialise(physical_offset) };
    let mut frame_allocator = unsafe{
-      SystemFrameAllocator::new(info.memory_regions.as_ref())
+      SystemFrameAllocator::new(info.memory_regions.as_ref(), physical_offset)
    };
 
    log::info!("Building the heap!");
    memory::build_heap(&mut mapper, &mut frame_allocator).expect("failed to initialise heap");
 
+   // Hand the page table and frame allocator over to the kernel so the heap can grow.
+   *memory::MAPPER.lock() = Some(mapper);
+   *memory::FRAME_ALLOCATOR.lock() = Some(frame_allocator);
+
+   // Track the kernel's memory areas so page faults inside them can be resolved.
+   memory::vma::initialise();
+
+   // Adopt the boot thread so kernel threads can be spawned and preempted.
+   process::scheduler::initialise();
+
+   // Bring up the interrupt controllers; every IRQ stays masked until it has a handler.
+   log::info!("Initialising interrupt controllers!");
+   interrupts::initialise_controllers(info.rsdp_addr.into_option());
+
    // Check CPU architecture and perform the proper initialisation.
    log::info!("Checking CPU architecture...");
    
@@ -61,6 +79,9 @@ pub fn main(info: &'static mut BootInfo) -> ! {
    #[cfg(target_arch = "aarch64")]
    arch::aarch64::initialise_platform();
 
+   // Start taking hardware interrupts.
+   interrupts::enable();
+
    // Example multitasking
    log::info!("Checking runtime multitasking...");
 
@@ -73,8 +94,17 @@ pub fn main(info: &'static mut BootInfo) -> ! {
       print!("{}", number);
    });
 
-   //tasks::add_future(tasks::keyboard::print_keypresses());
-   //Keyboard input still not working properly :(
+   tasks::add_named_future("keyboard-decoder", tasks::keyboard::decode_scancodes());
+   tasks::add_named_future("keyboard-echo", tasks::keyboard::print_keypresses());
+   tasks::add_named_future("stdin", process::stdin::feed_keypresses());
+
+   // Start the first user program, if the bootloader loaded one as the ramdisk.
+   if let Some(address) = info.ramdisk_addr.into_option() {
+      let init = unsafe { slice::from_raw_parts(address as *const u8, info.ramdisk_len as usize) };
+      if let Err(error) = process::initialise(init) {
+         log::error!("Failed to start init: {}", error);
+      }
+   }
 
    tasks::run_tasks(); // works now! :D
 
@@ -83,7 +113,8 @@ pub fn main(info: &'static mut BootInfo) -> ! {
    #[cfg(test)]
    test_main();
 
-   hlt_loop();
+   // Poll tasks as they are woken, halting in between.
+   tasks::run();
 }
 
 /// This function is called on compiler or runtime panic.
@@ -123,12 +154,6 @@ extern "C" fn abort() -> ! {
 #[no_mangle]
 extern "C" fn eh_personality() {}
 
-pub fn hlt_loop() -> ! {
-   loop{
-      x86_64::instructions::hlt();
-   }
-}
-
 springboard_api::start!(main, config = &BOOTLOADER_CONFIG);
 
 // MODULES //
@@ -136,6 +161,9 @@ springboard_api::start!(main, config = &BOOTLOADER_CONFIG);
 /// Important memory addresses, address-space utilities.
 pub mod address;
 
+/// Local APIC and I/O APIC support, configured from the ACPI tables.
+pub mod apic;
+
 /// Architecture-specific code.
 pub mod arch;
 
@@ -155,6 +183,9 @@ pub mod interrupts;
 /// Kernel memory management.
 pub mod memory;
 
+/// The legacy 8259 programmable interrupt controllers.
+pub mod pic;
+
 /// Kernel-level process management.
 pub mod process;
 
@@ -166,6 +197,7 @@ pub mod tests;
 extern crate alloc;
 extern crate acpi;
 #[macro_use] extern crate base;
+extern crate spin;
 extern crate springboard_api;
 extern crate x86_64;
 
@@ -173,7 +205,7 @@ extern crate x86_64;
 use {
    crate::memory::SystemFrameAllocator,
    base::{log, tasks, terminal},
-   core::panic::PanicInfo,
+   core::{panic::PanicInfo, slice},
    springboard_api::{BootInfo, BootloaderConfig, config::Mapping},
    x86_64::VirtAddr,
 };
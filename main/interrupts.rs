@@ -1,10 +1,47 @@
 pub static mut IDT: InterruptDescriptorTable = InterruptDescriptorTable::new();
 
+/// Vector of the first hardware interrupt; IRQ `n` arrives on vector `IRQ_BASE + n`.
+pub const IRQ_BASE: u8 = 32;
+
+/// Number of hardware interrupt lines with a vector of their own.
+pub const IRQ_COUNT: usize = 24;
+
+/// Vector the local APIC raises for internal errors.
+pub const ERROR_VECTOR: u8 = 0xFE;
+
+/// Vector the local APIC raises for spurious interrupts.
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+/// A hardware interrupt handler, called with the IRQ it was registered for.
+///
+/// Runs in interrupt context with interrupts disabled, so it must not block or take locks
+/// that are held with interrupts enabled.
+pub type IrqHandler = fn(irq: u8);
+
+/// The hardware that delivers IRQs to this CPU.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InterruptController {
+   /// The legacy pair of 8259 PICs.
+   Pic,
+
+   /// The local APIC, fed by one or more I/O APICs.
+   Apic,
+}
+
+static CONTROLLER: Once<InterruptController> = Once::new();
+
+static IRQ_HANDLERS: Mutex<[Option<IrqHandler>; IRQ_COUNT]> = Mutex::new([None; IRQ_COUNT]);
+
 pub fn initialise() {
    unsafe {
-      IDT.breakpoint.set_handler_fn(breakpoint);
-      IDT.double_fault.set_handler_fn(double_fault);
-      IDT.page_fault.set_handler_fn(page_fault);
+      exceptions::install(&mut IDT);
+
+      for (irq, &handler) in IRQ_STUBS.iter().enumerate() {
+         IDT[IRQ_BASE as usize + irq].set_handler_fn(handler);
+      }
+
+      IDT[ERROR_VECTOR as usize].set_handler_fn(apic_error);
+      IDT[SPURIOUS_VECTOR as usize].set_handler_fn(spurious);
 
       IDT.load();
    }
@@ -12,32 +49,169 @@ pub fn initialise() {
    log::info!("Added interrupt handlers to the IDT");
 }
 
-extern "x86-interrupt" fn breakpoint(frame: InterruptStackFrame) {
-   log::error!("EXCEPTION: BREAKPOINT\n{:#?}", frame);
+/// Brings up the interrupt controllers, leaving every IRQ masked until it has a handler.
+///
+/// Uses the local APIC and I/O APICs when the ACPI tables at `rsdp` describe them, falling
+/// back to the 8259 PICs. Needs the heap and the kernel page table.
+pub fn initialise_controllers(rsdp: Option<u64>) {
+   let controller = without_interrupts(|| {
+      if let Some(rsdp) = rsdp {
+         match unsafe { apic::initialise(rsdp) } {
+            Ok(()) => return InterruptController::Apic,
+            Err(error) => log::warn!("Falling back to the 8259 PIC: {}", error),
+         }
+      }
+
+      unsafe { pic::initialise(IRQ_BASE) };
+      return InterruptController::Pic;
+   });
+
+   CONTROLLER.call_once(|| controller);
+   log::info!("Hardware interrupts routed through the {:?}", controller);
+}
+
+/// The interrupt controller in use, once [`initialise_controllers`] has run.
+pub fn controller() -> Option<InterruptController> {
+   return CONTROLLER.get().copied();
 }
 
-extern "x86-interrupt" fn double_fault(frame: InterruptStackFrame, _: u64) -> ! {
-   log::error!("EXCEPTION: DOUBLE FAULT\n{:#?}", frame);
-   loop{}
+/// Starts delivering hardware interrupts to this CPU.
+pub fn enable() {
+   x86_64::instructions::interrupts::enable();
 }
 
-extern "x86-interrupt" fn page_fault(frame: InterruptStackFrame, code: PageFaultErrorCode) {
-   use x86_64::registers::control::Cr2;
+/// Installs `handler` for `irq` and unmasks the line.
+pub fn register_irq(irq: u8, handler: IrqHandler) -> Result<(), IrqError> {
+   if irq as usize >= IRQ_COUNT {
+      return Err(IrqError::OutOfRange);
+   }
 
-   log::error!("EXCEPTION: PAGE FAULT");
-   log::error!("Accessed address: {:?}", Cr2::read());
-   log::error!("Error code: {:?}", code);
-   log::error!("{:#?}", frame);
+   without_interrupts(|| {
+      let mut handlers = IRQ_HANDLERS.lock();
+      if handlers[irq as usize].is_some() {
+         return Err(IrqError::AlreadyRegistered);
+      }
 
-   loop{}
+      handlers[irq as usize] = Some(handler);
+      return Ok(());
+   })?;
+
+   set_masked(irq, false);
+   return Ok(());
 }
 
+/// Masks `irq` and removes its handler.
+pub fn unregister_irq(irq: u8) {
+   if irq as usize >= IRQ_COUNT {
+      return;
+   }
+
+   set_masked(irq, true);
+   without_interrupts(|| IRQ_HANDLERS.lock()[irq as usize] = None);
+}
+
+/// Masks or unmasks `irq` at whichever controller is in use.
+pub fn set_masked(irq: u8, masked: bool) {
+   match controller() {
+      Some(InterruptController::Apic) => {
+         if !apic::set_masked(irq, masked) {
+            log::warn!("No I/O APIC input for IRQ {}", irq);
+         }
+      }
+      Some(InterruptController::Pic) if irq < 16 => match masked {
+         true => pic::mask(irq),
+         false => pic::unmask(irq),
+      },
+      Some(InterruptController::Pic) => log::warn!("The 8259 PIC has no IRQ {}", irq),
+      None => log::warn!("IRQ {} changed before the interrupt controllers were initialised", irq),
+   }
+}
+
+/// Errors returned when registering an IRQ handler.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IrqError {
+   /// The IRQ has no vector of its own.
+   OutOfRange,
+
+   /// Another handler is already installed for the IRQ.
+   AlreadyRegistered,
+}
+
+impl Display for IrqError {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return match self {
+         IrqError::OutOfRange => write!(f, "IRQ out of range"),
+         IrqError::AlreadyRegistered => write!(f, "IRQ already has a handler"),
+      };
+   }
+}
+
+/// Runs the handler for `irq`, acknowledges it, then switches threads if the running one's
+/// time slice is used up.
+fn dispatch(irq: u8) {
+   if controller() == Some(InterruptController::Pic) && pic::is_spurious(irq) {
+      return;
+   }
+
+   let handler = IRQ_HANDLERS.lock()[irq as usize];
+   match handler {
+      Some(handler) => handler(irq),
+      None => log::warn!("Unhandled IRQ {}", irq),
+   }
+
+   end_of_interrupt(irq);
+   scheduler::preempt();
+}
+
+fn end_of_interrupt(irq: u8) {
+   match controller() {
+      Some(InterruptController::Apic) => apic::end_of_interrupt(),
+      _ => pic::end_of_interrupt(irq),
+   }
+}
+
+macro_rules! irq_stubs {
+   ($($irq:literal => $name:ident),* $(,)?) => {
+      $(
+         extern "x86-interrupt" fn $name(_: InterruptStackFrame) {
+            dispatch($irq);
+         }
+      )*
+
+      /// Entry points for each hardware interrupt, indexed by IRQ.
+      static IRQ_STUBS: [extern "x86-interrupt" fn(InterruptStackFrame); IRQ_COUNT] = [$($name),*];
+   };
+}
+
+irq_stubs! {
+   0 => irq0, 1 => irq1, 2 => irq2, 3 => irq3, 4 => irq4, 5 => irq5, 6 => irq6, 7 => irq7,
+   8 => irq8, 9 => irq9, 10 => irq10, 11 => irq11, 12 => irq12, 13 => irq13, 14 => irq14,
+   15 => irq15, 16 => irq16, 17 => irq17, 18 => irq18, 19 => irq19, 20 => irq20, 21 => irq21,
+   22 => irq22, 23 => irq23,
+}
+
+extern "x86-interrupt" fn apic_error(_: InterruptStackFrame) {
+   log::error!("Local APIC error");
+   apic::end_of_interrupt();
+}
+
+/// Spurious interrupts are never acknowledged.
+extern "x86-interrupt" fn spurious(_: InterruptStackFrame) {}
+
+// MODULES //
+
+/// CPU exception entry points and crash reporting.
+pub mod exceptions;
+
 // IMPORTS //
 
 use {
+   crate::{apic, pic, process::scheduler},
    base::log,
-   x86_64::structures::idt::{
-      InterruptDescriptorTable, InterruptStackFrame,
-      PageFaultErrorCode,
-   }
+   core::fmt::{self, Display},
+   spin::{Mutex, Once},
+   x86_64::{
+      instructions::interrupts::without_interrupts,
+      structures::idt::{InterruptDescriptorTable, InterruptStackFrame},
+   },
 };
@@ -0,0 +1,427 @@
+/// General-purpose registers saved by the exception entry stubs, in the order they sit on the
+/// stack.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Registers {
+   pub r15: u64,
+   pub r14: u64,
+   pub r13: u64,
+   pub r12: u64,
+   pub r11: u64,
+   pub r10: u64,
+   pub r9: u64,
+   pub r8: u64,
+   pub rbp: u64,
+   pub rdi: u64,
+   pub rsi: u64,
+   pub rdx: u64,
+   pub rcx: u64,
+   pub rbx: u64,
+   pub rax: u64,
+}
+
+/// Everything on the stack when an exception stub calls into Rust.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ExceptionFrame {
+   pub registers: Registers,
+
+   /// The exception vector.
+   pub vector: u64,
+
+   /// The error code pushed by the CPU, or zero for exceptions without one.
+   pub error_code: u64,
+
+   /// The frame pushed by the CPU.
+   pub stack: InterruptStackFrameValue,
+}
+
+/// Human-readable names of the exception vectors.
+pub const EXCEPTION_NAMES: [&str; 32] = [
+   "Divide error",
+   "Debug",
+   "Non-maskable interrupt",
+   "Breakpoint",
+   "Overflow",
+   "Bound range exceeded",
+   "Invalid opcode",
+   "Device not available",
+   "Double fault",
+   "Coprocessor segment overrun",
+   "Invalid TSS",
+   "Segment not present",
+   "Stack-segment fault",
+   "General protection fault",
+   "Page fault",
+   "Reserved",
+   "x87 floating-point exception",
+   "Alignment check",
+   "Machine check",
+   "SIMD floating-point exception",
+   "Virtualization exception",
+   "Control protection exception",
+   "Reserved",
+   "Reserved",
+   "Reserved",
+   "Reserved",
+   "Reserved",
+   "Reserved",
+   "Hypervisor injection exception",
+   "VMM communication exception",
+   "Security exception",
+   "Reserved",
+];
+
+/// Points every architecturally defined exception vector at its entry stub.
+///
+/// ## Safety
+///
+/// The GDT and its interrupt stacks must already be loaded.
+pub unsafe fn install(idt: &mut InterruptDescriptorTable) {
+   let address = |stub: unsafe extern "C" fn() -> !| VirtAddr::new(stub as usize as u64);
+
+   idt.divide_error.set_handler_addr(address(divide_error));
+   idt.debug.set_handler_addr(address(debug));
+   idt.non_maskable_interrupt.set_handler_addr(address(non_maskable_interrupt))
+      .set_stack_index(NMI_IST_INDEX);
+   idt.breakpoint.set_handler_addr(address(breakpoint));
+   idt.overflow.set_handler_addr(address(overflow));
+   idt.bound_range_exceeded.set_handler_addr(address(bound_range_exceeded));
+   idt.invalid_opcode.set_handler_addr(address(invalid_opcode));
+   idt.device_not_available.set_handler_addr(address(device_not_available));
+   idt.double_fault.set_handler_addr(address(double_fault))
+      .set_stack_index(DOUBLE_FAULT_IST_INDEX);
+   idt.invalid_tss.set_handler_addr(address(invalid_tss));
+   idt.segment_not_present.set_handler_addr(address(segment_not_present));
+   idt.stack_segment_fault.set_handler_addr(address(stack_segment_fault));
+   idt.general_protection_fault.set_handler_addr(address(general_protection_fault));
+   idt.page_fault.set_handler_addr(address(page_fault));
+   idt.x87_floating_point.set_handler_addr(address(x87_floating_point));
+   idt.alignment_check.set_handler_addr(address(alignment_check));
+   idt.machine_check.set_handler_addr(address(machine_check))
+      .set_stack_index(MACHINE_CHECK_IST_INDEX);
+   idt.simd_floating_point.set_handler_addr(address(simd_floating_point));
+   idt.virtualization.set_handler_addr(address(virtualization));
+   idt.cp_protection_exception.set_handler_addr(address(control_protection));
+   idt.hv_injection_exception.set_handler_addr(address(hypervisor_injection));
+   idt.vmm_communication_exception.set_handler_addr(address(vmm_communication));
+   idt.security_exception.set_handler_addr(address(security));
+}
+
+/// Called by [`exception_common`] with the saved state of the interrupted code.
+///
+/// Returning resumes the interrupted code with whatever is in `frame`.
+extern "C" fn dispatch(frame: &mut ExceptionFrame) {
+   match frame.vector {
+      // Debug traps and breakpoints are resumable, so they are reported without waiting on
+      // locks the interrupted code may hold.
+      DEBUG | BREAKPOINT => {
+         let name = EXCEPTION_NAMES[frame.vector as usize];
+         let _ = writeln!(TrapWriter, "{:5}: {} at {:#x}",
+            log::Level::Warn, name, frame.stack.instruction_pointer.as_u64());
+      }
+      // Faults inside a virtual memory area are backed on demand and the access retried.
+      PAGE_FAULT => {
+         let code = PageFaultErrorCode::from_bits_truncate(frame.error_code);
+         if let Err(error) = vma::handle_page_fault(Cr2::read(), code) {
+            fail(frame, Some(&error));
+         }
+      }
+      // A kernel stack overflow faults on the guard page and again delivering that fault.
+      DOUBLE_FAULT if vma::is_kernel_guard_page(Cr2::read()) => {
+         crash(frame, Some(&FaultError::StackOverflow));
+      }
+      _ => fail(frame, None),
+   }
+}
+
+/// Handles an exception that cannot be resolved: user code only loses its thread, while the
+/// kernel crashes.
+fn fail(frame: &ExceptionFrame, cause: Option<&dyn Display>) -> ! {
+   if frame.stack.code_segment & 0b11 != 3 {
+      crash(frame, cause);
+   }
+
+   let name = EXCEPTION_NAMES.get(frame.vector as usize).copied().unwrap_or("Unknown exception");
+   match cause {
+      Some(cause) => log::warn!("Thread {} killed by {} at {:#x}: {}",
+         scheduler::current(), name, frame.stack.instruction_pointer.as_u64(), cause),
+      None => log::warn!("Thread {} killed by {} at {:#x}",
+         scheduler::current(), name, frame.stack.instruction_pointer.as_u64()),
+   }
+
+   scheduler::exit();
+}
+
+/// Writes a crash report for `frame`, with the `cause` if known, to the framebuffer and serial
+/// port, then halts.
+pub fn crash(frame: &ExceptionFrame, cause: Option<&dyn Display>) -> ! {
+   x86_64::instructions::interrupts::disable();
+
+   let mut report = CrashWriter::take();
+   let _ = write_report(&mut report, frame, cause);
+
+   loop {
+      x86_64::instructions::hlt();
+   }
+}
+
+fn write_report(f: &mut impl Write, frame: &ExceptionFrame, cause: Option<&dyn Display>) -> fmt::Result {
+   let vector = frame.vector as usize;
+   let name = EXCEPTION_NAMES.get(vector).copied().unwrap_or("Unknown exception");
+   let registers = &frame.registers;
+   let stack = &frame.stack;
+
+   writeln!(f)?;
+   writeln!(f, "*** CPU EXCEPTION {}: {} ***", vector, name)?;
+   if let Some(cause) = cause {
+      writeln!(f, "Cause: {}", cause)?;
+   }
+   writeln!(f, "RIP {:#018x}  CS {:#06x}  RFLAGS {:#018x}",
+      stack.instruction_pointer.as_u64(), stack.code_segment, stack.cpu_flags)?;
+   writeln!(f, "RSP {:#018x}  SS {:#06x}", stack.stack_pointer.as_u64(), stack.stack_segment)?;
+
+   write!(f, "Error code {:#x}", frame.error_code)?;
+   match vector as u64 {
+      PAGE_FAULT => {
+         writeln!(f, ": {:?}", PageFaultErrorCode::from_bits_truncate(frame.error_code))?;
+         writeln!(f, "Faulting address {:#018x}", Cr2::read().as_u64())?;
+      }
+      INVALID_TSS | SEGMENT_NOT_PRESENT | STACK_SEGMENT_FAULT | GENERAL_PROTECTION_FAULT => {
+         writeln!(f, ": {}", SelectorErrorCode(frame.error_code))?;
+      }
+      CONTROL_PROTECTION => writeln!(f, ": {}", control_protection_cause(frame.error_code))?,
+      _ => writeln!(f)?,
+   }
+
+   writeln!(f, "RAX {:#018x}  RBX {:#018x}  RCX {:#018x}", registers.rax, registers.rbx, registers.rcx)?;
+   writeln!(f, "RDX {:#018x}  RSI {:#018x}  RDI {:#018x}", registers.rdx, registers.rsi, registers.rdi)?;
+   writeln!(f, "RBP {:#018x}  R8  {:#018x}  R9  {:#018x}", registers.rbp, registers.r8, registers.r9)?;
+   writeln!(f, "R10 {:#018x}  R11 {:#018x}  R12 {:#018x}", registers.r10, registers.r11, registers.r12)?;
+   writeln!(f, "R13 {:#018x}  R14 {:#018x}  R15 {:#018x}", registers.r13, registers.r14, registers.r15)?;
+
+   let (cr3, _) = Cr3::read_raw();
+   writeln!(f, "CR0 {:#018x}  CR2 {:#018x}", Cr0::read_raw(), Cr2::read().as_u64())?;
+   writeln!(f, "CR3 {:#018x}  CR4 {:#018x}", cr3.start_address().as_u64(), Cr4::read_raw())?;
+   writeln!(f, "{:#?}", stack)?;
+   writeln!(f, "System halted.")?;
+
+   return Ok(());
+}
+
+/// Decodes the selector error code pushed by #TS, #NP, #SS and #GP.
+struct SelectorErrorCode(u64);
+
+impl Display for SelectorErrorCode {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      if self.0 == 0 {
+         return write!(f, "no selector");
+      }
+
+      let table = match (self.0 >> 1) & 0b11 {
+         0 => "GDT",
+         2 => "LDT",
+         _ => "IDT",
+      };
+
+      write!(f, "{} index {}", table, (self.0 >> 3) & 0x1FFF)?;
+      if self.0 & 1 != 0 {
+         write!(f, " (external)")?;
+      }
+
+      return Ok(());
+   }
+}
+
+fn control_protection_cause(code: u64) -> &'static str {
+   return match code & 0x7FFF {
+      1 => "near return address mismatch",
+      2 => "far return address mismatch",
+      3 => "missing ENDBRANCH",
+      4 => "RSTORSSP token mismatch",
+      5 => "SETSSBSY token mismatch",
+      _ => "unknown cause",
+   };
+}
+
+/// Writes to the framebuffer and serial port, breaking any locks held by the crashed code.
+struct CrashWriter;
+
+impl CrashWriter {
+   fn take() -> Self {
+      if let Some(writer) = GLOBAL_WRITER.get() {
+         unsafe { writer.force_unlock() };
+      }
+
+      unsafe { COM2.force_unlock() };
+      return CrashWriter;
+   }
+}
+
+impl Write for CrashWriter {
+   fn write_str(&mut self, s: &str) -> fmt::Result {
+      if let Some(writer) = GLOBAL_WRITER.get().and_then(|writer| writer.writer.as_ref()) {
+         let _ = writer.lock().write_str(s);
+      }
+
+      let _ = COM2.lock().write_str(s);
+      return Ok(());
+   }
+}
+
+/// Writes to the framebuffer and serial port of the global writer, skipping whichever is
+/// locked.
+///
+/// Resumable exceptions use this, since breaking the locks would corrupt the writer the
+/// interrupted code is still using, and waiting on them would never end.
+struct TrapWriter;
+
+impl Write for TrapWriter {
+   fn write_str(&mut self, s: &str) -> fmt::Result {
+      let Some(writer) = GLOBAL_WRITER.get() else { return Ok(()) };
+
+      if let Some(mut framebuffer) = writer.writer.as_ref().and_then(|writer| writer.try_lock()) {
+         let _ = framebuffer.write_str(s);
+      }
+
+      if let Some(mut serial) = writer.serial.as_ref().and_then(|serial| serial.try_lock()) {
+         let _ = serial.write_str(s);
+      }
+
+      return Ok(());
+   }
+}
+
+const DEBUG: u64 = 1;
+const BREAKPOINT: u64 = 3;
+const DOUBLE_FAULT: u64 = 8;
+const INVALID_TSS: u64 = 10;
+const SEGMENT_NOT_PRESENT: u64 = 11;
+const STACK_SEGMENT_FAULT: u64 = 12;
+const GENERAL_PROTECTION_FAULT: u64 = 13;
+const PAGE_FAULT: u64 = 14;
+const CONTROL_PROTECTION: u64 = 21;
+
+/// Defines an entry stub for an exception vector.
+///
+/// Stubs for exceptions without an error code push a zero in its place, so every exception
+/// reaches [`exception_common`] with the same stack layout.
+macro_rules! exception_stub {
+   ($name:ident, $vector:literal) => {
+      #[naked]
+      unsafe extern "C" fn $name() -> ! {
+         asm!(
+            "push 0",
+            concat!("push ", $vector),
+            "jmp {common}",
+            common = sym exception_common,
+            options(noreturn),
+         );
+      }
+   };
+
+   ($name:ident, $vector:literal, error_code) => {
+      #[naked]
+      unsafe extern "C" fn $name() -> ! {
+         asm!(
+            concat!("push ", $vector),
+            "jmp {common}",
+            common = sym exception_common,
+            options(noreturn),
+         );
+      }
+   };
+}
+
+exception_stub!(divide_error, 0);
+exception_stub!(debug, 1);
+exception_stub!(non_maskable_interrupt, 2);
+exception_stub!(breakpoint, 3);
+exception_stub!(overflow, 4);
+exception_stub!(bound_range_exceeded, 5);
+exception_stub!(invalid_opcode, 6);
+exception_stub!(device_not_available, 7);
+exception_stub!(double_fault, 8, error_code);
+exception_stub!(invalid_tss, 10, error_code);
+exception_stub!(segment_not_present, 11, error_code);
+exception_stub!(stack_segment_fault, 12, error_code);
+exception_stub!(general_protection_fault, 13, error_code);
+exception_stub!(page_fault, 14, error_code);
+exception_stub!(x87_floating_point, 16);
+exception_stub!(alignment_check, 17, error_code);
+exception_stub!(machine_check, 18);
+exception_stub!(simd_floating_point, 19);
+exception_stub!(virtualization, 20);
+exception_stub!(control_protection, 21, error_code);
+exception_stub!(hypervisor_injection, 28);
+exception_stub!(vmm_communication, 29, error_code);
+exception_stub!(security, 30, error_code);
+
+/// Saves the general-purpose registers, hands the resulting [`ExceptionFrame`] to [`dispatch`],
+/// and returns from the exception with whatever state it leaves behind.
+///
+/// The CPU aligns the stack before pushing its frame, and the five words it pushes plus the
+/// vector, error code and fifteen registers keep it 16-byte aligned for the call.
+#[naked]
+unsafe extern "C" fn exception_common() -> ! {
+   asm!(
+      "push rax",
+      "push rbx",
+      "push rcx",
+      "push rdx",
+      "push rsi",
+      "push rdi",
+      "push rbp",
+      "push r8",
+      "push r9",
+      "push r10",
+      "push r11",
+      "push r12",
+      "push r13",
+      "push r14",
+      "push r15",
+      "mov rdi, rsp",
+      "cld",
+      "call {dispatch}",
+      "pop r15",
+      "pop r14",
+      "pop r13",
+      "pop r12",
+      "pop r11",
+      "pop r10",
+      "pop r9",
+      "pop r8",
+      "pop rbp",
+      "pop rdi",
+      "pop rsi",
+      "pop rdx",
+      "pop rcx",
+      "pop rbx",
+      "pop rax",
+      // Drop the vector and error code.
+      "add rsp, 16",
+      "iretq",
+      dispatch = sym dispatch,
+      options(noreturn),
+   );
+}
+
+// IMPORTS //
+
+use {
+   crate::{
+      gdt::{DOUBLE_FAULT_IST_INDEX, MACHINE_CHECK_IST_INDEX, NMI_IST_INDEX},
+      memory::vma::{self, FaultError},
+      process::scheduler,
+   },
+   base::{log, terminal::GLOBAL_WRITER, uart::COM2},
+   core::{
+      arch::asm,
+      fmt::{self, Display, Write},
+   },
+   x86_64::{
+      registers::control::{Cr0, Cr2, Cr3, Cr4},
+      structures::idt::{InterruptDescriptorTable, InterruptStackFrameValue, PageFaultErrorCode},
+      VirtAddr,
+   },
+};
@@ -1,4 +1,24 @@
+/// The kernel's active page table, once the heap has been built.
+///
+/// Like [`FRAME_ALLOCATOR`], only locked with interrupts disabled, so the page-fault handler
+/// and the heap grower never find it held by a preempted thread.
+pub static MAPPER: Mutex<Option<OffsetPageTable<'static>>> = Mutex::new(None);
+
+/// The kernel's physical frame allocator, once the heap has been built. Only locked with
+/// interrupts disabled.
+pub static FRAME_ALLOCATOR: Mutex<Option<SystemFrameAllocator>> = Mutex::new(None);
+
+/// Where the bootloader mapped all of physical memory, once [`initialise`] has run.
+static PHYSICAL_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+/// Start of the window device registers are mapped into by [`map_mmio`].
+pub const MMIO_START: u64 = 0xffff_d000_0000_0000;
+
+/// Next free address in the MMIO window.
+static MMIO_NEXT: AtomicU64 = AtomicU64::new(MMIO_START);
+
 pub unsafe fn initialise(physical_offset: VirtAddr) -> OffsetPageTable<'static> {
+   PHYSICAL_OFFSET.store(physical_offset.as_u64(), Ordering::Relaxed);
    let l4table = active_l4_page_table(physical_offset);
 
    log::info!("Got the level four page table.");
@@ -6,41 +26,117 @@ pub unsafe fn initialise(physical_offset: VirtAddr) -> OffsetPageTable<'static>
    return OffsetPageTable::new(l4table, physical_offset);
 }
 
-pub fn build_heap(
-   mapper: &mut impl Mapper<Size4KiB>,
-   frame_allocator: &mut impl FrameAllocator<Size4KiB>,
-) -> Result<(), MapToError<Size4KiB>> {
-   let mut heap = HEAP.lock();
+pub fn build_heap<M, A>(
+   mapper: &mut M,
+   frame_allocator: &mut A,
+) -> Result<(), MapToError<Size4KiB>>
+where
+   M: Mapper<Size4KiB> + Mapper<Size2MiB> + Mapper<Size1GiB>,
+   A: FrameAllocator<Size4KiB> + FrameAllocator<Size2MiB> + FrameAllocator<Size1GiB>
+      + FrameDeallocator<Size4KiB> + FrameDeallocator<Size2MiB> + FrameDeallocator<Size1GiB>,
+{
    let start_address = HEAP_START;
    let end_address = HEAP_START + HEAP_SIZE - 1usize;
 
-   let page_range = {
-      let heap_start_page = Page::containing_address(VirtAddr::new(start_address as u64));
-      let heap_end_page = Page::containing_address(VirtAddr::new(end_address as u64));
-      Page::range_inclusive(heap_start_page, heap_end_page)
+   let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+   map_range(
+      mapper,
+      VirtAddr::new(start_address as u64),
+      HEAP_SIZE as u64,
+      flags,
+      frame_allocator,
+   )?;
+
+   unsafe { init_heap(start_address, end_address) }.map_err(|_| MapToError::FrameAllocationFailed)?;
+
+   HEAP_MAPPED.store(HEAP_SIZE, Ordering::Relaxed);
+   set_heap_grower(grow_heap);
+
+   log::info!("Successfully initialised system heap.");
+
+   return Ok(());
+}
+
+/// The virtual address at which the bootloader mapped all of physical memory.
+pub fn physical_offset() -> VirtAddr {
+   return VirtAddr::new(PHYSICAL_OFFSET.load(Ordering::Relaxed));
+}
+
+/// Maps `size` bytes of device registers at `physical` into the MMIO window, uncached.
+///
+/// Returns the virtual address corresponding to `physical`. Needs the page table and frame
+/// allocator to have been handed over to [`MAPPER`] and [`FRAME_ALLOCATOR`].
+pub fn map_mmio(physical: PhysAddr, size: u64) -> Result<VirtAddr, MapToError<Size4KiB>> {
+   let start = physical.align_down(FRAME_SIZE);
+   let length = (physical + size).align_up(FRAME_SIZE) - start;
+
+   return without_interrupts(|| {
+      // The page table lock serialises callers, so the window only advances past mappings that
+      // succeeded.
+      let mut mapper = MAPPER.lock();
+      let mut frame_allocator = FRAME_ALLOCATOR.lock();
+      let virt = VirtAddr::new(MMIO_NEXT.load(Ordering::Relaxed));
+
+      let flags = PageTableFlags::PRESENT
+         | PageTableFlags::WRITABLE
+         | PageTableFlags::WRITE_THROUGH
+         | PageTableFlags::NO_CACHE;
+
+      map_physical_range(
+         mapper.as_mut().expect("page table not handed over to the kernel"),
+         virt,
+         start,
+         length,
+         flags,
+         frame_allocator.as_mut().expect("frame allocator not handed over to the kernel"),
+      )?;
+
+      MMIO_NEXT.store(virt.as_u64() + length, Ordering::Relaxed);
+      return Ok(virt + (physical - start));
+   });
+}
+
+/// Lets the heap grow into more of its window by mapping `size` bytes at `start`.
+///
+/// Registered as the heap's grower by [`build_heap`]. The range is mapped before the heap hands
+/// any of it out, so heap memory never has to be backed from inside a page fault. The grower
+/// runs with the heap locked and interrupts disabled, so it refuses to grow rather than wait if
+/// [`MAPPER`] or [`FRAME_ALLOCATOR`] is held, and unmaps whatever it managed to map on failure.
+fn grow_heap(start: usize, size: usize) -> bool {
+   let (Some(mut mapper), Some(mut frame_allocator)) = (MAPPER.try_lock(), FRAME_ALLOCATOR.try_lock()) else {
+      return false;
    };
 
-   for page in page_range {
-      let frame = frame_allocator
-         .allocate_frame()
-         .ok_or(MapToError::FrameAllocationFailed)?;
+   let (Some(mapper), Some(frame_allocator)) = (mapper.as_mut(), frame_allocator.as_mut()) else {
+      return false;
+   };
 
-      let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
-      unsafe {
-         mapper.map_to(page, frame, flags, frame_allocator)?.flush();
-      }
-   }
+   let start = VirtAddr::new(start as u64);
+   let pages = Page::<Size4KiB>::range(
+      Page::containing_address(start),
+      Page::containing_address(start + size as u64),
+   );
 
-   unsafe {
-      let mut blocks = Heap::new();
-      blocks.add_to_heap(start_address, end_address);
+   let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+   for page in pages {
+      let mapped = match FrameAllocator::<Size4KiB>::allocate_frame(frame_allocator) {
+         Some(frame) => map_new_page(mapper, page, frame, flags, frame_allocator),
+         None => Err(MapToError::FrameAllocationFailed),
+      };
 
-      *heap = Some(blocks);
-   }
+      if mapped.is_err() {
+         for page in Page::range(pages.start, page) {
+            if let Ok((frame, flush)) = mapper.unmap(page) {
+               flush.flush();
+               unsafe { frame_allocator.deallocate_frame(frame) };
+            }
+         }
 
-   log::info!("Successfully initialised system heap.");
+         return false;
+      }
+   }
 
-   return Ok(());
+   return true;
 }
 
 pub unsafe fn active_l4_page_table(phys_offset: VirtAddr) -> &'static mut PageTable {
@@ -58,11 +154,13 @@ pub unsafe fn active_l4_page_table(phys_offset: VirtAddr) -> &'static mut PageTa
 /// Translates the given virtual address to the mapped physical address, or
 /// `None` if the address is not mapped.
 ///
+/// Both 2 MiB (P2) and 1 GiB (P3) huge pages are handled, since the bootloader's
+/// physical memory mapping makes heavy use of them.
+///
 /// This function is unsafe because the caller must guarantee that the
 /// complete physical memory is mapped to virtual memory at the passed
 /// `physical_memory_offset`.
 pub fn translate_address(address: VirtAddr, physical_offset: VirtAddr) -> Option<PhysAddr> {
-   use x86_64::structures::paging::page_table::FrameError;
    use x86_64::registers::control::Cr3;
 
    let (l4Frame, _) = Cr3::read();
@@ -74,40 +172,334 @@ pub fn translate_address(address: VirtAddr, physical_offset: VirtAddr) -> Option
       address.p1_index(),
    ];
 
-   let mut frame = l4Frame;
+   let mut tableAddress = l4Frame.start_address();
 
    // Traverse the multi-level page table.
-   for &index in &tableIndices {
+   for (level, &index) in tableIndices.iter().enumerate() {
       // convert the frame into a page table reference
-      let virt = physical_offset + frame.start_address().as_u64();
+      let virt = physical_offset + tableAddress.as_u64();
       let table_pointer: *const PageTable = virt.as_ptr();
       let table = unsafe {&*table_pointer};
 
-      // read the page table entry and update `frame`
+      // read the page table entry and update `tableAddress`
       let entry = &table[index];
-      frame = match entry.frame() {
-         Ok(frame) => frame,
-         Err(FrameError::FrameNotPresent) => return None,
-         Err(FrameError::HugeFrame) => panic!("huge pages not supported"),
+      let flags = entry.flags();
+      if !flags.contains(PageTableFlags::PRESENT) {
+         return None;
+      }
+
+      // Bit 7 only means "huge page" in the P3 and P2 tables; in P1 it is the PAT bit.
+      // A huge page's own PAT bit sits at bit 12, inside the address field, so the frame is
+      // aligned down to the page size before the offset is added.
+      if flags.contains(PageTableFlags::HUGE_PAGE) {
+         let pageSize = match level {
+            1 => Size1GiB::SIZE,
+            2 => Size2MiB::SIZE,
+            3 => Size4KiB::SIZE,
+            _ => return None,
+         };
+
+         return Some(entry.addr().align_down(pageSize) + (address.as_u64() & (pageSize - 1)));
+      }
+
+      tableAddress = entry.addr();
+   }
+
+   return Some(tableAddress + u64::from(address.page_offset()));
+}
+
+/// Maps `size` bytes starting at `start` to freshly allocated frames.
+///
+/// 1 GiB and 2 MiB pages are used wherever the virtual address is suitably aligned and the
+/// frame allocator can provide a contiguous frame, falling back to 4 KiB pages otherwise.
+///
+/// On failure the frame allocated for the failing page is freed; pages mapped before it stay
+/// mapped for the caller to release.
+pub fn map_range<M, A>(
+   mapper: &mut M,
+   start: VirtAddr,
+   size: u64,
+   flags: PageTableFlags,
+   frame_allocator: &mut A,
+) -> Result<(), MapToError<Size4KiB>>
+where
+   M: Mapper<Size4KiB> + Mapper<Size2MiB> + Mapper<Size1GiB>,
+   A: FrameAllocator<Size4KiB> + FrameAllocator<Size2MiB> + FrameAllocator<Size1GiB>
+      + FrameDeallocator<Size4KiB> + FrameDeallocator<Size2MiB> + FrameDeallocator<Size1GiB>,
+{
+   let end = start + size;
+   let mut address = start;
+
+   while address < end {
+      let remaining = end - address;
+
+      if address.is_aligned(Size1GiB::SIZE) && remaining >= Size1GiB::SIZE {
+         if let Some(frame) = FrameAllocator::<Size1GiB>::allocate_frame(frame_allocator) {
+            map_new_page(mapper, Page::<Size1GiB>::containing_address(address), frame, flags, frame_allocator)?;
+            address += Size1GiB::SIZE;
+            continue;
+         }
+      }
+
+      if address.is_aligned(Size2MiB::SIZE) && remaining >= Size2MiB::SIZE {
+         if let Some(frame) = FrameAllocator::<Size2MiB>::allocate_frame(frame_allocator) {
+            map_new_page(mapper, Page::<Size2MiB>::containing_address(address), frame, flags, frame_allocator)?;
+            address += Size2MiB::SIZE;
+            continue;
+         }
+      }
+
+      let frame = FrameAllocator::<Size4KiB>::allocate_frame(frame_allocator)
+         .ok_or(MapToError::FrameAllocationFailed)?;
+      map_new_page(mapper, Page::<Size4KiB>::containing_address(address), frame, flags, frame_allocator)?;
+      address += Size4KiB::SIZE;
+   }
+
+   return Ok(());
+}
+
+/// Maps `size` bytes of existing physical memory starting at `physical` to the virtual
+/// range starting at `start`, e.g. for the framebuffer or other memory-mapped devices.
+///
+/// Huge pages are used wherever both addresses are suitably aligned. On failure, whatever part
+/// of the range was mapped is unmapped again, so the range can be reused.
+pub fn map_physical_range<M, A>(
+   mapper: &mut M,
+   start: VirtAddr,
+   physical: PhysAddr,
+   size: u64,
+   flags: PageTableFlags,
+   frame_allocator: &mut A,
+) -> Result<(), MapToError<Size4KiB>>
+where
+   M: Mapper<Size4KiB> + Mapper<Size2MiB> + Mapper<Size1GiB>,
+   A: FrameAllocator<Size4KiB>,
+{
+   let mut offset = 0;
+
+   while offset < size {
+      let virt = start + offset;
+      let phys = physical + offset;
+      let step = physical_page_size(virt, phys, size - offset);
+
+      let mapped = if step == Size1GiB::SIZE {
+         let frame = PhysFrame::<Size1GiB>::containing_address(phys);
+         map_page(mapper, Page::<Size1GiB>::containing_address(virt), frame, flags, frame_allocator)
+      } else if step == Size2MiB::SIZE {
+         let frame = PhysFrame::<Size2MiB>::containing_address(phys);
+         map_page(mapper, Page::<Size2MiB>::containing_address(virt), frame, flags, frame_allocator)
+      } else {
+         let frame = PhysFrame::<Size4KiB>::containing_address(phys);
+         map_page(mapper, Page::<Size4KiB>::containing_address(virt), frame, flags, frame_allocator)
+      };
+
+      if let Err(error) = mapped {
+         unmap_physical_range(mapper, start, physical, offset);
+         return Err(error);
+      }
+
+      offset += step;
+   }
+
+   return Ok(());
+}
+
+/// Unmaps the first `size` bytes of a range mapped by [`map_physical_range`], page by page as
+/// it mapped them. The frames are left alone, since they were never allocated.
+fn unmap_physical_range<M>(mapper: &mut M, start: VirtAddr, physical: PhysAddr, size: u64)
+where
+   M: Mapper<Size4KiB> + Mapper<Size2MiB> + Mapper<Size1GiB>,
+{
+   let mut offset = 0;
+
+   while offset < size {
+      let virt = start + offset;
+      let step = physical_page_size(virt, physical + offset, size - offset);
+
+      let unmapped = if step == Size1GiB::SIZE {
+         Mapper::<Size1GiB>::unmap(mapper, Page::containing_address(virt)).map(|(_, flush)| flush.flush())
+      } else if step == Size2MiB::SIZE {
+         Mapper::<Size2MiB>::unmap(mapper, Page::containing_address(virt)).map(|(_, flush)| flush.flush())
+      } else {
+         Mapper::<Size4KiB>::unmap(mapper, Page::containing_address(virt)).map(|(_, flush)| flush.flush())
       };
+
+      if unmapped.is_err() {
+         log::warn!("Failed to unmap {:#x} after a failed mapping", virt.as_u64());
+      }
+
+      offset += step;
    }
+}
+
+/// The largest page [`map_physical_range`] can map at `virt` to `phys` with `remaining` bytes
+/// left to map.
+fn physical_page_size(virt: VirtAddr, phys: PhysAddr, remaining: u64) -> u64 {
+   let fits = |size: u64| virt.is_aligned(size) && phys.is_aligned(size) && remaining >= size;
 
-   return Some(frame.start_address() + u64::from(address.page_offset()));
+   return if fits(Size1GiB::SIZE) {
+      Size1GiB::SIZE
+   } else if fits(Size2MiB::SIZE) {
+      Size2MiB::SIZE
+   } else {
+      Size4KiB::SIZE
+   };
 }
 
+/// Maps a single page of any size and flushes it from the TLB.
+///
+/// Errors are reported in terms of 4 KiB frames so callers mixing page sizes get a single
+/// error type.
+fn map_page<S, M, A>(
+   mapper: &mut M,
+   page: Page<S>,
+   frame: PhysFrame<S>,
+   flags: PageTableFlags,
+   frame_allocator: &mut A,
+) -> Result<(), MapToError<Size4KiB>>
+where
+   S: PageSize,
+   M: Mapper<S>,
+   A: FrameAllocator<Size4KiB>,
+{
+   unsafe {
+      match mapper.map_to(page, frame, flags, frame_allocator) {
+         Ok(flush) => flush.flush(),
+         Err(MapToError::FrameAllocationFailed) => return Err(MapToError::FrameAllocationFailed),
+         Err(MapToError::ParentEntryHugePage) => return Err(MapToError::ParentEntryHugePage),
+         Err(MapToError::PageAlreadyMapped(existing)) => {
+            return Err(MapToError::PageAlreadyMapped(
+               PhysFrame::containing_address(existing.start_address()),
+            ));
+         }
+      }
+   }
+
+   return Ok(());
+}
+
+/// Maps `page` to `frame`, a frame just allocated for it, handing the frame back to
+/// `frame_allocator` if the mapping fails.
+fn map_new_page<S, M, A>(
+   mapper: &mut M,
+   page: Page<S>,
+   frame: PhysFrame<S>,
+   flags: PageTableFlags,
+   frame_allocator: &mut A,
+) -> Result<(), MapToError<Size4KiB>>
+where
+   S: PageSize,
+   M: Mapper<S>,
+   A: FrameAllocator<Size4KiB> + FrameDeallocator<S>,
+{
+   return map_page(mapper, page, frame, flags, frame_allocator).map_err(|error| {
+      unsafe { frame_allocator.deallocate_frame(frame) };
+      error
+   });
+}
+
+/// Size of a single physical frame, in bytes.
+pub const FRAME_SIZE: u64 = 4096;
+
+/// A bitmap-based physical frame allocator built from the bootloader's memory map.
+///
+/// Every 4 KiB frame between the lowest and highest usable physical address is tracked by a
+/// single bit, where a set bit marks the frame as used. The bitmap itself is carved out of the
+/// first usable region large enough to hold it and is accessed through the physical memory
+/// mapping set up by the bootloader.
 pub struct SystemFrameAllocator {
+   /// The memory map handed to us by the bootloader.
    memory_map: &'static [MemoryRegion],
+
+   /// One bit per frame; `1` means the frame is in use.
+   bitmap: &'static mut [u64],
+
+   /// Physical address of the first frame tracked by the bitmap.
+   base: u64,
+
+   /// Total number of frames tracked by the bitmap.
+   frames: usize,
+
+   /// Number of frames currently available for allocation.
+   free: usize,
+
+   /// Index at which the next single-frame search starts.
    next: usize,
 }
 
 impl SystemFrameAllocator {
-   pub unsafe fn new(memory_map: &'static [MemoryRegion]) -> Self {
-      return SystemFrameAllocator{
+   /// Creates a new frame allocator from the given memory map.
+   ///
+   /// ## Safety
+   ///
+   /// The caller must guarantee that the memory map is valid, that all frames marked as
+   /// `Usable` are really unused, and that the complete physical memory is mapped at
+   /// `physical_offset`.
+   pub unsafe fn new(memory_map: &'static [MemoryRegion], physical_offset: VirtAddr) -> Self {
+      let usable = || memory_map.iter().filter(|r| r.kind == MemoryRegionKind::Usable);
+
+      let base = usable().map(|r| r.start).min().unwrap_or(0) & !(FRAME_SIZE - 1);
+      let top = usable().map(|r| r.end).max().unwrap_or(0);
+      let frames = (top.saturating_sub(base) / FRAME_SIZE) as usize;
+
+      let words = (frames + 63) / 64;
+      let bitmapBytes = (words * size_of::<u64>()) as u64;
+      let bitmapFrames = (bitmapBytes + FRAME_SIZE - 1) / FRAME_SIZE;
+
+      // Find a home for the bitmap itself.
+      let bitmapStart = usable()
+         .map(|r| ((r.start + FRAME_SIZE - 1) & !(FRAME_SIZE - 1), r.end))
+         .find(|&(start, end)| end.saturating_sub(start) >= bitmapFrames * FRAME_SIZE)
+         .map(|(start, _)| start)
+         .expect("no usable region large enough for the frame bitmap");
+
+      let pointer: *mut u64 = (physical_offset + bitmapStart).as_mut_ptr();
+      let bitmap = slice::from_raw_parts_mut(pointer, words);
+
+      // Everything starts out used; usable regions are then released frame by frame.
+      bitmap.fill(u64::MAX);
+
+      let mut allocator = SystemFrameAllocator{
          memory_map,
+         bitmap,
+         base,
+         frames,
+         free: 0,
          next: 0,
       };
+
+      for region in usable() {
+         let start = (region.start + FRAME_SIZE - 1) & !(FRAME_SIZE - 1);
+         let end = region.end & !(FRAME_SIZE - 1);
+
+         let mut address = start;
+         while address < end {
+            if let Some(index) = allocator.index_of(address) {
+               allocator.release(index);
+            }
+
+            address += FRAME_SIZE;
+         }
+      }
+
+      // Reserve the frames holding the bitmap.
+      for frame in 0..bitmapFrames {
+         if let Some(index) = allocator.index_of(bitmapStart + frame * FRAME_SIZE) {
+            allocator.reserve(index);
+         }
+      }
+
+      log::info!(
+         "Frame allocator tracking {} frames, {} free.",
+         allocator.frames,
+         allocator.free,
+      );
+
+      return allocator;
    }
 
+   /// Returns an iterator over every frame marked `Usable` in the memory map, regardless of
+   /// whether it is currently allocated.
    pub fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
       let regions = self.memory_map.iter();
       let usable = regions
@@ -117,28 +509,207 @@ impl SystemFrameAllocator {
       let frameAddresses = addressRanges.flat_map(|r| r.step_by(4096));
       return frameAddresses.map(|address| PhysFrame::containing_address(PhysAddr::new(address)));
    }
+
+   /// Number of frames currently available for allocation.
+   pub fn free_frames(&self) -> usize {
+      return self.free;
+   }
+
+   /// Number of tracked frames currently in use.
+   pub fn used_frames(&self) -> usize {
+      return self.frames - self.free;
+   }
+
+   /// Total number of frames tracked by the allocator.
+   pub fn total_frames(&self) -> usize {
+      return self.frames;
+   }
+
+   /// Allocates `count` physically contiguous frames whose first frame is aligned to
+   /// `align` frames, returning the first frame of the run.
+   ///
+   /// Useful for DMA buffers and other hardware that cannot scatter-gather.
+   pub fn allocate_contiguous(&mut self, count: usize, align: usize) -> Option<PhysFrame<Size4KiB>> {
+      if count == 0 || count > self.free {
+         return None;
+      }
+
+      let align = max(align, 1);
+      let mut index = 0;
+
+      while index + count <= self.frames {
+         // Align relative to physical address zero, not to the bitmap base.
+         let frameNumber = (self.base / FRAME_SIZE) as usize + index;
+         let misalignment = frameNumber % align;
+         if misalignment != 0 {
+            index += align - misalignment;
+            continue;
+         }
+
+         match (index..index + count).rev().find(|&i| self.is_used(i)) {
+            Some(used) => index = used + 1,
+            None => {
+               for i in index..index + count {
+                  self.reserve(i);
+               }
+
+               return Some(self.frame_at(index));
+            }
+         }
+      }
+
+      return None;
+   }
+
+   /// Returns a contiguous run of frames previously obtained from
+   /// [`allocate_contiguous`](SystemFrameAllocator::allocate_contiguous).
+   ///
+   /// Frames the allocator does not track are ignored.
+   ///
+   /// ## Safety
+   ///
+   /// The caller must ensure none of the frames are still in use.
+   pub unsafe fn deallocate_contiguous(&mut self, start: PhysFrame<Size4KiB>, count: usize) {
+      let Some(first) = self.index_of(start.start_address().as_u64()) else { return };
+
+      for index in first..first.saturating_add(count).min(self.frames) {
+         self.release(index);
+      }
+   }
+
+   /// The bitmap index of the frame at `address`, or `None` if the allocator does not track it.
+   fn index_of(&self, address: u64) -> Option<usize> {
+      let index = (address.checked_sub(self.base)? / FRAME_SIZE) as usize;
+      return (index < self.frames).then_some(index);
+   }
+
+   fn frame_at(&self, index: usize) -> PhysFrame<Size4KiB> {
+      let address = self.base + index as u64 * FRAME_SIZE;
+      return PhysFrame::containing_address(PhysAddr::new(address));
+   }
+
+   fn is_used(&self, index: usize) -> bool {
+      return self.bitmap[index / 64] & (1 << (index % 64)) != 0;
+   }
+
+   fn reserve(&mut self, index: usize) {
+      if !self.is_used(index) {
+         self.bitmap[index / 64] |= 1 << (index % 64);
+         self.free -= 1;
+      }
+   }
+
+   fn release(&mut self, index: usize) {
+      if self.is_used(index) {
+         self.bitmap[index / 64] &= !(1 << (index % 64));
+         self.free += 1;
+      }
+   }
 }
 
 unsafe impl FrameAllocator<Size4KiB> for SystemFrameAllocator {
    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
-      let frame = self.usable_frames().nth(self.next);
-      self.next += 1;
-      return frame;
+      if self.free == 0 {
+         return None;
+      }
+
+      let words = self.bitmap.len();
+      let startWord = self.next / 64;
+
+      // Skip over fully used words, wrapping around once.
+      for offset in 0..words {
+         let word = (startWord + offset) % words;
+         if self.bitmap[word] == u64::MAX {
+            continue;
+         }
+
+         let index = word * 64 + (!self.bitmap[word]).trailing_zeros() as usize;
+         if index >= self.frames {
+            continue;
+         }
+
+         self.reserve(index);
+         self.next = index + 1;
+         return Some(self.frame_at(index));
+      }
+
+      return None;
    }
 }
 
+impl FrameDeallocator<Size4KiB> for SystemFrameAllocator {
+   unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+      let Some(index) = self.index_of(frame.start_address().as_u64()) else { return };
+      self.release(index);
+
+      if index < self.next {
+         self.next = index;
+      }
+   }
+}
+
+unsafe impl FrameAllocator<Size2MiB> for SystemFrameAllocator {
+   fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+      let frames = (Size2MiB::SIZE / FRAME_SIZE) as usize;
+      let start = self.allocate_contiguous(frames, frames)?;
+      return Some(PhysFrame::containing_address(start.start_address()));
+   }
+}
+
+unsafe impl FrameAllocator<Size1GiB> for SystemFrameAllocator {
+   fn allocate_frame(&mut self) -> Option<PhysFrame<Size1GiB>> {
+      let frames = (Size1GiB::SIZE / FRAME_SIZE) as usize;
+      let start = self.allocate_contiguous(frames, frames)?;
+      return Some(PhysFrame::containing_address(start.start_address()));
+   }
+}
+
+impl FrameDeallocator<Size2MiB> for SystemFrameAllocator {
+   unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size2MiB>) {
+      let start = PhysFrame::containing_address(frame.start_address());
+      self.deallocate_contiguous(start, (Size2MiB::SIZE / FRAME_SIZE) as usize);
+   }
+}
+
+impl FrameDeallocator<Size1GiB> for SystemFrameAllocator {
+   unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size1GiB>) {
+      let start = PhysFrame::containing_address(frame.start_address());
+      self.deallocate_contiguous(start, (Size1GiB::SIZE / FRAME_SIZE) as usize);
+   }
+}
+
+// MODULES //
+
+/// Virtual memory areas and demand paging.
+pub mod vma;
+
 // IMPORTS //
 
 use {
-   base::{alloc::heap::{HEAP, Heap, HEAP_SIZE, HEAP_START}, log},
+   base::{
+      alloc::heap::{init_heap, set_heap_grower, HEAP_MAPPED, HEAP_SIZE, HEAP_START},
+      log,
+   },
+   core::{
+      cmp::max,
+      mem::size_of,
+      slice,
+      sync::atomic::{AtomicU64, Ordering},
+   },
+   spin::Mutex,
    springboard_api::info::{
       MemoryRegion, MemoryRegionKind,
    },
    x86_64::{
+      instructions::interrupts::without_interrupts,
       structures::paging::{
          FrameAllocator,
+         FrameDeallocator,
          PageTable,
+         PageSize,
          PhysFrame,
+         Size1GiB,
+         Size2MiB,
          Size4KiB,
          Mapper,
          OffsetPageTable,
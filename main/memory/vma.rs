@@ -0,0 +1,717 @@
+/// Every registered address space, keyed by the physical address of its level four table.
+///
+/// Only locked with interrupts disabled, like [`MAPPER`] and [`FRAME_ALLOCATOR`]; see
+/// [`FaultError::Locked`].
+pub static ADDRESS_SPACES: Mutex<BTreeMap<u64, AddressSpace>> = Mutex::new(BTreeMap::new());
+
+/// Root of the kernel's own address space, once [`initialise`] has run.
+static KERNEL_ROOT: Once<PhysFrame> = Once::new();
+
+/// Level four table entries covering user space, which user address spaces do not share.
+const USER_ENTRIES: Range<usize> = (USER_SPACE_START >> 39)..(USER_SPACE_END >> 39) + 1;
+
+/// Registers the kernel's own address space, whose only area so far is the heap window.
+///
+/// The window is reserved so nothing else is placed in it; the heap maps each part of it as it
+/// grows into it. Needs the page table and frame allocator to have been handed over to
+/// [`MAPPER`] and [`FRAME_ALLOCATOR`].
+pub fn initialise() {
+   let (root, _) = Cr3::read();
+   let mut space = AddressSpace::new(root);
+
+   let heap = VirtAddr::new(HEAP_START as u64);
+   space.reserve(heap, HEAP_WINDOW as u64, VmaFlags::READ | VmaFlags::WRITE, VmaKind::Heap)
+      .expect("heap window overlaps another area");
+
+   register(space);
+   KERNEL_ROOT.call_once(|| root);
+   log::info!("Kernel address space registered.");
+}
+
+/// The level four table kernel threads run on, and that [`AddressSpace::new_user`] copies the
+/// kernel's mappings from.
+///
+/// `None` until [`initialise`] has run, in which case the boot page table is still active.
+pub fn kernel_root() -> Option<PhysFrame> {
+   return KERNEL_ROOT.get().copied();
+}
+
+/// Makes `space` visible to the page-fault handler.
+pub fn register(space: AddressSpace) {
+   without_interrupts(|| {
+      ADDRESS_SPACES.lock().insert(space.root.start_address().as_u64(), space);
+   });
+}
+
+/// Removes the address space rooted at `root`, returning it.
+pub fn unregister(root: PhysFrame) -> Option<AddressSpace> {
+   return without_interrupts(|| ADDRESS_SPACES.lock().remove(&root.start_address().as_u64()));
+}
+
+/// Runs `f` on the address space the CPU is currently using, if it has been registered.
+///
+/// `f` runs with interrupts disabled, since the registry lock is held throughout.
+pub fn with_current<R>(f: impl FnOnce(&mut AddressSpace) -> R) -> Option<R> {
+   return without_interrupts(|| {
+      let (root, _) = Cr3::read();
+      return ADDRESS_SPACES.lock().get_mut(&root.start_address().as_u64()).map(f);
+   });
+}
+
+/// Runs `f` on the kernel's own address space, whichever page table is active.
+///
+/// `f` runs with interrupts disabled, since the registry lock is held throughout.
+pub fn with_kernel<R>(f: impl FnOnce(&mut AddressSpace) -> R) -> Option<R> {
+   let root = KERNEL_ROOT.get()?;
+   return without_interrupts(|| ADDRESS_SPACES.lock().get_mut(&root.start_address().as_u64()).map(f));
+}
+
+/// Reserves `size` bytes at `start` in the current address space; see [`AddressSpace::reserve`].
+pub fn reserve(start: VirtAddr, size: u64, flags: VmaFlags, kind: VmaKind) -> Result<(), VmaError> {
+   return with_current(|space| space.reserve(start, size, flags, kind))
+      .unwrap_or(Err(VmaError::NoAddressSpace));
+}
+
+/// Releases the area starting at `start` in the current address space; see
+/// [`AddressSpace::release`].
+pub fn release(start: VirtAddr) -> Result<Vma, VmaError> {
+   return with_current(|space| space.release(start))
+      .unwrap_or(Err(VmaError::NoAddressSpace));
+}
+
+/// Resolves a page fault at `address`, backing the page with a zeroed frame if it lies inside
+/// an area of the current address space and the access is allowed.
+///
+/// Runs inside the page-fault exception, so it never blocks: if the interrupted code holds any
+/// of the locks needed, the fault cannot be resolved and is reported as [`FaultError::Locked`].
+/// Those locks are only ever held with interrupts disabled, so no other thread can be preempted
+/// while holding one, and a lock found taken here was taken by the faulting code itself.
+pub fn handle_page_fault(address: VirtAddr, code: PageFaultErrorCode) -> Result<(), FaultError> {
+   // Only not-present faults can be resolved; the page is already backed otherwise.
+   if code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+      return Err(FaultError::Protection);
+   }
+
+   let page = Page::<Size4KiB>::containing_address(address);
+
+   let (root, _) = Cr3::read();
+   let flags = {
+      let spaces = ADDRESS_SPACES.try_lock().ok_or(FaultError::Locked)?;
+      let space = spaces.get(&root.start_address().as_u64()).ok_or(FaultError::Unmapped)?;
+
+      match space.find(address) {
+         Some(vma) => vma.flags,
+         None if space.is_guard_page(address) => return Err(FaultError::StackOverflow),
+         None => return Err(FaultError::Unmapped),
+      }
+   };
+
+   check_access(flags, code)?;
+   return back_page(page, flags);
+}
+
+/// Whether `address` is the guard page below one of the kernel's thread stacks.
+///
+/// A kernel thread that overflows its stack faults on the guard page, and the CPU then fails
+/// to push the page-fault frame onto that same stack, so the overflow surfaces as a double
+/// fault. Gives up rather than block if the address space lock is held.
+pub fn is_kernel_guard_page(address: VirtAddr) -> bool {
+   let Some(root) = KERNEL_ROOT.get() else { return false };
+
+   return ADDRESS_SPACES
+      .try_lock()
+      .and_then(|spaces| spaces.get(&root.start_address().as_u64()).map(|space| space.is_guard_page(address)))
+      .unwrap_or(false);
+}
+
+/// Whether the faulting access is allowed in an area with `flags`.
+///
+/// A present page is always readable, so areas without [`VmaFlags::READ`] are never backed.
+fn check_access(flags: VmaFlags, code: PageFaultErrorCode) -> Result<(), FaultError> {
+   let denied = !flags.contains(VmaFlags::READ)
+      || (code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) && !flags.contains(VmaFlags::WRITE))
+      || (code.contains(PageFaultErrorCode::INSTRUCTION_FETCH) && !flags.contains(VmaFlags::EXECUTE))
+      || (code.contains(PageFaultErrorCode::USER_MODE) && !flags.contains(VmaFlags::USER));
+
+   return match denied {
+      true => Err(FaultError::Protection),
+      false => Ok(()),
+   };
+}
+
+/// Maps a freshly zeroed frame at `page` in the current page table.
+fn back_page(page: Page<Size4KiB>, flags: VmaFlags) -> Result<(), FaultError> {
+   // The kernel page table lock also serialises edits to every other page table.
+   let _tables = MAPPER.try_lock().ok_or(FaultError::Locked)?;
+   let mut frame_allocator = FRAME_ALLOCATOR.try_lock().ok_or(FaultError::Locked)?;
+   let frame_allocator = frame_allocator.as_mut().ok_or(FaultError::OutOfMemory)?;
+
+   let frame: PhysFrame<Size4KiB> = frame_allocator.allocate_frame().ok_or(FaultError::OutOfMemory)?;
+   unsafe {
+      let contents = (physical_offset() + frame.start_address().as_u64()).as_mut_ptr::<u8>();
+      ptr::write_bytes(contents, 0, FRAME_SIZE as usize);
+   }
+
+   let (root, _) = Cr3::read();
+   let mut mapper = unsafe { page_table(root) };
+
+   return match map_page(&mut mapper, page, frame, flags.page_flags(), frame_allocator) {
+      Ok(()) => Ok(()),
+      Err(error) => {
+         unsafe { frame_allocator.deallocate_frame(frame) };
+
+         match error {
+            // Another fault got there first.
+            MapToError::PageAlreadyMapped(_) => Ok(()),
+            MapToError::FrameAllocationFailed => Err(FaultError::OutOfMemory),
+            MapToError::ParentEntryHugePage => Err(FaultError::Mapping),
+         }
+      }
+   };
+}
+
+/// Frees the page table `entry` points to, which is at `level`, and every table below it.
+///
+/// ## Safety
+///
+/// The caller must hold [`MAPPER`]'s lock, and nothing may be mapped through the tables any more.
+unsafe fn free_table(entry: &mut PageTableEntry, level: u8, frame_allocator: &mut SystemFrameAllocator) {
+   let frame = match entry.frame() {
+      Ok(frame) => frame,
+      Err(_) => return,
+   };
+
+   if level > 1 {
+      let table = &mut *table(frame);
+      for entry in table.iter_mut() {
+         free_table(entry, level - 1, frame_allocator);
+      }
+   }
+
+   entry.set_unused();
+   frame_allocator.deallocate_frame(frame);
+}
+
+/// The page table held in `frame`, through the physical memory mapping.
+unsafe fn table(frame: PhysFrame) -> *mut PageTable {
+   return (physical_offset() + frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
+}
+
+/// A mapper over the page table rooted at `root`.
+///
+/// ## Safety
+///
+/// `root` must hold a valid level four table, and the caller must hold [`MAPPER`]'s lock for as
+/// long as the mapper is used.
+unsafe fn page_table(root: PhysFrame) -> OffsetPageTable<'static> {
+   return OffsetPageTable::new(&mut *table(root), physical_offset());
+}
+
+/// Access permitted to a virtual memory area.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VmaFlags(u8);
+
+impl VmaFlags {
+   /// The area may be accessed at all; without it, like `PROT_NONE`, nothing is ever mapped.
+   pub const READ: Self = VmaFlags(1 << 0);
+   pub const WRITE: Self = VmaFlags(1 << 1);
+   pub const EXECUTE: Self = VmaFlags(1 << 2);
+
+   /// The area may be accessed from ring 3.
+   pub const USER: Self = VmaFlags(1 << 3);
+
+   pub const fn empty() -> Self {
+      return VmaFlags(0);
+   }
+
+   pub const fn contains(self, other: Self) -> bool {
+      return self.0 & other.0 == other.0;
+   }
+
+   /// The page table flags pages of the area are mapped with.
+   pub fn page_flags(self) -> PageTableFlags {
+      let mut flags = PageTableFlags::PRESENT;
+
+      if self.contains(VmaFlags::WRITE) {
+         flags |= PageTableFlags::WRITABLE;
+      }
+
+      if self.contains(VmaFlags::USER) {
+         flags |= PageTableFlags::USER_ACCESSIBLE;
+      }
+
+      // Setting NO_EXECUTE without EFER.NXE is a reserved-bit fault.
+      if !self.contains(VmaFlags::EXECUTE) && Efer::read().contains(EferFlags::NO_EXECUTE_ENABLE) {
+         flags |= PageTableFlags::NO_EXECUTE;
+      }
+
+      return flags;
+   }
+}
+
+impl BitOr for VmaFlags {
+   type Output = Self;
+
+   fn bitor(self, other: Self) -> Self {
+      return VmaFlags(self.0 | other.0);
+   }
+}
+
+/// What a virtual memory area is used for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VmaKind {
+   /// The kernel heap window.
+   Heap,
+
+   /// A stack, growing down towards an unreserved guard page.
+   Stack,
+
+   /// Zero-filled memory with no particular purpose.
+   Anonymous,
+}
+
+/// A page-aligned range of virtual memory that is backed with zeroed frames on first touch.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Vma {
+   pub start: VirtAddr,
+   pub end: VirtAddr,
+   pub flags: VmaFlags,
+   pub kind: VmaKind,
+}
+
+impl Vma {
+   pub fn contains(&self, address: VirtAddr) -> bool {
+      return self.start <= address && address < self.end;
+   }
+
+   pub fn size(&self) -> u64 {
+      return self.end - self.start;
+   }
+
+   fn overlaps(&self, start: VirtAddr, end: VirtAddr) -> bool {
+      return self.start < end && start < self.end;
+   }
+}
+
+/// The virtual memory areas of one page table.
+pub struct AddressSpace {
+   /// Frame holding the level four page table.
+   pub root: PhysFrame,
+
+   /// Areas keyed by their start address.
+   vmas: BTreeMap<u64, Vma>,
+
+   /// Lowest address the program break may take, once a program has been loaded.
+   break_start: Option<VirtAddr>,
+
+   /// End of the heap grown with `brk`; the memory up to the next page boundary is reserved.
+   program_break: VirtAddr,
+}
+
+impl AddressSpace {
+   pub fn new(root: PhysFrame) -> Self {
+      return AddressSpace{
+         root,
+         vmas: BTreeMap::new(),
+         break_start: None,
+         program_break: VirtAddr::zero(),
+      };
+   }
+
+   /// Creates an empty address space for a user program, to be registered once it is set up.
+   ///
+   /// The new level four table shares every top-level entry of the kernel's own table outside
+   /// user space, so the kernel, its heap and its stacks stay mapped while the program runs.
+   /// Kernel mappings later made under a top-level entry the kernel's table did not have yet are
+   /// not seen.
+   pub fn new_user() -> Result<Self, VmaError> {
+      let kernel = KERNEL_ROOT.get().ok_or(VmaError::NoAddressSpace)?;
+
+      return without_interrupts(|| {
+         let _tables = MAPPER.lock();
+         let mut frame_allocator = FRAME_ALLOCATOR.lock();
+         let frame_allocator = frame_allocator.as_mut().ok_or(VmaError::OutOfMemory)?;
+         let root: PhysFrame = frame_allocator.allocate_frame().ok_or(VmaError::OutOfMemory)?;
+
+         let source = unsafe { &*table(*kernel) };
+         let target = unsafe { &mut *table(root) };
+         target.zero();
+
+         for (index, entry) in source.iter().enumerate() {
+            if !USER_ENTRIES.contains(&index) {
+               target[index] = entry.clone();
+            } else if !entry.is_unused() {
+               // Something of the kernel's lives where programs are loaded.
+               unsafe { frame_allocator.deallocate_frame(root) };
+               return Err(VmaError::Overlaps);
+            }
+         }
+
+         return Ok(AddressSpace::new(root));
+      });
+   }
+
+   /// Frees everything in a user address space: its areas' pages, its user-half page tables and
+   /// the root table itself.
+   ///
+   /// The space must not be registered or active.
+   pub fn destroy(mut self) {
+      let (active, _) = Cr3::read();
+      assert_ne!(active, self.root, "destroying the active address space");
+
+      let starts: Vec<VirtAddr> = self.vmas().map(|vma| vma.start).collect();
+      for start in starts {
+         let _ = self.release(start);
+      }
+
+      without_interrupts(|| {
+         let _tables = MAPPER.lock();
+         let mut frame_allocator = FRAME_ALLOCATOR.lock();
+         let frame_allocator = frame_allocator.as_mut().expect("frame allocator not handed over to the kernel");
+
+         unsafe {
+            let level4 = &mut *table(self.root);
+            for index in USER_ENTRIES {
+               free_table(&mut level4[index], 3, frame_allocator);
+            }
+
+            frame_allocator.deallocate_frame(self.root);
+         }
+      });
+   }
+
+   /// Reserves `size` bytes at `start`, which are backed on demand rather than up front.
+   ///
+   /// Stacks should leave the page below `start` unreserved, so running off the end faults.
+   pub fn reserve(&mut self, start: VirtAddr, size: u64, flags: VmaFlags, kind: VmaKind) -> Result<(), VmaError> {
+      if !start.is_aligned(FRAME_SIZE) || size == 0 || size % FRAME_SIZE != 0 {
+         return Err(VmaError::Unaligned);
+      }
+
+      let end = VirtAddr::try_new(start.as_u64().checked_add(size).ok_or(VmaError::Unaligned)?)
+         .map_err(|_| VmaError::Unaligned)?;
+
+      if self.vmas.values().any(|vma| vma.overlaps(start, end)) {
+         return Err(VmaError::Overlaps);
+      }
+
+      self.vmas.insert(start.as_u64(), Vma{ start, end, flags, kind });
+      return Ok(());
+   }
+
+   /// Removes the area starting at `start`, unmapping and freeing whatever was backed.
+   pub fn release(&mut self, start: VirtAddr) -> Result<Vma, VmaError> {
+      let vma = self.vmas.remove(&start.as_u64()).ok_or(VmaError::NotFound)?;
+      self.unmap_pages(vma.start, vma.end);
+      return Ok(vma);
+   }
+
+   /// Removes `size` bytes at `start` from whichever areas they fall in, like `munmap`.
+   ///
+   /// Areas only partly inside the range are trimmed or split; parts of the range outside every
+   /// area are ignored.
+   pub fn unmap(&mut self, start: VirtAddr, size: u64) -> Result<(), VmaError> {
+      if !start.is_aligned(FRAME_SIZE) || size == 0 {
+         return Err(VmaError::Unaligned);
+      }
+
+      let end = VirtAddr::try_new(start.as_u64().checked_add(size).ok_or(VmaError::Unaligned)?)
+         .map_err(|_| VmaError::Unaligned)?
+         .align_up(FRAME_SIZE);
+
+      let overlapping: Vec<u64> = self.vmas.values()
+         .filter(|vma| vma.overlaps(start, end))
+         .map(|vma| vma.start.as_u64())
+         .collect();
+
+      for key in overlapping {
+         let vma = self.vmas.remove(&key).expect("area vanished while unmapping");
+
+         if vma.start < start {
+            self.vmas.insert(vma.start.as_u64(), Vma{ end: start, ..vma });
+         }
+
+         if end < vma.end {
+            self.vmas.insert(end.as_u64(), Vma{ start: end, ..vma });
+         }
+      }
+
+      self.unmap_pages(start, end);
+      return Ok(());
+   }
+
+   /// Lets the program break grow upwards from `start`, which should lie just past the loaded
+   /// program.
+   pub fn set_break_start(&mut self, start: VirtAddr) {
+      self.break_start = Some(start);
+      self.program_break = start;
+   }
+
+   /// The current program break, if a program has set one up.
+   pub fn program_break(&self) -> Option<VirtAddr> {
+      return self.break_start.map(|_| self.program_break);
+   }
+
+   /// Moves the program break to `address`, reserving or unmapping the pages in between, and
+   /// returns where the break ends up.
+   ///
+   /// Like Linux's `brk`, a refused move leaves the break where it was: it cannot drop below
+   /// where it started, nor grow into another area.
+   pub fn move_break(&mut self, address: VirtAddr) -> VirtAddr {
+      match self.break_start {
+         Some(start) if address >= start => {}
+         _ => return self.program_break,
+      }
+
+      let old = self.program_break.align_up(FRAME_SIZE);
+      let new = address.align_up(FRAME_SIZE);
+
+      let moved = if new > old {
+         let flags = VmaFlags::READ | VmaFlags::WRITE | VmaFlags::USER;
+         self.reserve(old, new - old, flags, VmaKind::Anonymous)
+      } else if new < old {
+         self.unmap(new, old - new)
+      } else {
+         Ok(())
+      };
+
+      if moved.is_ok() {
+         self.program_break = address;
+      }
+
+      return self.program_break;
+   }
+
+   /// The area containing `address`.
+   pub fn find(&self, address: VirtAddr) -> Option<&Vma> {
+      return self.vmas.range(..=address.as_u64())
+         .next_back()
+         .map(|(_, vma)| vma)
+         .filter(|vma| vma.contains(address));
+   }
+
+   /// Finds the lowest gap of at least `size` bytes between `bottom` and `top`.
+   ///
+   /// Returns `None` if no gap fits, including when the gap would run past the end of the
+   /// address space or into its non-canonical hole.
+   pub fn find_free(&self, size: u64, bottom: VirtAddr, top: VirtAddr) -> Option<VirtAddr> {
+      let end_of = |start: VirtAddr| VirtAddr::try_new(start.as_u64().checked_add(size)?).ok();
+      let mut candidate = bottom.align_up(FRAME_SIZE);
+
+      for vma in self.vmas.values() {
+         if vma.end <= candidate {
+            continue;
+         }
+
+         if vma.start >= end_of(candidate)? {
+            break;
+         }
+
+         candidate = vma.end;
+      }
+
+      return end_of(candidate).filter(|&end| end <= top).map(|_| candidate);
+   }
+
+   /// Every area, in address order.
+   pub fn vmas(&self) -> impl Iterator<Item = &Vma> {
+      return self.vmas.values();
+   }
+
+   /// Copies `bytes` into the space at `address`, backing pages as needed.
+   ///
+   /// Works whether or not the space is active, since it goes through the physical memory
+   /// mapping, and ignores the areas' permissions so read-only memory can be filled in. Areas
+   /// that cannot be read at all are refused, since mapping their pages would make them so.
+   pub fn write(&mut self, address: VirtAddr, bytes: &[u8]) -> Result<(), VmaError> {
+      return without_interrupts(|| {
+         let _tables = MAPPER.lock();
+         let mut frame_allocator = FRAME_ALLOCATOR.lock();
+         let frame_allocator = frame_allocator.as_mut().ok_or(VmaError::OutOfMemory)?;
+         let mut mapper = unsafe { page_table(self.root) };
+
+         let mut written = 0;
+         while written < bytes.len() {
+            let cursor = address + written as u64;
+            let flags = self.find(cursor).ok_or(VmaError::NotFound)?.flags;
+            if !flags.contains(VmaFlags::READ) {
+               return Err(VmaError::Inaccessible);
+            }
+
+            let page = Page::<Size4KiB>::containing_address(cursor);
+
+            let frame = match mapper.translate_page(page) {
+               Ok(frame) => frame,
+               Err(_) => {
+                  let frame: PhysFrame = frame_allocator.allocate_frame().ok_or(VmaError::OutOfMemory)?;
+                  unsafe {
+                     let contents = (physical_offset() + frame.start_address().as_u64()).as_mut_ptr::<u8>();
+                     ptr::write_bytes(contents, 0, FRAME_SIZE as usize);
+                  }
+
+                  if map_page(&mut mapper, page, frame, flags.page_flags(), frame_allocator).is_err() {
+                     unsafe { frame_allocator.deallocate_frame(frame) };
+                     return Err(VmaError::OutOfMemory);
+                  }
+
+                  frame
+               }
+            };
+
+            let offset = cursor - page.start_address();
+            let length = (FRAME_SIZE - offset).min((bytes.len() - written) as u64) as usize;
+            unsafe {
+               let target = (physical_offset() + frame.start_address().as_u64() + offset).as_mut_ptr::<u8>();
+               ptr::copy_nonoverlapping(bytes[written..].as_ptr(), target, length);
+            }
+
+            written += length;
+         }
+
+         return Ok(());
+      });
+   }
+
+   /// Unmaps and frees whatever pages between `start` and `end` were backed.
+   fn unmap_pages(&self, start: VirtAddr, end: VirtAddr) {
+      without_interrupts(|| {
+         let _tables = MAPPER.lock();
+         let mut frame_allocator = FRAME_ALLOCATOR.lock();
+         let frame_allocator = frame_allocator.as_mut().expect("frame allocator not handed over to the kernel");
+         let mut mapper = unsafe { page_table(self.root) };
+
+         let pages = Page::<Size4KiB>::range(Page::containing_address(start), Page::containing_address(end));
+         for page in pages {
+            if let Ok((frame, flush)) = mapper.unmap(page) {
+               flush.flush();
+               unsafe { frame_allocator.deallocate_frame(frame) };
+            }
+         }
+      });
+   }
+
+   /// Whether `address` is in the page just below a stack.
+   fn is_guard_page(&self, address: VirtAddr) -> bool {
+      return self.vmas.values().any(|vma| {
+         vma.kind == VmaKind::Stack
+            && address < vma.start
+            && address.as_u64() >= vma.start.as_u64().saturating_sub(FRAME_SIZE)
+      });
+   }
+}
+
+/// Errors returned when reserving or releasing areas.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VmaError {
+   /// The range is empty, not page-aligned or not canonical.
+   Unaligned,
+
+   /// The range overlaps an existing area.
+   Overlaps,
+
+   /// No area starts at the given address.
+   NotFound,
+
+   /// The current page table has no registered address space.
+   NoAddressSpace,
+
+   /// No frame was available for the memory or its page tables.
+   OutOfMemory,
+
+   /// The area cannot be accessed, so its pages may not be mapped.
+   Inaccessible,
+}
+
+impl Display for VmaError {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return match self {
+         VmaError::Unaligned => write!(f, "range is not page-aligned"),
+         VmaError::Overlaps => write!(f, "range overlaps an existing area"),
+         VmaError::NotFound => write!(f, "no area starts there"),
+         VmaError::NoAddressSpace => write!(f, "no address space registered"),
+         VmaError::OutOfMemory => write!(f, "out of physical memory"),
+         VmaError::Inaccessible => write!(f, "area cannot be accessed"),
+      };
+   }
+}
+
+/// Reasons a page fault could not be resolved.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FaultError {
+   /// The address lies outside every area.
+   Unmapped,
+
+   /// The address is in a stack's guard page.
+   StackOverflow,
+
+   /// The access is not allowed by the area's permissions.
+   Protection,
+
+   /// The faulting code holds a lock needed to resolve the fault.
+   ///
+   /// [`ADDRESS_SPACES`], [`MAPPER`] and [`FRAME_ALLOCATOR`] must only be taken with interrupts
+   /// disabled, so their holders cannot be preempted and this can only mean the fault was
+   /// raised while the faulting code held one of them.
+   Locked,
+
+   /// No frame was available to back the page.
+   OutOfMemory,
+
+   /// The page could not be mapped.
+   Mapping,
+}
+
+impl Display for FaultError {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return match self {
+         FaultError::Unmapped => write!(f, "address is not in any memory area"),
+         FaultError::StackOverflow => write!(f, "stack overflow"),
+         FaultError::Protection => write!(f, "access violates the area's permissions"),
+         FaultError::Locked => write!(f, "fault raised while the page tables were locked"),
+         FaultError::OutOfMemory => write!(f, "out of physical memory"),
+         FaultError::Mapping => write!(f, "page could not be mapped"),
+      };
+   }
+}
+
+// IMPORTS //
+
+use {
+   super::{map_page, physical_offset, SystemFrameAllocator, FRAME_ALLOCATOR, FRAME_SIZE, MAPPER},
+   crate::address::{USER_SPACE_END, USER_SPACE_START},
+   base::{
+      alloc::heap::{HEAP_START, HEAP_WINDOW},
+      log,
+   },
+   core::{
+      fmt::{self, Display},
+      ops::{BitOr, Range},
+      ptr,
+   },
+   spin::{Mutex, Once},
+   alloc::{collections::BTreeMap, vec::Vec},
+   x86_64::{
+      instructions::interrupts::without_interrupts,
+      registers::{
+         control::Cr3,
+         model_specific::{Efer, EferFlags},
+      },
+      structures::{
+         idt::PageFaultErrorCode,
+         paging::{
+            FrameAllocator,
+            FrameDeallocator,
+            Mapper,
+            OffsetPageTable,
+            Page,
+            PageTable,
+            PageTableFlags,
+            PhysFrame,
+            Size4KiB,
+            mapper::MapToError,
+            page_table::PageTableEntry,
+         },
+      },
+      VirtAddr,
+   },
+};
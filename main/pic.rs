@@ -0,0 +1,143 @@
+/// Command port of the master 8259.
+pub const PIC1_COMMAND: u16 = 0x20;
+
+/// Data port of the master 8259.
+pub const PIC1_DATA: u16 = 0x21;
+
+/// Command port of the slave 8259.
+pub const PIC2_COMMAND: u16 = 0xA0;
+
+/// Data port of the slave 8259.
+pub const PIC2_DATA: u16 = 0xA1;
+
+/// The master line the slave is cascaded through.
+pub const CASCADE_IRQ: u8 = 2;
+
+const ICW1_INIT: u8 = 0x10;
+const ICW1_ICW4: u8 = 0x01;
+const ICW4_8086: u8 = 0x01;
+const OCW3_READ_ISR: u8 = 0x0B;
+const END_OF_INTERRUPT: u8 = 0x20;
+
+/// Cached interrupt masks of the master (low byte) and slave (high byte).
+static MASK: Mutex<u16> = Mutex::new(0xFFFF);
+
+/// Remaps the PICs so IRQ 0-15 arrive on vectors `offset` through `offset + 15`, with every
+/// line masked except the cascade.
+///
+/// ## Safety
+///
+/// Must be called with interrupts disabled.
+pub unsafe fn initialise(offset: u8) {
+   // ICW1: start initialisation, expect ICW4.
+   outb(PIC1_COMMAND, ICW1_INIT | ICW1_ICW4);
+   wait();
+   outb(PIC2_COMMAND, ICW1_INIT | ICW1_ICW4);
+   wait();
+
+   // ICW2: vector offsets.
+   outb(PIC1_DATA, offset);
+   wait();
+   outb(PIC2_DATA, offset + 8);
+   wait();
+
+   // ICW3: the slave hangs off the master's IRQ 2.
+   outb(PIC1_DATA, 1 << CASCADE_IRQ);
+   wait();
+   outb(PIC2_DATA, CASCADE_IRQ);
+   wait();
+
+   // ICW4: 8086 mode.
+   outb(PIC1_DATA, ICW4_8086);
+   wait();
+   outb(PIC2_DATA, ICW4_8086);
+   wait();
+
+   write_mask(0xFFFF & !(1 << CASCADE_IRQ));
+}
+
+/// Masks every line, e.g. once the I/O APIC has taken over.
+///
+/// ## Safety
+///
+/// Must be called with interrupts disabled.
+pub unsafe fn disable() {
+   write_mask(0xFFFF);
+}
+
+/// Stops `irq` from being delivered.
+pub fn mask(irq: u8) {
+   without_interrupts(|| unsafe {
+      let mask = *MASK.lock() | 1 << irq;
+      write_mask(mask);
+   });
+}
+
+/// Allows `irq` to be delivered.
+pub fn unmask(irq: u8) {
+   without_interrupts(|| unsafe {
+      let mut mask = *MASK.lock() & !(1 << irq);
+
+      // Lines on the slave also need the cascade open.
+      if irq >= 8 {
+         mask &= !(1 << CASCADE_IRQ);
+      }
+
+      write_mask(mask);
+   });
+}
+
+/// Returns `true` if `irq` was raised spuriously, i.e. the line dropped before the PIC could
+/// deliver it. Only IRQ 7 and 15 can be spurious.
+///
+/// A spurious IRQ 15 has still been acknowledged by the master, which is sent its EOI here.
+pub fn is_spurious(irq: u8) -> bool {
+   let (command, line) = match irq {
+      7 => (PIC1_COMMAND, 7),
+      15 => (PIC2_COMMAND, 7),
+      _ => return false,
+   };
+
+   unsafe {
+      outb(command, OCW3_READ_ISR);
+      if inb(command) & (1 << line) != 0 {
+         return false;
+      }
+
+      if irq == 15 {
+         outb(PIC1_COMMAND, END_OF_INTERRUPT);
+      }
+   }
+
+   return true;
+}
+
+/// Acknowledges `irq`, so the PIC can deliver further interrupts at or below its priority.
+pub fn end_of_interrupt(irq: u8) {
+   unsafe {
+      if irq >= 8 {
+         outb(PIC2_COMMAND, END_OF_INTERRUPT);
+      }
+
+      outb(PIC1_COMMAND, END_OF_INTERRUPT);
+   }
+}
+
+unsafe fn write_mask(mask: u16) {
+   *MASK.lock() = mask;
+   outb(PIC1_DATA, mask as u8);
+   outb(PIC2_DATA, (mask >> 8) as u8);
+}
+
+/// Gives the PIC time to settle between initialisation words, by writing to an unused port.
+unsafe fn wait() {
+   outb(0x80, 0);
+}
+
+// IMPORTS //
+
+use {
+   spin::Mutex,
+   x86::io::{inb, outb},
+   x86_64::instructions::interrupts::without_interrupts,
+};
@@ -1,11 +1,39 @@
-/// Creates the initialisation process and calls the primary shell.
-pub fn initialise() -> usize {
-   0
+/// Creates the initialisation process from the executable `init`, the first program to run in
+/// user mode.
+pub fn initialise(init: &[u8]) -> Result<ThreadId, LoadError> {
+   return loader::spawn_program("init", init, &["init"], &["HOME=/", "TERM=linux"]);
 }
 
 // IMPORTS //
 
 // MODULES //
 
+/// Loads ELF64 executables into fresh user address spaces.
+pub mod loader;
+
+/// Blocking locks for kernel threads, with priority inheritance.
+pub mod mutex;
+
+/// Priority round-robin scheduling of kernel threads, preempted by the timer interrupt.
+pub mod scheduler;
+
+/// Guard-paged kernel thread stacks.
+pub mod stack;
+
+/// Line-buffered keyboard input for programs reading standard input.
+pub mod stdin;
+
+/// System call implementations and the table the entry stub dispatches through.
+pub mod syscalls;
+
 /// Task control block.
 pub mod task;
+
+// EXPORTS //
+
+pub use self::{
+   loader::{spawn_program, LoadError},
+   mutex::{Mutex, MutexGuard},
+   scheduler::{sleep, spawn_named_thread, spawn_thread, spawn_thread_at, thread_list, yield_now},
+   task::{Priority, Thread, ThreadId, ThreadInfo, ThreadState},
+};
@@ -0,0 +1,583 @@
+/// Largest argument and environment strings, taken together, that a program may start with.
+const MAX_ARGUMENTS: usize = 0x2_0000;
+
+/// Size of the thread control block placed after the initial TLS block; only its first word,
+/// which points at itself, is filled in.
+const TCB_SIZE: u64 = 0x100;
+
+// Identification.
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const EV_CURRENT: u8 = 1;
+const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
+const EM_X86_64: u16 = 62;
+
+// Program header types and flags.
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+const PT_PHDR: u32 = 6;
+const PT_TLS: u32 = 7;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+// Dynamic section tags and relocation types.
+const DT_NULL: i64 = 0;
+const DT_RELA: i64 = 7;
+const DT_RELASZ: i64 = 8;
+const DT_RELAENT: i64 = 9;
+const R_X86_64_NONE: u32 = 0;
+const R_X86_64_RELATIVE: u32 = 8;
+
+// Auxiliary vector keys.
+const AT_NULL: u64 = 0;
+const AT_PHDR: u64 = 3;
+const AT_PHENT: u64 = 4;
+const AT_PHNUM: u64 = 5;
+const AT_PAGESZ: u64 = 6;
+const AT_BASE: u64 = 7;
+const AT_FLAGS: u64 = 8;
+const AT_ENTRY: u64 = 9;
+const AT_UID: u64 = 11;
+const AT_EUID: u64 = 12;
+const AT_GID: u64 = 13;
+const AT_EGID: u64 = 14;
+const AT_SECURE: u64 = 23;
+const AT_RANDOM: u64 = 25;
+const AT_EXECFN: u64 = 31;
+
+/// Loads the ELF64 executable `image` into a fresh address space and starts a thread running it,
+/// with `arguments` and `environment` on its stack.
+///
+/// Static executables and static position-independent executables are supported; the latter
+/// are loaded at [`USER_SPACE_START`] and relocated. Programs needing an interpreter are not.
+pub fn spawn_program(name: &str, image: &[u8], arguments: &[&str], environment: &[&str]) -> Result<ThreadId, LoadError> {
+   let program = load(image, arguments, environment)?;
+   let (entry, stack) = (program.entry, program.stack);
+
+   let id = scheduler::spawn_in(name, program.root, program.thread_pointer, move || {
+      unsafe { context::enter_user_mode(entry, stack) };
+   });
+
+   return match id {
+      Ok(id) => {
+         log::info!("Started {} as thread {}, entering at {:#x}.", name, id, entry.as_u64());
+         Ok(id)
+      }
+      Err(error) => {
+         if let Some(space) = vma::unregister(program.root) {
+            space.destroy();
+         }
+
+         Err(LoadError::Thread(error))
+      }
+   };
+}
+
+/// A program loaded into its own address space, ready to run.
+struct Program {
+   root: PhysFrame,
+   entry: VirtAddr,
+
+   /// Initial stack pointer, pointing at `argc`.
+   stack: VirtAddr,
+
+   /// Initial FS base: the thread control block after the TLS block, or zero without one.
+   thread_pointer: u64,
+}
+
+/// Validates `image` and sets up a registered address space running it.
+fn load(image: &[u8], arguments: &[&str], environment: &[&str]) -> Result<Program, LoadError> {
+   let header: Header = read(image, 0)?;
+   check_header(&header)?;
+
+   let headers = (0..header.phnum as u64)
+      .map(|index| read::<ProgramHeader>(image, header.phoff.saturating_add(index * header.phentsize as u64)))
+      .collect::<Result<Vec<_>, _>>()?;
+
+   if headers.iter().any(|segment| segment.kind == PT_INTERP) {
+      return Err(LoadError::Interpreter);
+   }
+
+   let loads: Vec<&ProgramHeader> = headers.iter().filter(|segment| segment.kind == PT_LOAD).collect();
+   for segment in headers.iter().filter(|segment| segment.kind == PT_LOAD || segment.kind == PT_TLS) {
+      check_segment(image, segment)?;
+   }
+
+   // The lowest and highest addresses the program occupies, before relocation.
+   let lowest = loads.iter().map(|segment| segment.vaddr).min().ok_or(LoadError::Malformed)? & !(FRAME_SIZE - 1);
+   let highest = loads.iter().map(|segment| segment.vaddr + segment.memsz).max().ok_or(LoadError::Malformed)?;
+
+   let offset = match header.kind {
+      ET_DYN => VirtualAddressOffset::new(USER_SPACE_START as i128 - lowest as i128),
+      _ => VirtualAddressOffset::zero(),
+   };
+
+   let start = offset.offset() + lowest as i128;
+   let end = offset.offset() + highest as i128;
+   if start < USER_SPACE_START as i128 || end > USER_SPACE_END as i128 {
+      return Err(LoadError::OutOfUserSpace);
+   }
+
+   let entry = offset.offset() + header.entry as i128;
+   if entry < start || entry >= end {
+      return Err(LoadError::Malformed);
+   }
+
+   let mut space = AddressSpace::new_user()?;
+   let root = space.root;
+
+   return match populate(&mut space, image, &header, &headers, offset, arguments, environment) {
+      Ok((stack, thread_pointer)) => {
+         vma::register(space);
+         Ok(Program{ root, entry: VirtAddr::new(entry as u64), stack, thread_pointer })
+      }
+      Err(error) => {
+         space.destroy();
+         Err(error)
+      }
+   };
+}
+
+/// Maps the program's segments, relocates it and builds its TLS block and stack, returning the
+/// initial stack and thread pointers.
+fn populate(
+   space: &mut AddressSpace,
+   image: &[u8],
+   header: &Header,
+   headers: &[ProgramHeader],
+   offset: VirtualAddressOffset,
+   arguments: &[&str],
+   environment: &[&str],
+) -> Result<(VirtAddr, u64), LoadError> {
+   let mut top = VirtAddr::zero();
+
+   for segment in headers.iter().filter(|segment| segment.kind == PT_LOAD && segment.memsz > 0) {
+      let start = VirtAddr::new(offset + segment.vaddr);
+      let end = VirtAddr::new(offset + segment.vaddr + segment.memsz).align_up(FRAME_SIZE);
+      let base = start.align_down(FRAME_SIZE);
+
+      // A segment nothing may access is only reserved; its contents can never be seen.
+      let flags = segment_flags(segment.flags);
+      space.reserve(base, end - base, flags, VmaKind::Anonymous)?;
+      if flags.contains(VmaFlags::READ) {
+         space.write(start, file_bytes(image, segment))?;
+      }
+      top = top.max(end);
+   }
+
+   if header.kind == ET_DYN {
+      relocate(space, image, headers, offset)?;
+   }
+
+   // The heap grows from just past the program.
+   space.set_break_start(top);
+
+   let thread_pointer = match headers.iter().find(|segment| segment.kind == PT_TLS) {
+      Some(tls) => build_tls(space, image, tls)?,
+      None => 0,
+   };
+
+   let phdr = program_headers_address(header, headers, offset)?;
+   let stack = build_stack(space, header, phdr, offset, arguments, environment)?;
+
+   return Ok((stack, thread_pointer));
+}
+
+fn check_header(header: &Header) -> Result<(), LoadError> {
+   if header.ident[..4] != ELF_MAGIC {
+      return Err(LoadError::NotElf);
+   }
+
+   let supported = header.ident[4] == ELFCLASS64
+      && header.ident[5] == ELFDATA2LSB
+      && header.ident[6] == EV_CURRENT
+      && header.machine == EM_X86_64
+      && (header.kind == ET_EXEC || header.kind == ET_DYN);
+
+   if !supported {
+      return Err(LoadError::Unsupported);
+   }
+
+   if header.phentsize as usize != mem::size_of::<ProgramHeader>() || header.phnum == 0 {
+      return Err(LoadError::Malformed);
+   }
+
+   return Ok(());
+}
+
+fn check_segment(image: &[u8], segment: &ProgramHeader) -> Result<(), LoadError> {
+   let inFile = segment.offset.checked_add(segment.filesz).map_or(false, |end| end <= image.len() as u64);
+   let inMemory = segment.vaddr.checked_add(segment.memsz).map_or(false, |end| end <= USER_SPACE_END as u64);
+
+   if !inFile || !inMemory || segment.filesz > segment.memsz {
+      return Err(LoadError::Malformed);
+   }
+
+   if segment.kind == PT_TLS && (segment.align > FRAME_SIZE || segment.align & segment.align.wrapping_sub(1) != 0) {
+      return Err(LoadError::Malformed);
+   }
+
+   return Ok(());
+}
+
+/// The part of `segment` stored in the file.
+fn file_bytes<'a>(image: &'a [u8], segment: &ProgramHeader) -> &'a [u8] {
+   return &image[segment.offset as usize..(segment.offset + segment.filesz) as usize];
+}
+
+/// The area flags a segment's permissions ask for.
+fn segment_flags(flags: u32) -> VmaFlags {
+   let mut vmaFlags = VmaFlags::USER;
+
+   // Writable or executable pages are readable on x86 whatever the segment asks for.
+   if flags & (PF_R | PF_W | PF_X) != 0 {
+      vmaFlags = vmaFlags | VmaFlags::READ;
+   }
+
+   if flags & PF_W != 0 {
+      vmaFlags = vmaFlags | VmaFlags::WRITE;
+   }
+
+   if flags & PF_X != 0 {
+      vmaFlags = vmaFlags | VmaFlags::EXECUTE;
+   }
+
+   return vmaFlags;
+}
+
+/// Applies the relative relocations of a position-independent executable, which are all a
+/// static one has.
+///
+/// musl's static-PIE start code relocates itself too; relative relocations do not depend on
+/// what is already there, so applying them twice is harmless.
+fn relocate(space: &mut AddressSpace, image: &[u8], headers: &[ProgramHeader], offset: VirtualAddressOffset) -> Result<(), LoadError> {
+   let dynamic = match headers.iter().find(|segment| segment.kind == PT_DYNAMIC) {
+      Some(dynamic) => dynamic,
+      None => return Ok(()),
+   };
+
+   let (mut table, mut size, mut entrySize) = (None, 0, mem::size_of::<Rela>() as u64);
+   for index in 0..dynamic.filesz / mem::size_of::<Dyn>() as u64 {
+      let entry: Dyn = read(image, dynamic.offset + index * mem::size_of::<Dyn>() as u64)?;
+
+      match entry.tag {
+         DT_NULL => break,
+         DT_RELA => table = Some(entry.value),
+         DT_RELASZ => size = entry.value,
+         DT_RELAENT => entrySize = entry.value,
+         _ => {}
+      }
+   }
+
+   let table = match table {
+      Some(table) => file_offset(headers, table).ok_or(LoadError::Malformed)?,
+      None => return Ok(()),
+   };
+
+   if entrySize != mem::size_of::<Rela>() as u64 {
+      return Err(LoadError::Malformed);
+   }
+
+   for index in 0..size / entrySize {
+      let rela: Rela = read(image, table + index * entrySize)?;
+
+      match (rela.info & 0xffff_ffff) as u32 {
+         R_X86_64_NONE => {}
+         R_X86_64_RELATIVE => {
+            let target = offset.offset() + rela.offset as i128;
+            if target < USER_SPACE_START as i128 || target + 8 > USER_SPACE_END as i128 {
+               return Err(LoadError::Malformed);
+            }
+
+            let value = (offset.offset() + rela.addend as i128) as u64;
+            space.write(VirtAddr::new(target as u64), &value.to_le_bytes())?;
+         }
+         kind => return Err(LoadError::Relocation(kind)),
+      }
+   }
+
+   return Ok(());
+}
+
+/// Where the program's own virtual address `address` is stored in the file.
+fn file_offset(headers: &[ProgramHeader], address: u64) -> Option<u64> {
+   return headers.iter()
+      .filter(|segment| segment.kind == PT_LOAD)
+      .find(|segment| segment.vaddr <= address && address < segment.vaddr + segment.filesz)
+      .map(|segment| segment.offset + (address - segment.vaddr));
+}
+
+/// Where the program headers are once loaded, for `AT_PHDR`; the C library finds its TLS
+/// template there.
+fn program_headers_address(header: &Header, headers: &[ProgramHeader], offset: VirtualAddressOffset) -> Result<u64, LoadError> {
+   if let Some(phdr) = headers.iter().find(|segment| segment.kind == PT_PHDR) {
+      return Ok(offset + phdr.vaddr);
+   }
+
+   return headers.iter()
+      .filter(|segment| segment.kind == PT_LOAD)
+      .find(|segment| segment.offset <= header.phoff && header.phoff < segment.offset + segment.filesz)
+      .map(|segment| offset + segment.vaddr + (header.phoff - segment.offset))
+      .ok_or(LoadError::Malformed);
+}
+
+/// Sets up the initial thread's TLS block from the `PT_TLS` template, returning the thread
+/// pointer.
+///
+/// Uses the x86_64 layout: the block ends at the thread pointer, which points at a thread
+/// control block whose first word points back at itself.
+fn build_tls(space: &mut AddressSpace, image: &[u8], tls: &ProgramHeader) -> Result<u64, LoadError> {
+   let align = tls.align.max(16);
+   let blockSize = (tls.memsz + align - 1) & !(align - 1);
+   let size = (blockSize + TCB_SIZE + FRAME_SIZE - 1) & !(FRAME_SIZE - 1);
+
+   let bottom = VirtAddr::new(USER_MMAP_START as u64);
+   let start = space.find_free(size, bottom, VirtAddr::new(USER_SPACE_END as u64)).ok_or(VmaError::OutOfMemory)?;
+   space.reserve(start, size, VmaFlags::READ | VmaFlags::WRITE | VmaFlags::USER, VmaKind::Anonymous)?;
+
+   // The rest of the block, for `.tbss`, is left zeroed.
+   let threadPointer = start + blockSize;
+   space.write(start, file_bytes(image, tls))?;
+   space.write(threadPointer, &threadPointer.as_u64().to_le_bytes())?;
+
+   return Ok(threadPointer.as_u64());
+}
+
+/// Builds the initial stack the System V ABI describes below [`USER_STACK`], returning the
+/// stack pointer.
+///
+/// From the stack pointer up: `argc`, the argument pointers, a null, the environment pointers,
+/// a null, the auxiliary vector and, above those, the strings they point to.
+fn build_stack(
+   space: &mut AddressSpace,
+   header: &Header,
+   phdr: u64,
+   offset: VirtualAddressOffset,
+   arguments: &[&str],
+   environment: &[&str],
+) -> Result<VirtAddr, LoadError> {
+   let top = VirtAddr::new(USER_STACK as u64);
+   space.reserve(top - USER_STACK_SIZE as u64, USER_STACK_SIZE as u64, VmaFlags::READ | VmaFlags::WRITE | VmaFlags::USER, VmaKind::Stack)?;
+
+   let length: usize = arguments.iter().chain(environment).map(|string| string.len() + 1).sum();
+   if length > MAX_ARGUMENTS {
+      return Err(LoadError::ArgumentsTooLong);
+   }
+
+   // Strings go at the top: the AT_RANDOM bytes first, then every argument and variable.
+   let mut strings = Vec::with_capacity(16 + length);
+   strings.extend_from_slice(&random_bytes());
+
+   let mut pointers = Vec::with_capacity(arguments.len() + environment.len());
+   for string in arguments.iter().chain(environment) {
+      pointers.push(strings.len() as u64);
+      strings.extend_from_slice(string.as_bytes());
+      strings.push(0);
+   }
+
+   let stringsStart = (top - strings.len() as u64).align_down(16u64);
+   let address = |index: u64| stringsStart.as_u64() + index;
+   let execfn = pointers.first().map_or(0, |&index| address(index));
+
+   let auxiliary = [
+      (AT_PHDR, phdr),
+      (AT_PHENT, mem::size_of::<ProgramHeader>() as u64),
+      (AT_PHNUM, header.phnum as u64),
+      (AT_PAGESZ, FRAME_SIZE),
+      (AT_BASE, 0),
+      (AT_FLAGS, 0),
+      (AT_ENTRY, offset + header.entry),
+      (AT_UID, 0),
+      (AT_EUID, 0),
+      (AT_GID, 0),
+      (AT_EGID, 0),
+      (AT_SECURE, 0),
+      (AT_RANDOM, address(0)),
+      (AT_EXECFN, execfn),
+      (AT_NULL, 0),
+   ];
+
+   let mut words = Vec::with_capacity(3 + pointers.len() + 2 * auxiliary.len());
+   words.push(arguments.len() as u64);
+   words.extend(pointers[..arguments.len()].iter().map(|&index| address(index)));
+   words.push(0);
+   words.extend(pointers[arguments.len()..].iter().map(|&index| address(index)));
+   words.push(0);
+   words.extend(auxiliary.iter().flat_map(|&(key, value)| [key, value]));
+
+   // The ABI wants the stack pointer 16-byte aligned at the entry point.
+   let stack = (stringsStart - 8 * words.len() as u64).align_down(16u64);
+   let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+
+   space.write(stack, &bytes)?;
+   space.write(stringsStart, &strings)?;
+
+   return Ok(stack);
+}
+
+/// Bytes for `AT_RANDOM`, which C libraries seed stack canaries and pointer guards from.
+///
+/// There is no entropy source yet, so these are only the timestamp counter, scrambled.
+fn random_bytes() -> [u8; 16] {
+   let mut state = unsafe { _rdtsc() };
+   let mut next = || {
+      // SplitMix64.
+      state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+      let mut value = state;
+      value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+      value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+      return value ^ (value >> 31);
+   };
+
+   let mut bytes = [0; 16];
+   bytes[..8].copy_from_slice(&next().to_le_bytes());
+   bytes[8..].copy_from_slice(&next().to_le_bytes());
+   return bytes;
+}
+
+/// Reads a `T` from `image` at `offset`.
+fn read<T: Copy>(image: &[u8], offset: u64) -> Result<T, LoadError> {
+   let end = offset.checked_add(mem::size_of::<T>() as u64).ok_or(LoadError::Malformed)?;
+   if end > image.len() as u64 {
+      return Err(LoadError::Malformed);
+   }
+
+   return Ok(unsafe { ptr::read_unaligned(image.as_ptr().add(offset as usize) as *const T) });
+}
+
+/// The ELF64 file header.
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Header {
+   ident: [u8; 16],
+   kind: u16,
+   machine: u16,
+   version: u32,
+   entry: u64,
+   phoff: u64,
+   shoff: u64,
+   flags: u32,
+   ehsize: u16,
+   phentsize: u16,
+   phnum: u16,
+   shentsize: u16,
+   shnum: u16,
+   shstrndx: u16,
+}
+
+/// An ELF64 program header, describing one segment.
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct ProgramHeader {
+   kind: u32,
+   flags: u32,
+   offset: u64,
+   vaddr: u64,
+   paddr: u64,
+   filesz: u64,
+   memsz: u64,
+   align: u64,
+}
+
+/// An entry of the dynamic section.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Dyn {
+   tag: i64,
+   value: u64,
+}
+
+/// A relocation with an explicit addend.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Rela {
+   offset: u64,
+   info: u64,
+   addend: i64,
+}
+
+/// Reasons a program could not be loaded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LoadError {
+   /// The image does not start with the ELF magic number.
+   NotElf,
+
+   /// The image is not a 64-bit little-endian x86_64 executable.
+   Unsupported,
+
+   /// The program needs a dynamic linker.
+   Interpreter,
+
+   /// A header or segment lies outside the image or contradicts itself.
+   Malformed,
+
+   /// The program does not fit in user space.
+   OutOfUserSpace,
+
+   /// The program needs a relocation other than a relative one.
+   Relocation(u32),
+
+   /// The arguments and environment are too large for the stack.
+   ArgumentsTooLong,
+
+   /// The program's memory could not be set up.
+   Memory(VmaError),
+
+   /// The thread to run the program could not be started.
+   Thread(StackError),
+}
+
+impl From<VmaError> for LoadError {
+   fn from(error: VmaError) -> Self {
+      return LoadError::Memory(error);
+   }
+}
+
+impl Display for LoadError {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return match self {
+         LoadError::NotElf => write!(f, "not an ELF file"),
+         LoadError::Unsupported => write!(f, "not a 64-bit little-endian x86_64 executable"),
+         LoadError::Interpreter => write!(f, "dynamically linked programs are not supported"),
+         LoadError::Malformed => write!(f, "malformed ELF headers"),
+         LoadError::OutOfUserSpace => write!(f, "program does not fit in user space"),
+         LoadError::Relocation(kind) => write!(f, "unsupported relocation type {}", kind),
+         LoadError::ArgumentsTooLong => write!(f, "arguments and environment too long"),
+         LoadError::Memory(error) => write!(f, "could not set up memory: {}", error),
+         LoadError::Thread(error) => write!(f, "could not start thread: {}", error),
+      };
+   }
+}
+
+// IMPORTS //
+
+use {
+   super::{
+      scheduler,
+      stack::StackError,
+      task::ThreadId,
+   },
+   crate::{
+      address::{USER_MMAP_START, USER_SPACE_END, USER_SPACE_START, USER_STACK, USER_STACK_SIZE},
+      arch::x86_64::context,
+      memory::{
+         vma::{self, AddressSpace, VmaError, VmaFlags, VmaKind},
+         FRAME_SIZE,
+      },
+   },
+   alloc::vec::Vec,
+   base::{log, memory::VirtualAddressOffset},
+   core::{
+      arch::x86_64::_rdtsc,
+      fmt::{self, Display},
+      mem,
+      ptr,
+   },
+   x86_64::{structures::paging::PhysFrame, VirtAddr},
+};
@@ -0,0 +1,160 @@
+/// A mutual-exclusion lock that blocks waiting kernel threads instead of spinning.
+///
+/// While a thread waits, the owner runs at the waiter's priority if that is higher, so a
+/// low-priority owner cannot be starved by medium-priority threads while a high-priority one
+/// waits on it. Inheritance is one level deep: an owner blocked on a second mutex does not pass
+/// the boost on. The lock is handed straight to the longest waiter on unlock.
+///
+/// Must not be used from interrupt handlers, which cannot block.
+pub struct Mutex<T: ?Sized> {
+   state: SpinMutex<State>,
+   value: UnsafeCell<T>,
+}
+
+struct State {
+   owner: Option<ThreadId>,
+
+   /// Waiting threads and the priority each lent the owner.
+   waiters: VecDeque<(ThreadId, Priority)>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+   /// Creates an unlocked mutex holding `value`.
+   pub const fn new(value: T) -> Self {
+      return Mutex{
+         state: SpinMutex::new(State{ owner: None, waiters: VecDeque::new() }),
+         value: UnsafeCell::new(value),
+      };
+   }
+
+   /// Consumes the mutex, returning the value.
+   pub fn into_inner(self) -> T {
+      return self.value.into_inner();
+   }
+}
+
+impl<T: ?Sized> Mutex<T> {
+   /// Blocks the running thread until the lock is free, then takes it.
+   pub fn lock(&self) -> MutexGuard<'_, T> {
+      without_interrupts(|| {
+         let me = scheduler::current();
+         let mut state = self.state.lock();
+
+         match state.owner {
+            None => state.owner = Some(me),
+            Some(owner) if owner == me => panic!("thread {} locked a mutex it already holds", me),
+            Some(owner) => {
+               let priority = scheduler::priority(me);
+               state.waiters.push_back((me, priority));
+               scheduler::boost(owner, priority);
+               drop(state);
+
+               // Interrupts stay off until we are queued and blocked, so the hand-over cannot
+               // be missed; we own the lock once woken.
+               scheduler::block();
+            }
+         }
+      });
+
+      return MutexGuard{ mutex: self };
+   }
+
+   /// Takes the lock if it is free.
+   pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+      return without_interrupts(|| {
+         let mut state = self.state.lock();
+         if state.owner.is_some() {
+            return None;
+         }
+
+         state.owner = Some(scheduler::current());
+         return Some(MutexGuard{ mutex: self });
+      });
+   }
+
+   /// A mutable reference to the value; no locking is needed since the borrow is exclusive.
+   pub fn get_mut(&mut self) -> &mut T {
+      return self.value.get_mut();
+   }
+
+   /// Hands the lock to the longest waiter, moving the remaining waiters' boosts to it.
+   fn unlock(&self) {
+      without_interrupts(|| {
+         let mut state = self.state.lock();
+         let owner = state.owner.expect("unlocked a mutex nobody holds");
+
+         for &(_, priority) in state.waiters.iter() {
+            scheduler::unboost(owner, priority);
+         }
+
+         let next = state.waiters.pop_front().map(|(next, _)| next);
+         state.owner = next;
+
+         if let Some(next) = next {
+            for &(_, priority) in state.waiters.iter() {
+               scheduler::boost(next, priority);
+            }
+
+            drop(state);
+            scheduler::unblock(next);
+         }
+      });
+   }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return match self.try_lock() {
+         Some(guard) => f.debug_struct("Mutex").field("value", &&*guard).finish(),
+         None => f.debug_struct("Mutex").field("value", &format_args!("<locked>")).finish(),
+      };
+   }
+}
+
+/// Exclusive access to a [`Mutex`]'s value, released when dropped.
+#[must_use = "the lock is released as soon as the guard is dropped"]
+pub struct MutexGuard<'a, T: ?Sized> {
+   mutex: &'a Mutex<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for MutexGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+   type Target = T;
+
+   fn deref(&self) -> &T {
+      return unsafe { &*self.mutex.value.get() };
+   }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+   fn deref_mut(&mut self) -> &mut T {
+      return unsafe { &mut *self.mutex.value.get() };
+   }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+   fn drop(&mut self) {
+      self.mutex.unlock();
+   }
+}
+
+// IMPORTS //
+
+use {
+   super::{
+      scheduler,
+      task::{Priority, ThreadId},
+   },
+   alloc::collections::VecDeque,
+   core::{
+      cell::UnsafeCell,
+      fmt,
+      ops::{Deref, DerefMut},
+   },
+   spin::mutex::SpinMutex,
+   x86_64::instructions::interrupts::without_interrupts,
+};
@@ -0,0 +1,575 @@
+/// Timer ticks a thread may run before it makes way for another of the same priority.
+pub const TIME_SLICE_TICKS: u32 = 5;
+
+static SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler::new());
+
+/// Ticks left in the running thread's time slice.
+static SLICE_REMAINING: AtomicU32 = AtomicU32::new(TIME_SLICE_TICKS);
+
+/// Set from interrupt handlers when the running thread should be switched away from.
+static RESCHEDULE: AtomicBool = AtomicBool::new(false);
+
+/// Earliest deadline of any sleeping thread, in nanoseconds of uptime.
+static NEXT_WAKE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+const _: () = assert!(NUM_PRIORITIES <= u32::BITS as usize, "run queue bitmap is too small");
+
+/// Every thread, and a round-robin run queue for each priority.
+///
+/// Only ever locked with interrupts disabled, since the timer interrupt switches threads. Every
+/// queue has room for every thread, so nothing here allocates from an interrupt handler.
+struct Scheduler {
+   /// Boxed so a suspended thread's saved stack pointer stays put while the map changes.
+   threads: BTreeMap<ThreadId, Box<Thread>>,
+   queues: [VecDeque<ThreadId>; NUM_PRIORITIES],
+
+   /// Bit `n` is set while the queue for priority `n` is non-empty.
+   occupied: u32,
+
+   /// Sleeping threads and their deadlines, in nanoseconds of uptime.
+   sleepers: Vec<(u64, ThreadId)>,
+
+   current: ThreadId,
+
+   /// Uptime in nanoseconds when the running thread was switched in.
+   switched_in: u64,
+}
+
+impl Scheduler {
+   const fn new() -> Self {
+      const EMPTY: VecDeque<ThreadId> = VecDeque::new();
+
+      return Scheduler{
+         threads: BTreeMap::new(),
+         queues: [EMPTY; NUM_PRIORITIES],
+         occupied: 0,
+         sleepers: Vec::new(),
+         current: ThreadId::BOOT,
+         switched_in: 0,
+      };
+   }
+
+   fn thread(&mut self, id: ThreadId) -> &mut Thread {
+      return self.threads.get_mut(&id).expect("thread not registered");
+   }
+
+   fn enqueue(&mut self, id: ThreadId, priority: Priority) {
+      self.queues[priority.level()].push_back(id);
+      self.occupied |= 1 << priority.level();
+   }
+
+   /// Takes the next thread from the highest-priority non-empty queue.
+   fn dequeue(&mut self) -> Option<ThreadId> {
+      let level = (u32::BITS - 1).checked_sub(self.occupied.leading_zeros())? as usize;
+      let id = self.queues[level].pop_front();
+
+      if self.queues[level].is_empty() {
+         self.occupied &= !(1 << level);
+      }
+
+      return id;
+   }
+
+   fn unqueue(&mut self, id: ThreadId, priority: Priority) {
+      let queue = &mut self.queues[priority.level()];
+      if let Some(position) = queue.iter().position(|&queued| queued == id) {
+         queue.remove(position);
+      }
+
+      if queue.is_empty() {
+         self.occupied &= !(1 << priority.level());
+      }
+   }
+
+   /// Makes room for every thread in every queue, outside of interrupt handlers.
+   fn reserve(&mut self) {
+      let count = self.threads.len();
+
+      for queue in self.queues.iter_mut() {
+         queue.reserve(count.saturating_sub(queue.len()));
+      }
+
+      self.sleepers.reserve(count.saturating_sub(self.sleepers.len()));
+   }
+
+   fn make_ready(&mut self, id: ThreadId) {
+      let thread = self.thread(id);
+      thread.state = ThreadState::Ready;
+      let priority = thread.priority;
+
+      self.enqueue(id, priority);
+   }
+
+   /// Recomputes the priority `id` runs at, moving it between queues if it is ready.
+   fn reprioritise(&mut self, id: ThreadId) {
+      let thread = self.thread(id);
+      let (old, new) = (thread.priority, thread.effective_priority());
+      thread.priority = new;
+
+      if old != new && thread.state == ThreadState::Ready {
+         self.unqueue(id, old);
+         self.enqueue(id, new);
+      }
+   }
+
+   /// Whether a ready thread outranks the running one.
+   fn should_preempt(&self) -> bool {
+      let highest = match (u32::BITS - 1).checked_sub(self.occupied.leading_zeros()) {
+         Some(level) => level as usize,
+         None => return false,
+      };
+
+      return match self.threads.get(&self.current) {
+         Some(thread) => highest > thread.priority.level(),
+         None => false,
+      };
+   }
+
+   /// Readies every sleeper whose deadline has passed.
+   fn wake_sleepers(&mut self, now: u64) {
+      let mut next_wake = u64::MAX;
+      let mut index = 0;
+
+      while index < self.sleepers.len() {
+         let (deadline, id) = self.sleepers[index];
+
+         if deadline <= now {
+            self.sleepers.swap_remove(index);
+            self.make_ready(id);
+         } else {
+            next_wake = next_wake.min(deadline);
+            index += 1;
+         }
+      }
+
+      NEXT_WAKE.store(next_wake, Ordering::Relaxed);
+   }
+}
+
+/// Adopts the thread the kernel booted on and starts the idle thread.
+///
+/// From here on the async executor sleeps between ticks when it has nothing to do, rather than
+/// halting, so threads of any priority get the CPU meanwhile.
+pub fn initialise() {
+   without_interrupts(|| {
+      let mut scheduler = SCHEDULER.lock();
+      scheduler.threads.insert(ThreadId::BOOT, Box::new(Thread::boot()));
+      scheduler.switched_in = uptime_nanos();
+   });
+
+   spawn(Some(String::from("idle")), Priority::IDLE, idle).expect("failed to start the idle thread");
+   executor::set_idle_handler(idle_executor);
+
+   log::info!("Scheduler running {} priorities; time slice is {} ticks.", NUM_PRIORITIES, TIME_SLICE_TICKS);
+}
+
+/// Starts a kernel thread running `entry` at [`Priority::NORMAL`].
+pub fn spawn_thread<F>(entry: F) -> Result<ThreadId, StackError>
+where
+   F: FnOnce() + Send + 'static,
+{
+   return spawn(None, Priority::NORMAL, entry);
+}
+
+/// Starts a named kernel thread running `entry` at [`Priority::NORMAL`].
+pub fn spawn_named_thread<F>(name: &str, entry: F) -> Result<ThreadId, StackError>
+where
+   F: FnOnce() + Send + 'static,
+{
+   return spawn(Some(String::from(name)), Priority::NORMAL, entry);
+}
+
+/// Starts a named kernel thread running `entry` at `priority`.
+pub fn spawn_thread_at<F>(name: &str, priority: Priority, entry: F) -> Result<ThreadId, StackError>
+where
+   F: FnOnce() + Send + 'static,
+{
+   return spawn(Some(String::from(name)), priority.max(Priority::LOWEST), entry);
+}
+
+/// Starts a thread at [`Priority::NORMAL`] that runs `entry` in the user address space rooted
+/// at `root`, with its FS base at `fs_base`.
+///
+/// The thread takes ownership of the address space, which is destroyed once it has finished.
+pub(super) fn spawn_in<F>(name: &str, root: PhysFrame, fs_base: u64, entry: F) -> Result<ThreadId, StackError>
+where
+   F: FnOnce() + Send + 'static,
+{
+   reap();
+
+   let mut thread = Box::new(Thread::new(Some(String::from(name)), Priority::NORMAL, entry)?);
+   thread.address_space = Some(root);
+   thread.fs_base = fs_base;
+
+   return Ok(start(thread));
+}
+
+fn spawn<F>(name: Option<String>, priority: Priority, entry: F) -> Result<ThreadId, StackError>
+where
+   F: FnOnce() + Send + 'static,
+{
+   reap();
+
+   let thread = Box::new(Thread::new(name, priority, entry)?);
+   return Ok(start(thread));
+}
+
+/// Makes a new thread ready, switching to it straight away if it outranks the caller.
+fn start(thread: Box<Thread>) -> ThreadId {
+   let id = thread.id;
+
+   without_interrupts(|| {
+      let mut scheduler = SCHEDULER.lock();
+      scheduler.threads.insert(id, thread);
+      scheduler.reserve();
+      scheduler.make_ready(id);
+
+      if scheduler.should_preempt() {
+         drop(scheduler);
+         switch(ThreadState::Ready);
+      }
+   });
+
+   return id;
+}
+
+/// The thread running right now.
+pub fn current() -> ThreadId {
+   return without_interrupts(|| SCHEDULER.lock().current);
+}
+
+/// Gives the rest of the time slice to the next ready thread of the same or higher priority.
+pub fn yield_now() {
+   without_interrupts(|| switch(ThreadState::Ready));
+}
+
+/// Suspends the running thread for at least `duration`.
+pub fn sleep(duration: Duration) {
+   sleep_until(time::uptime().saturating_add(duration));
+}
+
+/// Suspends the running thread until `deadline`, measured as uptime.
+///
+/// Sleepers are woken by the timer interrupt, so the wait is rounded up to the next tick.
+pub fn sleep_until(deadline: Duration) {
+   let deadline = u64::try_from(deadline.as_nanos()).unwrap_or(u64::MAX);
+
+   without_interrupts(|| {
+      {
+         let mut scheduler = SCHEDULER.lock();
+         let id = scheduler.current;
+         if !scheduler.threads.contains_key(&id) {
+            return;
+         }
+
+         scheduler.sleepers.push((deadline, id));
+      }
+
+      NEXT_WAKE.fetch_min(deadline, Ordering::Relaxed);
+      switch(ThreadState::Sleeping);
+   });
+}
+
+/// Ends the running thread. Its stack is freed the next time a thread is spawned.
+pub fn exit() -> ! {
+   interrupts::disable();
+   switch(ThreadState::Finished);
+
+   unreachable!("finished thread was resumed");
+}
+
+/// Changes the base priority of thread `id`; it may still run higher while it holds a mutex.
+pub fn set_priority(id: ThreadId, priority: Priority) -> Result<(), ThreadError> {
+   if priority == Priority::IDLE {
+      return Err(ThreadError::ReservedPriority);
+   }
+
+   return without_interrupts(|| {
+      let mut scheduler = SCHEDULER.lock();
+      scheduler.threads.get_mut(&id).ok_or(ThreadError::NotFound)?.base_priority = priority;
+      scheduler.reprioritise(id);
+
+      if scheduler.should_preempt() {
+         drop(scheduler);
+         switch(ThreadState::Ready);
+      }
+
+      return Ok(());
+   });
+}
+
+/// A snapshot of every thread, including how much CPU time each has used.
+pub fn thread_list() -> Vec<ThreadInfo> {
+   let now = uptime_nanos();
+
+   return without_interrupts(|| {
+      let scheduler = SCHEDULER.lock();
+      let running = now.saturating_sub(scheduler.switched_in);
+
+      return scheduler.threads.values()
+         .map(|thread| thread.info(if thread.id == scheduler.current { running } else { 0 }))
+         .collect();
+   });
+}
+
+/// Counts down the running thread's time slice and wakes sleepers; called from the timer
+/// interrupt.
+pub fn tick() {
+   if SLICE_REMAINING.fetch_sub(1, Ordering::Relaxed) <= 1 {
+      RESCHEDULE.store(true, Ordering::Relaxed);
+   }
+
+   let now = uptime_nanos();
+   if now >= NEXT_WAKE.load(Ordering::Relaxed) {
+      let mut scheduler = SCHEDULER.lock();
+      scheduler.wake_sleepers(now);
+
+      if scheduler.should_preempt() {
+         RESCHEDULE.store(true, Ordering::Relaxed);
+      }
+   }
+}
+
+/// Switches away from the running thread if its time slice is used up or a higher-priority
+/// thread has woken.
+///
+/// Called at the end of every hardware interrupt, once it has been acknowledged, so the
+/// controller keeps delivering interrupts to whichever thread runs next.
+pub fn preempt() {
+   if RESCHEDULE.swap(false, Ordering::Relaxed) {
+      switch(ThreadState::Ready);
+   }
+}
+
+/// The priority thread `id` currently runs at.
+pub(super) fn priority(id: ThreadId) -> Priority {
+   return without_interrupts(|| SCHEDULER.lock().thread(id).priority);
+}
+
+/// Suspends the running thread until [`unblock`] readies it.
+///
+/// Interrupts must be disabled from before the thread registered itself with whatever will
+/// unblock it, so the wake-up cannot come first.
+pub(super) fn block() {
+   switch(ThreadState::Blocked);
+}
+
+/// Readies the blocked thread `id`, switching to it straight away if it outranks the caller.
+pub(super) fn unblock(id: ThreadId) {
+   without_interrupts(|| {
+      let mut scheduler = SCHEDULER.lock();
+      if scheduler.thread(id).state != ThreadState::Blocked {
+         return;
+      }
+
+      scheduler.make_ready(id);
+
+      if scheduler.should_preempt() {
+         drop(scheduler);
+         switch(ThreadState::Ready);
+      }
+   });
+}
+
+/// Lends `priority` to thread `id` until a matching [`unboost`].
+///
+/// Does nothing if `id` has already been reaped, e.g. because it exited holding a mutex.
+pub(super) fn boost(id: ThreadId, priority: Priority) {
+   without_interrupts(|| {
+      let mut scheduler = SCHEDULER.lock();
+      let Some(thread) = scheduler.threads.get_mut(&id) else { return };
+
+      let count = &mut thread.boosts[priority.level()];
+      *count = count.saturating_add(1);
+      scheduler.reprioritise(id);
+   });
+}
+
+/// Takes back a priority lent by [`boost`].
+///
+/// Boosts are cleared when a thread exits, so one taken back from a thread that exited holding
+/// a mutex, or was reaped since, is ignored.
+pub(super) fn unboost(id: ThreadId, priority: Priority) {
+   without_interrupts(|| {
+      let mut scheduler = SCHEDULER.lock();
+      let Some(thread) = scheduler.threads.get_mut(&id) else { return };
+
+      let count = &mut thread.boosts[priority.level()];
+      *count = count.saturating_sub(1);
+      scheduler.reprioritise(id);
+   });
+}
+
+/// Puts the running thread into `state` and resumes the highest-priority ready thread,
+/// returning once the running thread is switched back to.
+///
+/// Interrupts must be disabled.
+fn switch(state: ThreadState) {
+   SLICE_REMAINING.store(TIME_SLICE_TICKS, Ordering::Relaxed);
+   let now = uptime_nanos();
+
+   let (old, new) = {
+      let mut scheduler = SCHEDULER.lock();
+      let current = scheduler.current;
+
+      let priority = match scheduler.threads.get_mut(&current) {
+         Some(thread) => {
+            thread.state = state;
+
+            // A thread that exits holding a mutex keeps no priority lent by its waiters.
+            if state == ThreadState::Finished {
+               thread.boosts = [0; NUM_PRIORITIES];
+            }
+
+            thread.priority
+         }
+         None => return,
+      };
+
+      if state == ThreadState::Ready {
+         scheduler.enqueue(current, priority);
+      }
+
+      // The idle thread is always ready when not running, so something is always found.
+      let next = scheduler.dequeue().expect("no thread left to run");
+      if next == current {
+         scheduler.thread(current).state = ThreadState::Running;
+         return;
+      }
+
+      let running = now.saturating_sub(scheduler.switched_in);
+      let old = {
+         let thread = scheduler.thread(current);
+         thread.cpu_nanos += running;
+         thread.fs_base = FsBase::read().as_u64();
+         context::save_fpu(&mut thread.fpu);
+         &mut thread.rsp as *mut u64
+      };
+
+      let new = {
+         let thread = scheduler.thread(next);
+         thread.state = ThreadState::Running;
+         thread.switches += 1;
+
+         // Traps from user mode must land on the thread's own kernel stack.
+         if let Some(stack) = thread.stack() {
+            gdt::set_kernel_stack(stack.top());
+         }
+
+         FsBase::write(VirtAddr::new(thread.fs_base));
+         context::restore_fpu(&thread.fpu);
+
+         let root = thread.address_space.or(vma::kernel_root());
+         let (active, flags) = Cr3::read();
+         if let Some(root) = root.filter(|&root| root != active) {
+            unsafe { Cr3::write(root, flags) };
+         }
+
+         thread.rsp
+      };
+
+      scheduler.current = next;
+      scheduler.switched_in = now;
+      (old, new)
+   };
+
+   unsafe { context::switch_context(old, new) };
+}
+
+/// Frees the threads that have finished, along with the address spaces they ran programs in.
+///
+/// Their stacks and address spaces are released outside the scheduler lock, with interrupts
+/// enabled, since that takes the page table locks.
+fn reap() {
+   let finished: Vec<Box<Thread>> = without_interrupts(|| {
+      let mut scheduler = SCHEDULER.lock();
+      let ids: Vec<ThreadId> = scheduler.threads.values()
+         .filter(|thread| thread.state == ThreadState::Finished)
+         .map(|thread| thread.id)
+         .collect();
+
+      return ids.into_iter().filter_map(|id| scheduler.threads.remove(&id)).collect();
+   });
+
+   for thread in finished {
+      if let Some(space) = thread.address_space.and_then(vma::unregister) {
+         space.destroy();
+      }
+   }
+}
+
+/// Runs when no other thread is ready, halting until the next interrupt.
+fn idle() {
+   loop {
+      interrupts::enable_and_hlt();
+   }
+}
+
+/// Lets the async executor, which runs on the boot thread, sleep until the next tick when no
+/// task is ready.
+///
+/// Interrupt-driven wake-ups are therefore noticed on the next tick rather than straight away.
+fn idle_executor(ready: &dyn Fn() -> bool) {
+   without_interrupts(|| {
+      if !ready() {
+         sleep_until(time::uptime());
+      }
+   });
+}
+
+fn uptime_nanos() -> u64 {
+   return u64::try_from(time::uptime().as_nanos()).unwrap_or(u64::MAX);
+}
+
+/// Errors returned when changing a thread's scheduling.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ThreadError {
+   /// No thread has the given ID.
+   NotFound,
+
+   /// The priority is kept for the idle thread.
+   ReservedPriority,
+}
+
+impl Display for ThreadError {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return match self {
+         ThreadError::NotFound => write!(f, "no such thread"),
+         ThreadError::ReservedPriority => write!(f, "priority is reserved for the idle thread"),
+      };
+   }
+}
+
+// IMPORTS //
+
+use {
+   super::{
+      stack::StackError,
+      task::{Priority, Thread, ThreadId, ThreadInfo, ThreadState},
+   },
+   crate::{
+      address::NUM_PRIORITIES,
+      arch::x86_64::context,
+      gdt,
+      memory::vma,
+   },
+   alloc::{
+      boxed::Box,
+      collections::{BTreeMap, VecDeque},
+      string::String,
+      vec::Vec,
+   },
+   base::{log, tasks::executor, time},
+   core::{
+      fmt::{self, Display},
+      sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+      time::Duration,
+   },
+   spin::Mutex,
+   x86_64::{
+      instructions::interrupts::{self, without_interrupts},
+      registers::{control::Cr3, model_specific::FsBase},
+      structures::paging::PhysFrame,
+      VirtAddr,
+   },
+};
@@ -0,0 +1,120 @@
+/// Start of the window kernel thread stacks are allocated from.
+pub const STACKS_START: u64 = 0xffff_e000_0000_0000;
+
+/// Size of the kernel stack window.
+pub const STACKS_WINDOW: u64 = 4 * 1024 * 1024 * 1024;
+
+/// A kernel stack in the kernel address space, with an unmapped guard page below it.
+///
+/// The stack is mapped up front rather than on demand, since a thread can be running with the
+/// page table locks held when it first touches a page. Running into the guard page cannot be
+/// handled on the overflowed stack, so it escalates to a double fault, which runs on its own
+/// stack and reports the overflow. The stack is unmapped and its frames freed when dropped.
+pub struct KernelStack {
+   bottom: VirtAddr,
+   size: u64,
+}
+
+impl KernelStack {
+   /// Allocates and maps a stack of at least `size` bytes.
+   pub fn allocate(size: usize) -> Result<Self, StackError> {
+      let size = (size as u64).max(FRAME_SIZE);
+      let size = (size + FRAME_SIZE - 1) / FRAME_SIZE * FRAME_SIZE;
+      let flags = VmaFlags::READ | VmaFlags::WRITE;
+
+      // Every stack starts a page above its gap, so the page below it is never reserved.
+      let bottom = vma::with_kernel(|space| {
+         let window = VirtAddr::new(STACKS_START);
+         let gap = space.find_free(size + FRAME_SIZE, window, window + STACKS_WINDOW)
+            .ok_or(StackError::WindowFull)?;
+
+         let bottom = gap + FRAME_SIZE;
+         space.reserve(bottom, size, flags, VmaKind::Stack).map_err(|_| StackError::WindowFull)?;
+         return Ok(bottom);
+      }).ok_or(StackError::NoAddressSpace)??;
+
+      let stack = KernelStack{ bottom, size };
+
+      // The locks are released before `stack` can be dropped, which takes them again.
+      let mapped = without_interrupts(|| {
+         let mut mapper = MAPPER.lock();
+         let mut frame_allocator = FRAME_ALLOCATOR.lock();
+
+         return map_range(
+            mapper.as_mut().expect("page table not handed over to the kernel"),
+            bottom,
+            size,
+            flags.page_flags(),
+            frame_allocator.as_mut().expect("frame allocator not handed over to the kernel"),
+         );
+      });
+
+      // On failure, dropping the stack frees whatever was mapped.
+      return match mapped {
+         Ok(()) => Ok(stack),
+         Err(_) => Err(StackError::OutOfMemory),
+      };
+   }
+
+   /// Lowest usable address.
+   pub fn bottom(&self) -> VirtAddr {
+      return self.bottom;
+   }
+
+   /// Address just past the highest usable byte, where the stack pointer starts.
+   pub fn top(&self) -> VirtAddr {
+      return self.bottom + self.size;
+   }
+
+   pub fn size(&self) -> u64 {
+      return self.size;
+   }
+}
+
+impl Drop for KernelStack {
+   fn drop(&mut self) {
+      let released = vma::with_kernel(|space| space.release(self.bottom));
+
+      if !matches!(released, Some(Ok(_))) {
+         log::warn!("Kernel stack at {:#x} was not released", self.bottom.as_u64());
+      }
+   }
+}
+
+/// Errors returned when allocating a kernel stack.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StackError {
+   /// The kernel address space has not been registered yet.
+   NoAddressSpace,
+
+   /// No room is left in the stack window.
+   WindowFull,
+
+   /// No frames were left to back the stack.
+   OutOfMemory,
+}
+
+impl Display for StackError {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return match self {
+         StackError::NoAddressSpace => write!(f, "kernel address space not registered"),
+         StackError::WindowFull => write!(f, "kernel stack window is full"),
+         StackError::OutOfMemory => write!(f, "out of physical memory"),
+      };
+   }
+}
+
+// IMPORTS //
+
+use {
+   crate::memory::{
+      map_range,
+      vma::{self, VmaFlags, VmaKind},
+      FRAME_ALLOCATOR,
+      FRAME_SIZE,
+      MAPPER,
+   },
+   base::log,
+   core::fmt::{self, Display},
+   x86_64::{instructions::interrupts::without_interrupts, VirtAddr},
+};
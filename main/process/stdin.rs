@@ -0,0 +1,144 @@
+/// Most bytes typed ahead of any reader; keys pressed beyond this are dropped.
+const BUFFER_SIZE: usize = 4096;
+
+/// ASCII backspace, which erases the last character of the line being typed.
+const BACKSPACE: char = '\u{8}';
+
+/// Keyboard input waiting to be read.
+static STDIN: SpinMutex<Stdin> = SpinMutex::new(Stdin{
+   buffer: VecDeque::new(),
+   lines: 0,
+   readers: VecDeque::new(),
+});
+
+struct Stdin {
+   buffer: VecDeque<u8>,
+
+   /// Number of complete lines in the buffer; bytes after the last newline are still being typed.
+   lines: usize,
+
+   /// Threads blocked until a line is complete.
+   readers: VecDeque<ThreadId>,
+}
+
+/// Collects typed characters into lines for [`read`], like a terminal in canonical mode.
+///
+/// Runs for as long as the kernel does; spawn it once on the executor. Characters are echoed by
+/// the keyboard tasks, not here.
+pub async fn feed_keypresses() {
+   let mut events = keyboard::subscribe();
+
+   while let Some(event) = poll_fn(|cx| events.poll_event(cx)).await {
+      let character = match event.character() {
+         Some(character) => character,
+         None => continue,
+      };
+
+      let readers = without_interrupts(|| {
+         let mut stdin = STDIN.lock();
+         stdin.push(character);
+
+         return match stdin.lines {
+            0 => VecDeque::new(),
+            _ => mem::take(&mut stdin.readers),
+         };
+      });
+
+      for reader in readers {
+         scheduler::unblock(reader);
+      }
+   }
+}
+
+/// Blocks the running thread until a line has been typed, then moves as much of it as fits into
+/// `buffer`, returning the number of bytes read.
+///
+/// What does not fit is left for the next read, as on Linux.
+pub fn read(buffer: &mut [u8]) -> usize {
+   if buffer.is_empty() {
+      return 0;
+   }
+
+   let line: Vec<u8> = loop {
+      let line = without_interrupts(|| {
+         let mut stdin = STDIN.lock();
+         if stdin.lines > 0 {
+            return Some(stdin.take_line(buffer.len()));
+         }
+
+         // Interrupts stay off until we are queued and blocked, so the wakeup cannot be missed.
+         stdin.readers.push_back(scheduler::current());
+         drop(stdin);
+         scheduler::block();
+         return None;
+      });
+
+      if let Some(line) = line {
+         break line;
+      }
+   };
+
+   // Copied outside the lock, since touching user memory may fault.
+   buffer[..line.len()].copy_from_slice(&line);
+   return line.len();
+}
+
+impl Stdin {
+   fn push(&mut self, character: char) {
+      if character == BACKSPACE {
+         if self.buffer.back().map_or(false, |&byte| byte != b'\n') {
+            // Drop the whole UTF-8 sequence of the last character.
+            while let Some(byte) = self.buffer.pop_back() {
+               if byte & 0xc0 != 0x80 {
+                  break;
+               }
+            }
+         }
+
+         return;
+      }
+
+      let mut encoded = [0; 4];
+      let encoded = character.encode_utf8(&mut encoded).as_bytes();
+      if self.buffer.len() + encoded.len() > BUFFER_SIZE {
+         log::warn!("stdin buffer full; dropping keyboard input");
+         return;
+      }
+
+      self.buffer.extend(encoded.iter().copied());
+      if character == '\n' {
+         self.lines += 1;
+      }
+   }
+
+   /// Takes up to `limit` bytes of the first complete line, newline included.
+   fn take_line(&mut self, limit: usize) -> Vec<u8> {
+      let mut line = Vec::new();
+
+      while line.len() < limit {
+         match self.buffer.pop_front() {
+            Some(byte) => {
+               line.push(byte);
+               if byte == b'\n' {
+                  self.lines -= 1;
+                  break;
+               }
+            }
+            None => break,
+         }
+      }
+
+      return line;
+   }
+}
+
+// IMPORTS //
+
+use {
+   super::{scheduler, task::ThreadId},
+   alloc::{collections::VecDeque, vec::Vec},
+   base::{log, tasks::keyboard},
+   core::{future::poll_fn, mem},
+   spin::mutex::SpinMutex,
+   x86_64::instructions::interrupts::without_interrupts,
+};
@@ -0,0 +1,497 @@
+/// Every system call the kernel implements, indexed by its Linux number.
+pub static SYSCALL_TABLE: SyscallTable = SyscallTable::new()
+   .with(SYSNO_READ, sys_read)
+   .with(SYSNO_WRITE, sys_write)
+   .with(SYSNO_CLOSE, sys_close)
+   .with(SYSNO_FSTAT, sys_fstat)
+   .with(SYSNO_MMAP, sys_mmap)
+   .with(SYSNO_MUNMAP, sys_munmap)
+   .with(SYSNO_BRK, sys_brk)
+   .with(SYSNO_IOCTL, sys_ioctl)
+   .with(SYSNO_WRITEV, sys_writev)
+   .with(SYSNO_EXIT, sys_exit)
+   .with(SYSNO_ARCH_PRCTL, sys_arch_prctl)
+   .with(SYSNO_SET_TID_ADDRESS, sys_set_tid_address)
+   .with(SYSNO_CLOCK_GETTIME, sys_clock_gettime)
+   .with(SYSNO_EXIT_GROUP, sys_exit)
+   .with(SYSNO_OPENAT, sys_openat);
+
+/// Most entries `writev` accepts, as on Linux.
+const IOV_MAX: u64 = 1024;
+
+/// Size the terminal reports when output only goes to the serial port.
+const DEFAULT_WINDOW: (u16, u16) = (24, 80);
+
+// `ioctl` requests.
+const TIOCGWINSZ: u64 = 0x5413;
+
+// `arch_prctl` codes.
+const ARCH_SET_FS: u64 = 0x1002;
+const ARCH_GET_FS: u64 = 0x1003;
+
+// `mmap` protection and flags.
+const PROT_READ: u64 = 0x1;
+const PROT_WRITE: u64 = 0x2;
+const PROT_EXEC: u64 = 0x4;
+const MAP_SHARED: u64 = 0x01;
+const MAP_PRIVATE: u64 = 0x02;
+const MAP_FIXED: u64 = 0x10;
+const MAP_ANONYMOUS: u64 = 0x20;
+
+// Clocks `clock_gettime` can read; every one counts from boot, as there is no real-time clock.
+const CLOCK_REALTIME: u64 = 0;
+const CLOCK_MONOTONIC: u64 = 1;
+const CLOCK_MONOTONIC_RAW: u64 = 4;
+const CLOCK_REALTIME_COARSE: u64 = 5;
+const CLOCK_MONOTONIC_COARSE: u64 = 6;
+const CLOCK_BOOTTIME: u64 = 7;
+
+/// `st_mode` of the terminal: a character device, readable and writable by its owner.
+const TERMINAL_MODE: u32 = 0o020000 | 0o620;
+
+/// `st_rdev` of the terminal: `/dev/tty1`, major 4 and minor 1.
+const TERMINAL_DEVICE: u64 = (4 << 8) | 1;
+
+/// `read(fd, buffer, count)`: standard input blocks until a line has been typed.
+extern "C" fn sys_read(fd: u64, buffer: u64, count: u64, _: u64, _: u64, _: u64) -> i64 {
+   if fd != 0 {
+      return -EBADF;
+   }
+
+   let buffer = match user_slice_mut(buffer, count) {
+      Ok(buffer) => buffer,
+      Err(errno) => return -errno,
+   };
+
+   return stdin::read(buffer) as i64;
+}
+
+/// `write(fd, buffer, count)`: standard output and error go to the terminal.
+extern "C" fn sys_write(fd: u64, buffer: u64, count: u64, _: u64, _: u64, _: u64) -> i64 {
+   if fd != 1 && fd != 2 {
+      return -EBADF;
+   }
+
+   let bytes = match user_slice(buffer, count) {
+      Ok(bytes) => bytes,
+      Err(errno) => return -errno,
+   };
+
+   write_terminal(bytes);
+   return count as i64;
+}
+
+/// `writev(fd, iov, count)`: [`sys_write`] for each buffer in turn.
+extern "C" fn sys_writev(fd: u64, iov: u64, count: u64, _: u64, _: u64, _: u64) -> i64 {
+   if fd != 1 && fd != 2 {
+      return -EBADF;
+   }
+
+   if count > IOV_MAX {
+      return -EINVAL;
+   }
+
+   let vectors = match user_slice(iov, count * mem::size_of::<IoVec>() as u64) {
+      Ok(bytes) => bytes,
+      Err(errno) => return -errno,
+   };
+
+   let mut written = 0;
+   for vector in vectors.chunks_exact(mem::size_of::<IoVec>()) {
+      let vector = unsafe { ptr::read_unaligned(vector.as_ptr() as *const IoVec) };
+
+      let bytes = match user_slice(vector.base, vector.length) {
+         Ok(bytes) => bytes,
+         Err(errno) => return -errno,
+      };
+
+      write_terminal(bytes);
+      written += vector.length as i64;
+   }
+
+   return written;
+}
+
+/// Writes user output to the terminal byte for byte, so text that is not UTF-8 (or splits a
+/// character across writes) reaches the serial port unchanged.
+fn write_terminal(bytes: &[u8]) {
+   if let Some(writer) = GLOBAL_WRITER.get() {
+      writer.write_bytes(bytes);
+   }
+}
+
+/// `close(fd)`: only the standard streams are ever open, and they stay open.
+extern "C" fn sys_close(fd: u64, _: u64, _: u64, _: u64, _: u64, _: u64) -> i64 {
+   return match fd {
+      0..=2 => 0,
+      _ => -EBADF,
+   };
+}
+
+/// `openat(dirfd, path, flags, mode)`: there is no filesystem, so nothing can be found.
+extern "C" fn sys_openat(_: u64, _: u64, _: u64, _: u64, _: u64, _: u64) -> i64 {
+   return -ENOENT;
+}
+
+/// `fstat(fd, stat)`: the standard streams are all the terminal, a character device.
+extern "C" fn sys_fstat(fd: u64, address: u64, _: u64, _: u64, _: u64, _: u64) -> i64 {
+   if fd > 2 {
+      return -EBADF;
+   }
+
+   let stat = Stat{
+      mode: TERMINAL_MODE,
+      links: 1,
+      special_device: TERMINAL_DEVICE,
+      block_size: 1024,
+      ..Stat::default()
+   };
+
+   return match write_user(address, stat) {
+      Ok(()) => 0,
+      Err(errno) => -errno,
+   };
+}
+
+/// `ioctl(fd, request, argument)`: the standard streams answer `TIOCGWINSZ`, which is also how
+/// musl's `isatty` asks whether they are a terminal.
+extern "C" fn sys_ioctl(fd: u64, request: u64, argument: u64, _: u64, _: u64, _: u64) -> i64 {
+   if fd > 2 {
+      return -EBADF;
+   }
+
+   if request != TIOCGWINSZ {
+      return -ENOTTY;
+   }
+
+   let (rows, columns) = window_size();
+   let size = WindowSize{ rows, columns, width: 0, height: 0 };
+
+   return match write_user(argument, size) {
+      Ok(()) => 0,
+      Err(errno) => -errno,
+   };
+}
+
+/// `mmap(address, length, protection, flags, fd, offset)`: anonymous mappings only, backed with
+/// zeroed pages on first touch.
+///
+/// Without `MAP_FIXED` the address is a hint, used if the range there is free.
+extern "C" fn sys_mmap(address: u64, length: u64, protection: u64, flags: u64, _: u64, _: u64) -> i64 {
+   let sharing = flags & (MAP_SHARED | MAP_PRIVATE);
+   if length == 0 || sharing == 0 || sharing == MAP_SHARED | MAP_PRIVATE {
+      return -EINVAL;
+   }
+
+   // There are no files to map.
+   if flags & MAP_ANONYMOUS == 0 {
+      return -EBADF;
+   }
+
+   let length = match length.checked_add(FRAME_SIZE - 1) {
+      Some(length) if length <= USER_SPACE_END as u64 => length & !(FRAME_SIZE - 1),
+      _ => return -ENOMEM,
+   };
+
+   let fixed = flags & MAP_FIXED != 0;
+   if fixed && (address % FRAME_SIZE != 0 || !in_user_space(address, length)) {
+      return -EINVAL;
+   }
+
+   let flags = vma_flags(protection);
+   let mapped = vma::with_current(|space| {
+      if fixed {
+         let start = VirtAddr::new(address);
+         space.unmap(start, length).ok()?;
+         return space.reserve(start, length, flags, VmaKind::Anonymous).ok().map(|_| start);
+      }
+
+      let hint = address & !(FRAME_SIZE - 1);
+      if hint != 0 && in_user_space(hint, length) {
+         let hint = VirtAddr::new(hint);
+         if space.reserve(hint, length, flags, VmaKind::Anonymous).is_ok() {
+            return Some(hint);
+         }
+      }
+
+      let bottom = VirtAddr::new(USER_MMAP_START as u64);
+      let start = space.find_free(length, bottom, VirtAddr::new(USER_SPACE_END as u64))?;
+      space.reserve(start, length, flags, VmaKind::Anonymous).ok()?;
+      return Some(start);
+   });
+
+   return match mapped {
+      Some(Some(start)) => start.as_u64() as i64,
+      _ => -ENOMEM,
+   };
+}
+
+/// `munmap(address, length)`: unmaps whole pages, splitting any mapping the range cuts through.
+extern "C" fn sys_munmap(address: u64, length: u64, _: u64, _: u64, _: u64, _: u64) -> i64 {
+   if address % FRAME_SIZE != 0 || length == 0 || !in_user_space(address, length) {
+      return -EINVAL;
+   }
+
+   let unmapped = vma::with_current(|space| space.unmap(VirtAddr::new(address), length));
+   return match unmapped {
+      Some(Ok(())) => 0,
+      _ => -EINVAL,
+   };
+}
+
+/// `brk(address)`: moves the program break, returning where it ends up.
+///
+/// The break stays put if the move fails, so asking for address zero just reads it.
+extern "C" fn sys_brk(address: u64, _: u64, _: u64, _: u64, _: u64, _: u64) -> i64 {
+   let brk = vma::with_current(|space| {
+      let current = space.program_break()?;
+
+      return match VirtAddr::try_new(address) {
+         Ok(address) if address.as_u64() < USER_SPACE_END as u64 => Some(space.move_break(address)),
+         _ => Some(current),
+      };
+   });
+
+   return match brk {
+      Some(Some(brk)) => brk.as_u64() as i64,
+      _ => 0,
+   };
+}
+
+/// `arch_prctl(code, address)`: sets or reads the FS base, which points at thread-local storage.
+extern "C" fn sys_arch_prctl(code: u64, address: u64, _: u64, _: u64, _: u64, _: u64) -> i64 {
+   return match code {
+      ARCH_SET_FS => {
+         if address >= USER_SPACE_END as u64 {
+            return -EPERM;
+         }
+
+         FsBase::write(VirtAddr::new(address));
+         0
+      }
+      ARCH_GET_FS => match write_user(address, FsBase::read().as_u64()) {
+         Ok(()) => 0,
+         Err(errno) => -errno,
+      },
+      _ => -EINVAL,
+   };
+}
+
+/// `set_tid_address(pointer)`: returns the thread ID.
+///
+/// There are no futexes yet, so the pointer is not cleared when the thread exits.
+extern "C" fn sys_set_tid_address(_: u64, _: u64, _: u64, _: u64, _: u64, _: u64) -> i64 {
+   return scheduler::current().as_u64() as i64;
+}
+
+/// `clock_gettime(clock, time)`: every supported clock reads the time since boot.
+extern "C" fn sys_clock_gettime(clock: u64, time: u64, _: u64, _: u64, _: u64, _: u64) -> i64 {
+   match clock {
+      CLOCK_REALTIME | CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_REALTIME_COARSE
+         | CLOCK_MONOTONIC_COARSE | CLOCK_BOOTTIME => {}
+      _ => return -EINVAL,
+   }
+
+   let uptime = time::uptime();
+   let spec = TimeSpec{ seconds: uptime.as_secs() as i64, nanoseconds: uptime.subsec_nanos() as i64 };
+
+   return match write_user(time, spec) {
+      Ok(()) => 0,
+      Err(errno) => -errno,
+   };
+}
+
+/// `exit(status)` and, with one thread per program, `exit_group(status)`.
+extern "C" fn sys_exit(status: u64, _: u64, _: u64, _: u64, _: u64, _: u64) -> i64 {
+   log::debug!("Thread {} exited with status {}", scheduler::current(), status as i32);
+   scheduler::exit();
+}
+
+/// Borrows `length` bytes of user memory at `address`.
+///
+/// The range must lie in user space and inside readable, user-accessible areas of the current
+/// address space, so touching it can only fault in pages the fault handler will back.
+pub fn user_slice<'a>(address: u64, length: u64) -> Result<&'a [u8], i64> {
+   check_user_range(address, length, VmaFlags::USER | VmaFlags::READ)?;
+   return Ok(unsafe { slice::from_raw_parts(address as *const u8, length as usize) });
+}
+
+/// Mutably borrows `length` bytes of user memory at `address`; see [`user_slice`].
+///
+/// Every area the range covers must also be writable.
+pub fn user_slice_mut<'a>(address: u64, length: u64) -> Result<&'a mut [u8], i64> {
+   check_user_range(address, length, VmaFlags::USER | VmaFlags::WRITE)?;
+   return Ok(unsafe { slice::from_raw_parts_mut(address as *mut u8, length as usize) });
+}
+
+/// Copies `value` out to user memory at `address`, which need not be aligned.
+fn write_user<T: Copy>(address: u64, value: T) -> Result<(), i64> {
+   let bytes = user_slice_mut(address, mem::size_of::<T>() as u64)?;
+   unsafe { ptr::write_unaligned(bytes.as_mut_ptr() as *mut T, value) };
+   return Ok(());
+}
+
+/// Checks that `length` bytes at `address` lie in user space, inside areas that all have
+/// `required`.
+fn check_user_range(address: u64, length: u64, required: VmaFlags) -> Result<(), i64> {
+   if length == 0 {
+      return Ok(());
+   }
+
+   if !in_user_space(address, length) {
+      return Err(EFAULT);
+   }
+
+   // Walk the areas covering the range; they are contiguous if each starts where the last ended.
+   let end = address + length;
+   let covered = vma::with_current(|space| {
+      let mut cursor = VirtAddr::new(address);
+      while cursor.as_u64() < end {
+         match space.find(cursor) {
+            Some(area) if area.flags.contains(required) => cursor = area.end,
+            _ => return false,
+         }
+      }
+
+      return true;
+   });
+
+   return match covered {
+      Some(true) => Ok(()),
+      _ => Err(EFAULT),
+   };
+}
+
+/// Whether `length` bytes at `address` lie inside user space.
+fn in_user_space(address: u64, length: u64) -> bool {
+   return match address.checked_add(length) {
+      Some(end) => address >= USER_SPACE_START as u64 && end <= USER_SPACE_END as u64,
+      None => false,
+   };
+}
+
+/// The area flags `mmap` protection bits ask for.
+fn vma_flags(protection: u64) -> VmaFlags {
+   let mut flags = VmaFlags::USER;
+
+   // Writable or executable pages are readable on x86 whatever the caller asks for, and
+   // `PROT_NONE` leaves the area without READ so it is never mapped.
+   if protection & (PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+      flags = flags | VmaFlags::READ;
+   }
+
+   if protection & PROT_WRITE != 0 {
+      flags = flags | VmaFlags::WRITE;
+   }
+
+   if protection & PROT_EXEC != 0 {
+      flags = flags | VmaFlags::EXECUTE;
+   }
+
+   return flags;
+}
+
+/// Rows and columns of text the terminal shows.
+fn window_size() -> (u16, u16) {
+   let writer = GLOBAL_WRITER.get().and_then(|writer| writer.writer.as_ref());
+
+   return match writer {
+      Some(writer) => {
+         let writer = writer.lock();
+         (writer.rows() as u16, writer.columns() as u16)
+      }
+      None => DEFAULT_WINDOW,
+   };
+}
+
+/// Linux's `struct iovec`.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct IoVec {
+   base: u64,
+   length: u64,
+}
+
+/// Linux's `struct winsize`.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct WindowSize {
+   rows: u16,
+   columns: u16,
+   width: u16,
+   height: u16,
+}
+
+/// Linux's `struct timespec`.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct TimeSpec {
+   seconds: i64,
+   nanoseconds: i64,
+}
+
+/// Linux's x86_64 `struct stat`.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct Stat {
+   device: u64,
+   inode: u64,
+   links: u64,
+   mode: u32,
+   user: u32,
+   group: u32,
+   _padding: u32,
+   special_device: u64,
+   size: i64,
+   block_size: i64,
+   blocks: i64,
+   access_time: [i64; 2],
+   modify_time: [i64; 2],
+   change_time: [i64; 2],
+   _reserved: [i64; 3],
+}
+
+// IMPORTS //
+
+use {
+   super::{scheduler, stdin},
+   crate::{
+      address::{USER_MMAP_START, USER_SPACE_END, USER_SPACE_START},
+      memory::{
+         vma::{self, VmaFlags, VmaKind},
+         FRAME_SIZE,
+      },
+   },
+   base::{
+      log,
+      syscall::{
+         SyscallTable,
+         EBADF,
+         EFAULT,
+         EINVAL,
+         ENOENT,
+         ENOMEM,
+         ENOTTY,
+         EPERM,
+         SYSNO_ARCH_PRCTL,
+         SYSNO_BRK,
+         SYSNO_CLOCK_GETTIME,
+         SYSNO_CLOSE,
+         SYSNO_EXIT,
+         SYSNO_EXIT_GROUP,
+         SYSNO_FSTAT,
+         SYSNO_IOCTL,
+         SYSNO_MMAP,
+         SYSNO_MUNMAP,
+         SYSNO_OPENAT,
+         SYSNO_READ,
+         SYSNO_SET_TID_ADDRESS,
+         SYSNO_WRITE,
+         SYSNO_WRITEV,
+      },
+      terminal::GLOBAL_WRITER,
+      time,
+   },
+   core::{mem, ptr, slice},
+   x86_64::{registers::model_specific::FsBase, VirtAddr},
+};
@@ -1,5 +1,227 @@
+/// Identifies a kernel thread.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ThreadId(u64);
 
+impl ThreadId {
+   /// The thread the kernel booted on, which runs the async executor.
+   pub const BOOT: ThreadId = ThreadId(0);
 
-// IMPORTS //
+   pub(super) fn new() -> Self {
+      static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+      return ThreadId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+   }
+
+   pub fn as_u64(self) -> u64 {
+      return self.0;
+   }
+}
+
+impl Display for ThreadId {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return write!(f, "{}", self.0);
+   }
+}
+
+/// Scheduling priority; a ready thread always runs before every thread of lower priority.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Priority(u8);
+
+impl Priority {
+   /// Reserved for the idle thread, which only runs when nothing else can.
+   pub const IDLE: Priority = Priority(0);
+
+   pub const LOWEST: Priority = Priority(1);
+   pub const NORMAL: Priority = Priority(NUM_PRIORITIES as u8 / 2);
+   pub const HIGHEST: Priority = Priority(NUM_PRIORITIES as u8 - 1);
+
+   /// The priority at `level`, or `None` past [`NUM_PRIORITIES`].
+   pub const fn new(level: u8) -> Option<Self> {
+      return match (level as usize) < NUM_PRIORITIES {
+         true => Some(Priority(level)),
+         false => None,
+      };
+   }
+
+   pub const fn level(self) -> usize {
+      return self.0 as usize;
+   }
+}
+
+impl Display for Priority {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      return write!(f, "{}", self.0);
+   }
+}
+
+/// Where a thread is in its life.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ThreadState {
+   /// Waiting in its priority's run queue.
+   Ready,
+
+   /// On the CPU.
+   Running,
+
+   /// Waiting for a deadline to pass.
+   Sleeping,
+
+   /// Waiting for another thread to wake it, e.g. to hand over a [`Mutex`](super::Mutex).
+   Blocked,
+
+   /// Returned from its entry point; its stack is freed once another thread reaps it.
+   Finished,
+}
+
+/// Task control block: everything needed to suspend, resume and schedule a kernel thread.
+pub struct Thread {
+   pub id: ThreadId,
+   pub name: Option<String>,
+   pub state: ThreadState,
+
+   /// Priority the thread was given.
+   pub base_priority: Priority,
+
+   /// Priority the thread runs at: its base priority, raised while it holds a mutex that a
+   /// higher-priority thread is waiting for.
+   pub priority: Priority,
+
+   /// Number of waiters at each priority blocked on mutexes this thread holds.
+   pub(super) boosts: [u16; NUM_PRIORITIES],
+
+   /// Nanoseconds spent on the CPU, up to the last time it was switched out.
+   pub(super) cpu_nanos: u64,
+
+   /// Number of times the thread has been switched to.
+   pub(super) switches: u64,
 
+   /// Stack pointer saved by the context switch while the thread is suspended.
+   pub(super) rsp: u64,
+
+   /// FS segment base, which user code points at its thread-local storage; swapped in and out
+   /// by the context switch.
+   pub(super) fs_base: u64,
+
+   /// x87 and SSE registers, saved and restored by the context switch.
+   pub(super) fpu: FpuState,
+
+   /// Root of the user address space the thread runs a program in, which it owns; kernel threads
+   /// run in the kernel's.
+   pub(super) address_space: Option<PhysFrame>,
+
+   /// The thread's own stack; the boot thread keeps the bootloader's.
+   stack: Option<KernelStack>,
+}
+
+impl Thread {
+   /// The thread the kernel is already running on.
+   pub(super) fn boot() -> Self {
+      let mut thread = Thread::with_stack(ThreadId::BOOT, Some(String::from("kernel")), Priority::NORMAL, None, 0);
+      thread.state = ThreadState::Running;
+      return thread;
+   }
+
+   /// A thread that will call `entry` the first time it is switched to.
+   pub(super) fn new<F>(name: Option<String>, priority: Priority, entry: F) -> Result<Self, StackError>
+   where
+      F: FnOnce() + Send + 'static,
+   {
+      let stack = KernelStack::allocate(STACK_SIZE)?;
+
+      // Boxed twice so the closure crosses into the new thread as a thin pointer.
+      let entry: Box<dyn FnOnce() + Send> = Box::new(entry);
+      let argument = Box::into_raw(Box::new(entry)) as usize;
+      let rsp = unsafe { context::prepare_stack(stack.top(), thread_main, argument) };
+
+      return Ok(Thread::with_stack(ThreadId::new(), name, priority, Some(stack), rsp));
+   }
+
+   fn with_stack(id: ThreadId, name: Option<String>, priority: Priority, stack: Option<KernelStack>, rsp: u64) -> Self {
+      return Thread{
+         id,
+         name,
+         state: ThreadState::Ready,
+         base_priority: priority,
+         priority,
+         boosts: [0; NUM_PRIORITIES],
+         cpu_nanos: 0,
+         switches: 0,
+         rsp,
+         fs_base: 0,
+         fpu: FpuState::new(),
+         address_space: None,
+         stack,
+      };
+   }
+
+   /// The thread's stack, unless it is the boot thread.
+   pub fn stack(&self) -> Option<&KernelStack> {
+      return self.stack.as_ref();
+   }
+
+   /// The priority the thread should run at, given its base priority and boosts.
+   pub(super) fn effective_priority(&self) -> Priority {
+      let boosted = self.boosts.iter().rposition(|&count| count > 0).unwrap_or(0);
+      return self.base_priority.max(Priority(boosted as u8));
+   }
+
+   /// A snapshot for [`thread_list`](super::scheduler::thread_list); `running_nanos` is how long
+   /// the thread has been on the CPU since it was last switched in.
+   pub(super) fn info(&self, running_nanos: u64) -> ThreadInfo {
+      return ThreadInfo{
+         id: self.id,
+         name: self.name.clone(),
+         state: self.state,
+         base_priority: self.base_priority,
+         priority: self.priority,
+         cpu_time: Duration::from_nanos(self.cpu_nanos + running_nanos),
+         switches: self.switches,
+      };
+   }
+}
+
+/// A snapshot of a thread's scheduling state and CPU usage.
+#[derive(Clone, Debug)]
+pub struct ThreadInfo {
+   pub id: ThreadId,
+   pub name: Option<String>,
+   pub state: ThreadState,
+   pub base_priority: Priority,
+   pub priority: Priority,
+
+   /// Total time spent on the CPU.
+   pub cpu_time: Duration,
+
+   /// Number of times the thread has been switched to.
+   pub switches: u64,
+}
+
+/// Where every spawned thread starts, with interrupts still disabled by the switch that got
+/// it here.
+extern "C" fn thread_main(argument: usize) -> ! {
+   let entry = unsafe { Box::from_raw(argument as *mut Box<dyn FnOnce() + Send>) };
+
+   interrupts::enable();
+   entry();
+
+   scheduler::exit();
+}
+
+// IMPORTS //
 
+use {
+   super::{
+      scheduler,
+      stack::{KernelStack, StackError},
+   },
+   crate::{
+      address::{NUM_PRIORITIES, STACK_SIZE},
+      arch::x86_64::context::{self, FpuState},
+   },
+   alloc::{boxed::Box, string::String},
+   core::{
+      fmt::{self, Display},
+      sync::atomic::{AtomicU64, Ordering},
+      time::Duration,
+   },
+   x86_64::{instructions::interrupts, structures::paging::PhysFrame},
+};
@@ -4,3 +4,137 @@ pub fn trivial_assertion() {
    assert_eq!(1, 1);
    println!("[ok]");
 }
+
+#[test_case]
+fn user_address_spaces_share_the_kernel() {
+   print!("A user address space can be created after boot: ");
+   let space = AddressSpace::new_user().expect("kernel mappings overlap user space");
+   assert_ne!(Some(space.root), vma::kernel_root());
+
+   space.destroy();
+   println!("[ok]");
+}
+
+#[test_case]
+fn equal_priority_threads_take_turns() {
+   print!("Threads of equal priority run in the order they became ready: ");
+   let order = Arc::new(SpinMutex::new(Vec::new()));
+
+   for index in 0..3 {
+      let order = order.clone();
+      process::spawn_thread(move || order.lock().push(index)).expect("failed to spawn a thread");
+   }
+
+   while order.lock().len() < 3 {
+      process::yield_now();
+   }
+
+   assert_eq!(*order.lock(), [0, 1, 2]);
+   println!("[ok]");
+}
+
+#[test_case]
+fn higher_priority_threads_run_first() {
+   print!("A thread that outranks its spawner runs straight away: ");
+   static RAN: AtomicBool = AtomicBool::new(false);
+
+   process::spawn_thread_at("outranks", Priority::HIGHEST, || RAN.store(true, Ordering::Relaxed))
+      .expect("failed to spawn a thread");
+
+   assert!(RAN.load(Ordering::Relaxed));
+   println!("[ok]");
+}
+
+#[test_case]
+fn mutex_owners_inherit_waiter_priority() {
+   print!("A mutex owner runs at the priority of its highest waiter: ");
+   static LOCK: process::Mutex<u32> = process::Mutex::new(0);
+
+   let me = scheduler::current();
+   let priority = || process::thread_list().into_iter().find(|thread| thread.id == me).map(|thread| thread.priority);
+   let base = priority();
+
+   let guard = LOCK.lock();
+   process::spawn_thread_at("waiter", Priority::HIGHEST, || *LOCK.lock() += 1)
+      .expect("failed to spawn a thread");
+
+   // The waiter ran straight away and blocked on the lock, lending us its priority.
+   assert_eq!(priority(), Some(Priority::HIGHEST));
+   drop(guard);
+
+   // The lock went to the waiter, which outranks us and so has already finished with it.
+   assert_eq!(*LOCK.lock(), 1);
+   assert_eq!(priority(), base);
+   println!("[ok]");
+}
+
+#[test_case]
+fn frame_allocator_only_tracks_its_regions() {
+   print!("The frame allocator only hands out and takes back frames it tracks: ");
+   const FRAMES: u64 = 16;
+
+   let start = without_interrupts(|| {
+      FRAME_ALLOCATOR.lock().as_mut().and_then(|frames| frames.allocate_contiguous(FRAMES as usize, 1))
+   }).expect("no frames left for a test allocator");
+
+   let base = start.start_address().as_u64();
+   let end = base + FRAMES * FRAME_SIZE;
+   let map = Box::leak(Box::new([MemoryRegion{ start: base, end, kind: MemoryRegionKind::Usable }]));
+   let mut allocator = unsafe { SystemFrameAllocator::new(map, memory::physical_offset()) };
+
+   // The first frame holds the allocator's own bitmap.
+   assert_eq!(allocator.total_frames(), FRAMES as usize);
+   assert_eq!(allocator.free_frames(), FRAMES as usize - 1);
+
+   let mut taken = Vec::new();
+   while let Some(frame) = FrameAllocator::<Size4KiB>::allocate_frame(&mut allocator) {
+      let address = frame.start_address().as_u64();
+      assert!(address > base && address < end);
+      assert!(!taken.contains(&frame));
+      taken.push(frame);
+   }
+
+   assert_eq!(taken.len(), FRAMES as usize - 1);
+
+   // Frames outside the map are ignored rather than indexing past the bitmap.
+   let outside = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(end));
+   unsafe {
+      allocator.deallocate_frame(outside);
+      allocator.deallocate_contiguous(outside, 4);
+   }
+   assert_eq!(allocator.free_frames(), 0);
+
+   for frame in taken {
+      unsafe { allocator.deallocate_frame(frame) };
+   }
+   assert_eq!(allocator.free_frames(), FRAMES as usize - 1);
+
+   without_interrupts(|| unsafe {
+      FRAME_ALLOCATOR.lock().as_mut().unwrap().deallocate_contiguous(start, FRAMES as usize);
+   });
+   println!("[ok]");
+}
+
+// IMPORTS //
+
+use {
+   crate::{
+      memory::{
+         self,
+         vma::{self, AddressSpace},
+         SystemFrameAllocator,
+         FRAME_ALLOCATOR,
+         FRAME_SIZE,
+      },
+      process::{self, scheduler, Priority},
+   },
+   alloc::{boxed::Box, sync::Arc, vec::Vec},
+   core::sync::atomic::{AtomicBool, Ordering},
+   spin::Mutex as SpinMutex,
+   springboard_api::info::{MemoryRegion, MemoryRegionKind},
+   x86_64::{
+      instructions::interrupts::without_interrupts,
+      structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB},
+      PhysAddr,
+   },
+};